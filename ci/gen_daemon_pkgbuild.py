@@ -19,8 +19,9 @@ arch=('x86_64')
 url="{url}"
 license=('MIT')
 
-depends=('acpid' 'zsh' 'pciutils' 'usbutils' 'yad')
+depends=('hwdata' 'yad')
 optdepends=(
+'acpid: needed for AC/battery switching without power supply uevents, see --acpi-events'
 'brightnessctl: needed for brightness settings' 
 'net-tools: needed to disable ethernet cards' 'net-tools: needed to disable ethernet cards'
 'xorg-xrandr: needed for screen settings' 
@@ -62,13 +63,11 @@ post_install() {{
   systemctl daemon-reload
   systemctl enable power-options.service
   systemctl start power-options.service
-  systemctl restart acpid.service
 }}
 
 post_upgrade() {{
   systemctl daemon-reload
   systemctl restart power-options.service
-  systemctl restart acpid.service
 }}
 
 post_remove() {{
@@ -81,13 +80,11 @@ def create_install_file():
     return f"""post_install() {{
   power-daemon-mgr setup
   systemctl daemon-reload
-  systemctl restart acpid.service
   systemctl enable --now power-options.service
 }}
 
 post_upgrade() {{
   systemctl daemon-reload
-  systemctl restart acpid.service
   systemctl restart power-options.service
 }}
 
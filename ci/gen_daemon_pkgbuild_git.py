@@ -24,8 +24,9 @@ arch=('x86_64')
 url="{url}"
 license=('MIT')
 
-depends=('acpid' 'zsh' 'pciutils' 'usbutils' 'yad')
+depends=('hwdata' 'yad')
 optdepends=(
+'acpid: needed for AC/battery switching without power supply uevents, see --acpi-events'
 'brightnessctl: needed for brightness settings' 
 'net-tools: needed to disable ethernet cards' 'net-tools: needed to disable ethernet cards'
 'xorg-xrandr: needed for screen settings' 
@@ -69,13 +70,11 @@ def create_install_file():
     return f"""post_install() {{
   power-daemon-mgr setup
   systemctl daemon-reload
-  systemctl restart acpid.service
   systemctl enable --now power-options.service
 }}
 
 post_upgrade() {{
   systemctl daemon-reload
-  systemctl restart acpid.service
   systemctl restart power-options.service
 }}
 
@@ -1,8 +1,17 @@
+use std::fs;
+use std::path::PathBuf;
+
+use adw::prelude::*;
+
 use lazy_static::lazy_static;
 use log::debug;
+use log::error;
 use log::trace;
-use power_daemon::DefaultProfileType;
+use power_daemon::communication::client::DaemonEvent;
+use power_daemon::communication::ControlError;
 use power_daemon::{communication::client::ControlClient, Config, Profile, ReducedUpdate};
+use power_daemon::{Bundle, DefaultProfileType};
+use relm4::prelude::*;
 use tokio::sync::MappedMutexGuard;
 use tokio::sync::Mutex;
 use tokio::sync::MutexGuard;
@@ -11,6 +20,8 @@ use super::{CONFIG, PROFILES_INFO, PROFILE_OVERRIDE};
 
 lazy_static! {
     static ref CLIENT: Mutex<Option<ControlClient>> = None.into();
+    /// The revision of the synced config, which config edits are made at
+    static ref CONFIG_REVISION: Mutex<Option<u64>> = None.into();
 }
 
 pub async fn setup_control_client() {
@@ -18,105 +29,266 @@ pub async fn setup_control_client() {
     *CLIENT.lock().await = Some(ControlClient::new().await.unwrap());
 }
 
+/// Keeps the synced values up to date with changes that didn't come from us,
+/// like profile rules or other clients
+pub async fn spawn_event_listener() {
+    debug!("Subscribing to daemon events");
+    let mut events = get_client().await.subscribe().await.unwrap();
+
+    tokio::spawn(async move {
+        while let Some(event) = events.recv().await {
+            trace!("Received daemon event: {event:?}");
+            match event {
+                DaemonEvent::ActiveProfileChanged(name) => {
+                    // The active profile is part of profiles info
+                    let local_info_up_to_date = PROFILES_INFO
+                        .get()
+                        .await
+                        .as_ref()
+                        .is_some_and(|p| p.get_active_profile().profile_name == name);
+
+                    if !local_info_up_to_date {
+                        get_profiles_info().await;
+                    }
+                }
+                // Obtained again along with its revision
+                DaemonEvent::ConfigUpdated(_) => get_config().await,
+                DaemonEvent::ProfilesChanged(profiles_info) => {
+                    PROFILES_INFO.set(profiles_info).await
+                }
+                DaemonEvent::ProfileOverrideChanged(temporary_override) => {
+                    PROFILE_OVERRIDE.set(temporary_override).await
+                }
+                DaemonEvent::ApplyFinished(_) | DaemonEvent::DriftDetected(_) => {}
+            }
+        }
+    });
+}
+
 pub async fn get_config() {
     debug!("Obtaining config");
-    CONFIG
-        .set(get_client().await.get_config().await.unwrap())
-        .await
+    match get_client().await.get_config_with_revision().await {
+        Ok(revisioned) => {
+            *CONFIG_REVISION.lock().await = Some(revisioned.revision);
+            CONFIG.set(revisioned.config).await
+        }
+        Err(error) => report_error("Could not obtain the config", error),
+    }
 }
 pub async fn get_profiles_info() {
     debug!("Obtaining profiles info");
-    PROFILES_INFO
-        .set(get_client().await.get_profiles_info().await.unwrap())
-        .await
+    match get_client().await.get_profiles_info().await {
+        Ok(profiles_info) => PROFILES_INFO.set(profiles_info).await,
+        Err(error) => report_error("Could not obtain the profiles", error),
+    }
 }
+/// If someone else changed the config in the meantime the edit is dropped,
+/// and the settings show the config as it is now so that it can be made again
 pub async fn update_config(config: Config) {
     debug!("Updating config");
-    get_client().await.update_config(config).await.unwrap();
+    let revision = *CONFIG_REVISION.lock().await;
+    let result = match revision {
+        Some(revision) => {
+            get_client()
+                .await
+                .update_config_at_revision(config, revision)
+                .await
+        }
+        None => get_client().await.update_config(config).await,
+    };
+    match result {
+        Ok(()) => {}
+        Err(ControlError::RevisionConflict(description)) => {
+            error!("Could not update config: {description}");
+            get_config().await;
+        }
+        Err(error) => {
+            report_error("Could not update the config", error);
+            get_config().await;
+        }
+    }
 }
-pub async fn get_active_profile_name() -> String {
+pub async fn get_active_profile_name() -> Option<String> {
     debug!("Obtaining the name of the active profile");
-    get_client().await.get_active_profile_name().await.unwrap()
-}
-pub async fn create_profile(profile_type: DefaultProfileType) {
-    debug!("Creating profile of type {profile_type:?}");
     get_client()
         .await
-        .create_profile(profile_type)
+        .get_active_profile_name()
         .await
-        .unwrap();
+        .inspect_err(|error| error!("Could not obtain the active profile: {error}"))
+        .ok()
+}
+pub async fn create_profile(profile_type: DefaultProfileType) {
+    debug!("Creating profile of type {profile_type:?}");
+    if let Err(error) = get_client().await.create_profile(profile_type).await {
+        report_error("Could not create the profile", error);
+    }
 }
 pub async fn reset_profile(idx: u32) {
     debug!("Resetting profile {idx}");
-    get_client().await.reset_profile(idx).await.unwrap();
+    if let Err(error) = get_client().await.reset_profile(idx).await {
+        report_error("Could not reset the profile", error);
+    }
 }
 pub async fn remove_profile(idx: u32) {
     debug!("Removing profile {idx}");
-    get_client().await.remove_profile(idx).await.unwrap();
+    if let Err(error) = get_client().await.remove_profile(idx).await {
+        report_error("Could not remove the profile", error);
+    }
 }
 pub async fn swap_profiles(idx: u32, new_idx: u32) {
     debug!("Swapping profile {idx} with {new_idx}");
-    get_client()
-        .await
-        .swap_profiles(idx, new_idx)
-        .await
-        .unwrap();
+    if let Err(error) = get_client().await.swap_profiles(idx, new_idx).await {
+        report_error("Could not reorder the profiles", error);
+    }
 }
 pub async fn update_profile_name(idx: u32, new_name: String) {
     debug!("Updating profile {idx} name to {new_name}");
-    get_client()
-        .await
-        .update_profile_name(idx, new_name)
-        .await
-        .unwrap();
+    // The name might be taken, which is not worth crashing over
+    if let Err(error) = get_client().await.update_profile_name(idx, new_name).await {
+        error!("Could not rename profile: {error}");
+    }
 }
 pub async fn update_profile_full(idx: u32, updated: Profile) {
     debug!("Updating profile {idx} fully");
     trace!("Updated profile: {updated:#?}");
 
-    get_client()
-        .await
-        .update_profile_full(idx, updated)
+    let id = updated.id.clone();
+    let revision = synced_revision(&id).await;
+    let result = get_client()
         .await
-        .unwrap();
+        .update_profile_full_by_id(&id, updated, revision)
+        .await;
+    handle_profile_update_result(result).await;
 }
 pub async fn update_profile_reduced(idx: u32, updated: Profile, reduced_update: ReducedUpdate) {
     debug!("Updating profile {idx} reduced: {reduced_update:?}");
     trace!("Updated profile: {updated:#?}");
 
-    get_client()
+    let id = updated.id.clone();
+    let revision = synced_revision(&id).await;
+    let result = get_client()
         .await
-        .update_profile_reduced(idx, updated, reduced_update)
+        .update_profile_reduced_by_id(&id, updated, reduced_update, revision)
+        .await;
+    handle_profile_update_result(result).await;
+}
+
+/// The revision of the profile the edited copy was taken from
+async fn synced_revision(id: &str) -> Option<u64> {
+    PROFILES_INFO
+        .get()
         .await
-        .unwrap();
+        .as_ref()
+        .map(|profiles_info| profiles_info.revision_of(id))
+}
+
+/// If someone else changed the profile in the meantime the edit is dropped,
+/// and the groups show the profile as it is now so that it can be made again
+async fn handle_profile_update_result(result: Result<(), ControlError>) {
+    match result {
+        Ok(()) => {}
+        Err(ControlError::RevisionConflict(description)) => {
+            error!("Could not update profile: {description}");
+            get_profiles_info().await;
+        }
+        Err(error) => {
+            report_error("Could not update the profile", error);
+            get_profiles_info().await;
+        }
+    }
 }
 
 pub async fn update_full() {
     debug!("Updating fully");
-    get_client().await.update_full().await.unwrap();
+    if let Err(error) = get_client().await.update_full().await {
+        report_error("Could not apply the profile", error);
+    }
 }
 pub async fn update(reduced_update: ReducedUpdate) {
     debug!("Updating reduced: {reduced_update:?}");
-    get_client().await.update_full().await.unwrap();
+    if let Err(error) = get_client().await.update_full().await {
+        report_error("Could not apply the profile", error);
+    }
 }
 
 pub async fn get_profile_override() {
     debug!("Obtaining profile override");
-    PROFILE_OVERRIDE
-        .set(get_client().await.get_profile_override().await.unwrap())
-        .await;
+    match get_client().await.get_temporary_override().await {
+        Ok(temporary_override) => PROFILE_OVERRIDE.set(temporary_override).await,
+        Err(error) => report_error("Could not obtain the profile override", error),
+    }
 }
 pub async fn set_profile_override(profile_name: String) {
     debug!("Setting profile override");
-    get_client()
-        .await
-        .set_profile_override(profile_name)
-        .await
-        .unwrap();
+    if let Err(error) = get_client().await.set_profile_override(profile_name).await {
+        report_error("Could not switch the profile", error);
+    }
 }
 pub async fn remove_profile_override() {
     debug!("Removing profile override profile override");
-    get_client().await.remove_profile_override().await.unwrap();
+    if let Err(error) = get_client().await.remove_profile_override().await {
+        report_error("Could not remove the profile override", error);
+    }
+}
+
+/// Returns a description of the outcome to show to the user
+pub async fn export_bundle(path: PathBuf, include_assignments: bool) -> String {
+    debug!("Exporting profiles to {path:?}");
+    match get_client()
+        .await
+        .export_bundle(&[], include_assignments)
+        .await
+    {
+        Ok(bundle) => match fs::write(&path, bundle.to_toml()) {
+            Ok(()) => format!(
+                "Exported {} profiles to {}",
+                bundle.profiles.len(),
+                path.display()
+            ),
+            Err(error) => format!("Could not write {}: {error}", path.display()),
+        },
+        Err(error) => format!("Could not export profiles: {error}"),
+    }
+}
+/// Returns a description of the outcome to show to the user
+pub async fn import_bundle(path: PathBuf) -> String {
+    debug!("Importing profiles from {path:?}");
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(error) => return format!("Could not read {}: {error}", path.display()),
+    };
+    let bundle = match Bundle::from_toml(&content) {
+        Ok(bundle) => bundle,
+        Err(error) => return format!("Could not parse {}: {error}", path.display()),
+    };
+
+    match get_client().await.import_bundle(&bundle).await {
+        Ok(report) => report.summary(),
+        Err(error) => format!("Could not import profiles: {error}"),
+    }
+}
+
+/// Logs the error and tells the user about it. Requests are sent from tokio
+/// tasks, so the dialog is shown from the main context.
+fn report_error(what: &str, error: ControlError) {
+    error!("{what}: {error}");
+
+    let heading = what.to_string();
+    let body = match error {
+        ControlError::NotAuthorized(_) => {
+            "You are not allowed to do this. Ask an administrator to allow it for your user."
+                .to_string()
+        }
+        error => error.to_string(),
+    };
+
+    gtk::glib::MainContext::default().invoke(move || {
+        relm4::spawn_local(async move {
+            let dialog = adw::AlertDialog::new(Some(&heading), Some(&body));
+            dialog.add_response("close", "Close");
+            dialog.choose_future(&gtk::Window::default()).await;
+        });
+    });
 }
 
 async fn get_client() -> MappedMutexGuard<'static, ControlClient> {
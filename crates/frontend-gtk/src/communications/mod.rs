@@ -1,6 +1,6 @@
 use lazy_static::lazy_static;
 
-use power_daemon::{Config, ProfilesInfo, SystemInfo};
+use power_daemon::{Config, ProfilesInfo, SystemInfo, TemporaryOverride};
 
 use crate::helpers::SyncedValue;
 
@@ -10,6 +10,6 @@ pub mod system_info;
 lazy_static! {
     pub static ref CONFIG: SyncedValue<Config> = SyncedValue::new();
     pub static ref PROFILES_INFO: SyncedValue<ProfilesInfo> = SyncedValue::new();
-    pub static ref PROFILE_OVERRIDE: SyncedValue<Option<String>> = SyncedValue::new();
+    pub static ref PROFILE_OVERRIDE: SyncedValue<Option<TemporaryOverride>> = SyncedValue::new();
     pub static ref SYSTEM_INFO: SyncedValue<SystemInfo> = SyncedValue::new();
 }
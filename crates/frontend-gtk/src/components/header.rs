@@ -1,5 +1,7 @@
+use std::time::Duration;
+
 use gtk::{glib::clone, prelude::*};
-use power_daemon::ProfilesInfo;
+use power_daemon::{ProfilesInfo, TemporaryOverride};
 use relm4::prelude::*;
 
 use crate::communications::daemon_control;
@@ -12,6 +14,7 @@ pub enum HeaderInput {
     ChangingTo(Option<usize>),
     AllowApplyButton(bool),
     UpdateTempOverrideResetBtn(TempOverrideResetButtonStatus),
+    RefreshRemainingTime,
 }
 
 impl From<RootRequest> for HeaderInput {
@@ -25,7 +28,7 @@ pub enum TempOverrideResetButtonStatus {
     #[default]
     Disabled,
     Loading,
-    Enabled(String),
+    Enabled(TemporaryOverride),
 }
 
 #[derive(Debug, Default)]
@@ -55,10 +58,18 @@ impl SimpleComponent for Header {
     fn init(
         _init: Self::Init,
         root: Self::Root,
-        _sender: ComponentSender<Self>,
+        sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
         let model = Header::default();
 
+        // Keeps the remaining time of timed overrides up to date
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                sender.input(HeaderInput::RefreshRemainingTime);
+            }
+        });
+
         let widgets = HeaderWidgets { header_bar: root };
 
         ComponentParts { model, widgets }
@@ -75,9 +86,9 @@ impl SimpleComponent for Header {
                     self.profiles_info = (*profiles_info).clone();
                 }
                 if let AppSyncUpdate::TemporaryOverride(temporary_override) = message.clone() {
-                    if let Some(profile_name) = temporary_override.as_ref() {
+                    if let Some(temporary_override) = temporary_override.as_ref() {
                         self.reset_temp_override_btn_status =
-                            TempOverrideResetButtonStatus::Enabled(profile_name.clone())
+                            TempOverrideResetButtonStatus::Enabled(temporary_override.clone())
                     } else {
                         self.reset_temp_override_btn_status =
                             TempOverrideResetButtonStatus::Disabled;
@@ -88,6 +99,8 @@ impl SimpleComponent for Header {
             HeaderInput::ChangingTo(idx) => self.changing_to = idx,
             HeaderInput::AllowApplyButton(v) => self.enable_apply_button = v,
             HeaderInput::UpdateTempOverrideResetBtn(v) => self.reset_temp_override_btn_status = v,
+            // Only re-renders, the daemon tells us once the override expires
+            HeaderInput::RefreshRemainingTime => {}
         }
     }
 
@@ -178,12 +191,26 @@ impl SimpleComponent for Header {
 
                 ret.set_end_widget(Some(&end_widget));
 
-                if let TempOverrideResetButtonStatus::Enabled(ref profile_name) =
+                if let TempOverrideResetButtonStatus::Enabled(ref temporary_override) =
                     self.reset_temp_override_btn_status
                 {
+                    let profile_name = &temporary_override.profile_name;
+                    let (label, tooltip) = if let Some(remaining) = temporary_override.remaining() {
+                        let minutes = remaining.as_secs().div_ceil(60);
+                        (
+                            format!("Reset Override ({minutes} min left)"),
+                            format!("The current profile is currently locked to \"{}\" for {} more minutes or until next restart. Note that this is not the same as the persistent override found in daemon settings.", profile_name, minutes),
+                        )
+                    } else {
+                        (
+                            "Reset Override".to_string(),
+                            format!("The current profile is currently locked to \"{}\" until next restart. Note that this is not the same as the persistent override found in daemon settings.", profile_name),
+                        )
+                    };
+
                     let reset_temporary_override_button = gtk::Button::builder()
-                        .label("Reset Override")
-                        .tooltip_text(format!("The current profile is currently locked to \"{}\" until next restart. Note that this is not the same as the persistent override found in daemon settings.", profile_name))
+                        .label(label)
+                        .tooltip_text(tooltip)
                         .build();
 
                     reset_temporary_override_button.connect_clicked(clone!(
@@ -22,6 +22,9 @@ pub enum SettingsInput {
     Changed(ChangeAction),
     AskAndRemoveProfile(DynamicIndex),
     AskAndResetProfile(DynamicIndex),
+    ExportBundle,
+    ImportBundle,
+    ShowBundleOutcome(String),
 }
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
@@ -58,6 +61,8 @@ pub struct Settings {
     supressed_actions: HashMap<ChangeAction, u32>,
 
     last_config: Option<Config>,
+    /// In the order of the config's list of profile ids
+    profile_names: Vec<String>,
 
     profiles: FactoryVecDeque<ProfileFactoryRenderer>,
 
@@ -71,6 +76,8 @@ pub struct Settings {
 
     persistent_override_set: BoolBinding,
     selected_persitent_override: U32Binding,
+
+    bundle_include_assignments: BoolBinding,
 }
 
 impl Settings {
@@ -92,31 +99,32 @@ impl Settings {
         );
 
         *self.available_profiles.guard() = gtk::StringList::new(
-            &config
-                .profiles
+            &self
+                .profile_names
                 .iter()
                 .map(|e| e as &str)
                 .collect::<Vec<&str>>(),
         );
 
-        *self.selected_bat_profile.guard() = config
-            .profiles
+        // The profiles may not have been received yet
+        *self.selected_bat_profile.guard() = self
+            .profile_names
             .iter()
             .position(|p| *p == config.bat_profile)
-            .unwrap() as u32;
-        *self.selected_ac_profile.guard() = config
-            .profiles
+            .unwrap_or_default() as u32;
+        *self.selected_ac_profile.guard() = self
+            .profile_names
             .iter()
             .position(|p| *p == config.ac_profile)
-            .unwrap() as u32;
+            .unwrap_or_default() as u32;
 
         if let Some(ref persistent_override) = config.profile_override {
             *self.persistent_override_set.guard() = true;
-            *self.selected_persitent_override.guard() = config
-                .profiles
+            *self.selected_persitent_override.guard() = self
+                .profile_names
                 .iter()
                 .position(|p| p == persistent_override)
-                .unwrap() as u32;
+                .unwrap_or_default() as u32;
         } else {
             *self.persistent_override_set.guard() = false;
         }
@@ -191,6 +199,27 @@ impl SimpleAsyncComponent for Settings {
 
                     adw::PreferencesGroup {
                         container_add: model.profiles.widget(),
+                    },
+
+                    adw::PreferencesGroup {
+                        set_title: "Share profiles",
+                        set_description: Some("Export every profile into a single file that can be imported on other machines"),
+                        adw::SwitchRow {
+                            set_title: "Export AC and battery profiles and rules",
+                            add_binding: (&model.bundle_include_assignments, "active"),
+                        },
+                        gtk::Box {
+                            set_spacing: 5,
+                            set_homogeneous: true,
+                            gtk::Button {
+                                set_label: "Export profiles",
+                                connect_clicked => SettingsInput::ExportBundle,
+                            },
+                            gtk::Button {
+                                set_label: "Import profiles",
+                                connect_clicked => SettingsInput::ImportBundle,
+                            },
+                        },
                     }
                 }
             }
@@ -208,6 +237,7 @@ impl SimpleAsyncComponent for Settings {
             supressed_actions: HashMap::new(),
 
             last_config: None,
+            profile_names: Vec::new(),
 
             profiles: FactoryVecDeque::builder()
                 .launch(gtk::Box::new(gtk::Orientation::Vertical, 0))
@@ -227,6 +257,7 @@ impl SimpleAsyncComponent for Settings {
             selected_ac_profile: Default::default(),
             persistent_override_set: Default::default(),
             selected_persitent_override: Default::default(),
+            bundle_include_assignments: Default::default(),
         };
 
         let widgets = view_output!();
@@ -253,6 +284,16 @@ impl SimpleAsyncComponent for Settings {
                                     profile,
                                 });
                             }
+                            drop(guard);
+
+                            self.profile_names = profiles_info
+                                .profiles
+                                .iter()
+                                .map(|p| p.profile_name.clone())
+                                .collect();
+                            if let Some(config) = self.last_config.clone() {
+                                self.from_config(&config);
+                            }
                         }
                     }
                     if let AppSyncUpdate::Config(config) = &update {
@@ -385,6 +426,56 @@ impl SimpleAsyncComponent for Settings {
                     ))
                 }
             }
+            SettingsInput::ExportBundle => {
+                let include_assignments = self.bundle_include_assignments.value();
+                let dialog = gtk::FileDialog::builder()
+                    .title("Export profiles")
+                    .initial_name("power-options-bundle.toml")
+                    .build();
+
+                if let Some(path) = dialog
+                    .save_future(None::<&gtk::Window>)
+                    .await
+                    .ok()
+                    .and_then(|file| file.path())
+                {
+                    tokio::spawn(async move {
+                        sender.input(SettingsInput::SetUpdating(true));
+
+                        let outcome =
+                            daemon_control::export_bundle(path, include_assignments).await;
+
+                        sender.input(SettingsInput::SetUpdating(false));
+                        sender.input(SettingsInput::ShowBundleOutcome(outcome));
+                    });
+                }
+            }
+            SettingsInput::ImportBundle => {
+                let dialog = gtk::FileDialog::builder().title("Import profiles").build();
+
+                if let Some(path) = dialog
+                    .open_future(None::<&gtk::Window>)
+                    .await
+                    .ok()
+                    .and_then(|file| file.path())
+                {
+                    tokio::spawn(async move {
+                        sender.input(SettingsInput::SetUpdating(true));
+
+                        let outcome = daemon_control::import_bundle(path).await;
+                        daemon_control::get_config().await;
+                        daemon_control::get_profiles_info().await;
+
+                        sender.input(SettingsInput::SetUpdating(false));
+                        sender.input(SettingsInput::ShowBundleOutcome(outcome));
+                    });
+                }
+            }
+            SettingsInput::ShowBundleOutcome(outcome) => {
+                let dialog = adw::AlertDialog::new(Some("Share profiles"), Some(&outcome));
+                dialog.add_response("close", "Close");
+                dialog.choose_future(&gtk::Window::default()).await;
+            }
             SettingsInput::SetUpdating(v) => self.updating = v,
             SettingsInput::Changed(changed) => {
                 if let Some(val) = self.supressed_actions.get_mut(&changed) {
@@ -395,7 +486,7 @@ impl SimpleAsyncComponent for Settings {
                         debug!("Applying action {changed:?}");
                         match changed {
                             ChangeAction::Bat => {
-                                config.bat_profile = config.profiles
+                                config.bat_profile = self.profile_names
                                     [self.selected_bat_profile.value() as usize]
                                     .clone();
                                 tokio::spawn(async move {
@@ -409,7 +500,7 @@ impl SimpleAsyncComponent for Settings {
                                 });
                             }
                             ChangeAction::AC => {
-                                config.ac_profile = config.profiles
+                                config.ac_profile = self.profile_names
                                     [self.selected_ac_profile.value() as usize]
                                     .clone();
                                 tokio::spawn(async move {
@@ -425,7 +516,7 @@ impl SimpleAsyncComponent for Settings {
                             ChangeAction::PersistentOverride => {
                                 config.profile_override = if self.persistent_override_set.value() {
                                     Some(
-                                        config.profiles
+                                        self.profile_names
                                             [self.selected_persitent_override.value() as usize]
                                             .clone(),
                                     )
@@ -1,14 +1,17 @@
 use std::time::Duration;
 
 use dioxus::prelude::*;
+use futures_util::StreamExt;
 
-use power_daemon::communication::client::ControlClient;
+use power_daemon::communication::client::{ControlClient, DaemonEvent};
+use power_daemon::communication::ControlError;
 use power_daemon::systeminfo::SystemInfo;
 use power_daemon::Config;
 use power_daemon::DefaultProfileType;
 use power_daemon::ProfilesInfo;
 use power_daemon::ReducedUpdate;
-use power_daemon::{communication::client::SystemInfoClient, Profile};
+use power_daemon::TemporaryOverride;
+use power_daemon::{communication::client::SystemInfoClient, Bundle, Profile};
 
 use crate::helpers::coroutine_extensions::{wait_for_diff_msg, wait_for_msg};
 
@@ -140,51 +143,66 @@ pub enum ControlAction {
     GetProfileOverride,
     SetProfileOverride(String),
     RemoveProfileOverride,
+
+    /// Writes every profile to `path`, reporting the outcome in `status`
+    ExportBundle {
+        path: String,
+        include_assignments: bool,
+        status: Signal<String>,
+    },
+    /// Reports the outcome in `status`
+    ImportBundle {
+        path: String,
+        status: Signal<String>,
+    },
 }
 
-pub async fn background_daemon_sync_routine(
-    mut active_profile_name: Signal<Option<String>>,
-    profiles_info: Signal<Option<ProfilesInfo>>,
+/// Keeps the local state in sync with changes that didn't come from us, like
+/// profile rules or other clients
+pub async fn daemon_events_routine(
+    mut profiles_info: Signal<Option<ProfilesInfo>>,
+    mut active_profile_override: Signal<Option<TemporaryOverride>>,
     control_routine: ControlRoutine,
 ) {
     let control_client = ControlClient::new()
         .await
         .expect("Could not initialize control client");
 
-    active_profile_name.set(
-        control_client
-            .get_active_profile_name()
-            .await
-            .unwrap()
-            .into(),
-    );
-
-    loop {
-        let name = control_client.get_active_profile_name().await.unwrap();
+    let mut events = control_client
+        .subscribe()
+        .await
+        .expect("Could not subscribe to daemon events");
 
-        if active_profile_name().unwrap() != name {
-            let local_info_up_to_date = if let Some(profiles_info) = profiles_info() {
-                profiles_info.get_active_profile().profile_name == name
-            } else {
-                false
-            };
+    while let Some(event) = events.recv().await {
+        match event {
+            DaemonEvent::ActiveProfileChanged(name) => {
+                // The active profile is part of profiles info
+                let local_info_up_to_date = profiles_info
+                    .peek()
+                    .as_ref()
+                    .is_some_and(|p| p.get_active_profile().profile_name == name);
 
-            if !local_info_up_to_date {
-                control_routine_send_multiple(
-                    control_routine,
-                    &[
-                        ControlAction::GetProfilesInfo,
-                        ControlAction::GetConfig,
-                        ControlAction::GetProfileOverride,
-                    ],
-                    None,
-                );
+                if !local_info_up_to_date {
+                    control_routine.send((ControlAction::GetProfilesInfo, None));
+                }
             }
-
-            active_profile_name.set(name.into());
+            // Obtained again along with its revision, which config edits are
+            // made at
+            DaemonEvent::ConfigUpdated(_) => {
+                control_routine.send((ControlAction::GetConfig, None));
+            }
+            DaemonEvent::ProfilesChanged(updated) => {
+                if profiles_info.peek().as_ref() != Some(&updated) {
+                    profiles_info.set(Some(updated));
+                }
+            }
+            DaemonEvent::ProfileOverrideChanged(updated) => {
+                if *active_profile_override.peek() != updated {
+                    active_profile_override.set(updated);
+                }
+            }
+            DaemonEvent::ApplyFinished(_) | DaemonEvent::DriftDetected(_) => {}
         }
-
-        tokio::time::sleep(std::time::Duration::from_secs_f32(1.0)).await;
     }
 }
 
@@ -194,84 +212,161 @@ pub async fn control_service(
     mut rx: UnboundedReceiver<(ControlAction, Option<Signal<bool>>)>,
     mut config: Signal<Option<Config>>,
     mut profiles_info: Signal<Option<ProfilesInfo>>,
-    mut active_profile_override: Signal<Option<String>>,
+    mut active_profile_override: Signal<Option<TemporaryOverride>>,
 ) {
     let control_client = ControlClient::new()
         .await
         .expect("Could not initialize control client");
 
-    loop {
-        if let Ok(Some(sent_msg)) = rx.try_next() {
-            let msg = sent_msg.0;
-            if let Some(mut signal) = sent_msg.1 {
-                signal.set(true);
-            }
+    // The revision of the config the edited copy was taken from
+    let mut config_revision = None;
+
+    while let Some(sent_msg) = rx.next().await {
+        let msg = sent_msg.0;
+        if let Some(mut signal) = sent_msg.1 {
+            signal.set(true);
+        }
 
-            match msg {
-                ControlAction::GetConfig => {
-                    config.set(Some(
+        match msg {
+            ControlAction::GetConfig => {
+                sync_config(&control_client, &mut config, &mut config_revision).await
+            }
+            ControlAction::GetProfilesInfo => profiles_info.set(Some(
+                control_client
+                    .get_profiles_info()
+                    .await
+                    .expect("Could not obtain profiles info."),
+            )),
+            ControlAction::UpdateConfig(updated) => {
+                let result = match config_revision {
+                    Some(revision) => {
                         control_client
-                            .get_config()
+                            .update_config_at_revision(*updated, revision)
                             .await
-                            .expect("Could not obtain config"),
-                    ));
+                    }
+                    None => control_client.update_config(*updated).await,
+                };
+                match result {
+                    Ok(()) => {}
+                    // Someone else changed the config in the meantime, the
+                    // edit is dropped and the config shown as it is now
+                    Err(ControlError::RevisionConflict(description)) => {
+                        tracing::error!("Could not update config: {description}");
+                        sync_config(&control_client, &mut config, &mut config_revision).await;
+                    }
+                    Err(error) => panic!("Could not update config: {error}"),
                 }
-                ControlAction::GetProfilesInfo => profiles_info.set(Some(
-                    control_client
-                        .get_profiles_info()
-                        .await
-                        .expect("Could not obtain profiles info."),
-                )),
-                ControlAction::UpdateConfig(config) => control_client
-                    .update_config(*config)
-                    .await
-                    .expect("Could not update config"),
-                ControlAction::UpdateProfileReduced(idx, updated, reduced_update) => control_client
-                    .update_profile_reduced(idx, *updated, reduced_update)
+            }
+            ControlAction::UpdateProfileReduced(_, updated, reduced_update) => {
+                // The revision of the profile the edited copy was taken from
+                let id = updated.id.clone();
+                let revision = profiles_info
+                    .peek()
+                    .as_ref()
+                    .map(|profiles_info| profiles_info.revision_of(&id));
+                match control_client
+                    .update_profile_reduced_by_id(&id, *updated, reduced_update, revision)
                     .await
-                    .expect("Could not update profile"),
-                ControlAction::CreateProfile(profile_type) => control_client
+                {
+                    Ok(()) => {}
+                    // Someone else changed the profile in the meantime, the
+                    // edit is dropped and the profile shown as it is now
+                    Err(ControlError::RevisionConflict(description)) => {
+                        tracing::error!("Could not update profile: {description}");
+                        profiles_info.set(Some(
+                            control_client
+                                .get_profiles_info()
+                                .await
+                                .expect("Could not obtain profiles info."),
+                        ));
+                    }
+                    Err(error) => panic!("Could not update profile: {error}"),
+                }
+            }
+            ControlAction::CreateProfile(profile_type) => {
+                control_client
                     .create_profile(profile_type)
                     .await
-                    .expect("Could not create profile"),
-                ControlAction::SwapProfiles(idx, new_idx) => control_client
-                    .swap_profiles(idx, new_idx)
-                    .await
-                    .expect("Could not create profile"),
-                ControlAction::ResetProfile(idx) => control_client
-                    .reset_profile(idx)
-                    .await
-                    .expect("Could not reset profile"),
-                ControlAction::RenameProfile(idx, name) => control_client
-                    .update_profile_name(idx, name)
-                    .await
-                    .expect("Could not reset profile"),
-                ControlAction::RemoveProfile(idx) => control_client
-                    .remove_profile(idx)
-                    .await
-                    .expect("Could not remove profile"),
-                ControlAction::GetProfileOverride => active_profile_override.set(
-                    control_client
-                        .get_profile_override()
-                        .await
-                        .expect("Could not obtain profile override"),
-                ),
-                ControlAction::SetProfileOverride(profile_name) => control_client
-                    .set_profile_override(profile_name)
-                    .await
-                    .expect("Could not set profile override"),
-                ControlAction::RemoveProfileOverride => control_client
-                    .remove_profile_override()
+                    .expect("Could not create profile");
+            }
+            ControlAction::SwapProfiles(idx, new_idx) => control_client
+                .swap_profiles(idx, new_idx)
+                .await
+                .expect("Could not create profile"),
+            ControlAction::ResetProfile(idx) => control_client
+                .reset_profile(idx)
+                .await
+                .expect("Could not reset profile"),
+            ControlAction::RenameProfile(idx, name) => {
+                // The name might be taken, which is not worth crashing over
+                if let Err(error) = control_client.update_profile_name(idx, name).await {
+                    tracing::error!("Could not rename profile: {error}");
+                }
+            }
+            ControlAction::RemoveProfile(idx) => control_client
+                .remove_profile(idx)
+                .await
+                .expect("Could not remove profile"),
+            ControlAction::GetProfileOverride => active_profile_override.set(
+                control_client
+                    .get_temporary_override()
                     .await
-                    .expect("Could not remove profile override"),
+                    .expect("Could not obtain profile override"),
+            ),
+            ControlAction::SetProfileOverride(profile_name) => control_client
+                .set_profile_override(profile_name)
+                .await
+                .expect("Could not set profile override"),
+            ControlAction::RemoveProfileOverride => control_client
+                .remove_profile_override()
+                .await
+                .expect("Could not remove profile override"),
+            ControlAction::ExportBundle {
+                path,
+                include_assignments,
+                mut status,
+            } => status.set(
+                match control_client.export_bundle(&[], include_assignments).await {
+                    Ok(bundle) => match std::fs::write(&path, bundle.to_toml()) {
+                        Ok(()) => format!("Exported {} profiles to {path}", bundle.profiles.len()),
+                        Err(error) => format!("Could not write {path}: {error}"),
+                    },
+                    Err(error) => format!("Could not export profiles: {error}"),
+                },
+            ),
+            ControlAction::ImportBundle { path, mut status } => {
+                status.set(match std::fs::read_to_string(&path) {
+                    Ok(content) => match Bundle::from_toml(&content) {
+                        Ok(bundle) => match control_client.import_bundle(&bundle).await {
+                            Ok(report) => report.summary(),
+                            Err(error) => format!("Could not import bundle: {error}"),
+                        },
+                        Err(error) => format!("Could not parse {path}: {error}"),
+                    },
+                    Err(error) => format!("Could not read {path}: {error}"),
+                })
             }
+        }
 
-            if let Some(mut signal) = sent_msg.1 {
-                signal.set(false);
-            }
+        if let Some(mut signal) = sent_msg.1 {
+            signal.set(false);
         }
+    }
+}
 
-        tokio::time::sleep(Duration::from_millis(20)).await
+/// Obtains the config along with the revision edits of it are made at
+async fn sync_config(
+    control_client: &ControlClient,
+    config: &mut Signal<Option<Config>>,
+    config_revision: &mut Option<u64>,
+) {
+    let revisioned = control_client
+        .get_config_with_revision()
+        .await
+        .expect("Could not obtain config");
+    *config_revision = Some(revisioned.revision);
+    if config.peek().as_ref() != Some(&revisioned.config) {
+        config.set(Some(revisioned.config));
     }
 }
 
@@ -8,8 +8,8 @@ mod settings;
 use std::time::Duration;
 
 use communication_services::{
-    background_daemon_sync_routine, control_routine_send_multiple, control_service,
-    system_info_service, ControlAction, ControlRoutine, SystemInfoSyncType,
+    control_routine_send_multiple, control_service, daemon_events_routine, system_info_service,
+    ControlAction, ControlRoutine, SystemInfoSyncType,
 };
 use setting_groups::{
     audio::AudioGroup, cpu::CPUGroup, firmware::FirmwareGroup, gpu::GpuGroup, kernel::KernelGroup,
@@ -22,7 +22,7 @@ use dioxus::{
     desktop::{Config, LogicalSize, WindowBuilder},
     prelude::*,
 };
-use power_daemon::{ProfilesInfo, SystemInfo};
+use power_daemon::{ProfilesInfo, SystemInfo, TemporaryOverride};
 use tracing::Level;
 
 fn main() {
@@ -66,9 +66,8 @@ fn App() -> Element {
         control_service(rx, config, profiles_info, active_profile_override)
     });
 
-    let active_profile_name = use_signal(|| None);
     let _ = use_coroutine(move |_: UnboundedReceiver<()>| {
-        background_daemon_sync_routine(active_profile_name, profiles_info, control_routine)
+        daemon_events_routine(profiles_info, active_profile_override, control_routine)
     });
 
     control_routine_send_multiple(
@@ -127,7 +126,7 @@ fn App() -> Element {
 #[component]
 fn PowerProfilesNav(
     profiles_info: Signal<Option<ProfilesInfo>>,
-    active_profile_override: ReadOnlySignal<Option<String>>,
+    active_profile_override: ReadOnlySignal<Option<TemporaryOverride>>,
     control_routine: ControlRoutine,
 ) -> Element {
     let waiting_override_set = use_signal(|| false);
@@ -135,6 +134,22 @@ fn PowerProfilesNav(
 
     let waiting_override_remove = use_signal(|| false);
 
+    // Re-rendering periodically keeps the remaining time of timed overrides
+    // up to date
+    let mut refresh_count = use_signal(|| 0u32);
+    use_future(move || async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            refresh_count += 1;
+        }
+    });
+    let _ = refresh_count();
+
+    let override_name = active_profile_override().map(|o| o.profile_name);
+    let override_remaining = active_profile_override()
+        .and_then(|o| o.remaining())
+        .map(|r| format!("{} min left", r.as_secs().div_ceil(60)));
+
     if profiles_info().is_some() {
         let mut buttons = Vec::new();
         for (idx, profile) in profiles_info()
@@ -176,9 +191,7 @@ fn PowerProfilesNav(
                                             button.2(e);
                                         },
                                         class: if button.0 == profiles_info().as_ref().unwrap().active_profile {
-                                            if active_profile_override().is_some()
-                                                && active_profile_override().unwrap() == button.1
-                                            {
+                                            if override_name.as_ref() == Some(&button.1) {
                                                 "temporary-override"
                                             } else {
                                                 "active"
@@ -188,9 +201,10 @@ fn PowerProfilesNav(
                                         },
                                         "{button.1}"
                                     }
-                                    if active_profile_override().is_some()
-                                        && active_profile_override().unwrap() == button.1
-                                    {
+                                    if override_name.as_ref() == Some(&button.1) {
+                                        if let Some(ref remaining) = override_remaining {
+                                            span { class: "override-remaining", "{remaining}" }
+                                        }
                                         if waiting_override_remove() {
                                             div {
                                                 display: "inline-block",
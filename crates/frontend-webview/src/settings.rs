@@ -24,17 +24,24 @@ struct SettingsForm {
 }
 
 impl SettingsForm {
-    pub fn new(config: &Config) -> SettingsForm {
+    pub fn new(config: &Config, profiles_info: &ProfilesInfo) -> SettingsForm {
         let mut ret = SettingsForm::default();
-        ret.set_values(config);
+        ret.set_values(config, profiles_info);
         ret
     }
 
-    pub fn set_values(&mut self, config: &Config) {
+    pub fn set_values(&mut self, config: &Config, profiles_info: &ProfilesInfo) {
         self.ac_profile.set(config.ac_profile.clone());
         self.bat_profile.set(config.bat_profile.clone());
         self.profile_override.from(config.profile_override.clone());
-        self.profiles.set(config.profiles.clone());
+        // The config lists profile ids, the form works with names
+        self.profiles.set(
+            profiles_info
+                .profiles
+                .iter()
+                .map(|p| p.profile_name.clone())
+                .collect(),
+        );
     }
 }
 
@@ -59,10 +66,12 @@ pub fn SettingsMenu(
     let profiles_info = profiles_info().unwrap();
 
     let mut form_used_config = use_signal(|| config.clone());
-    let mut form = use_hook(|| SettingsForm::new(&config));
-    if form_used_config() != config {
-        form.set_values(&config);
+    let mut form_used_profiles_info = use_signal(|| profiles_info.clone());
+    let mut form = use_hook(|| SettingsForm::new(&config, &profiles_info));
+    if form_used_config() != config || form_used_profiles_info() != profiles_info {
+        form.set_values(&config, &profiles_info);
         form_used_config.set(config.clone());
+        form_used_profiles_info.set(profiles_info.clone());
     }
 
     let mut changed = use_signal(|| false);
@@ -81,6 +90,16 @@ pub fn SettingsMenu(
     let mut awaiting_move_down_idx = use_signal(|| 0);
     let mut awaiting_rename_idx = use_signal(|| 0);
 
+    let mut bundle_path = use_signal(|| {
+        format!(
+            "{}/power-options-bundle.toml",
+            std::env::var("HOME").unwrap_or_default()
+        )
+    });
+    let mut bundle_include_assignments = use_signal(|| false);
+    let bundle_status = use_signal(String::new);
+    let awaiting_bundle = use_signal(|| false);
+
     let new_profile_type = use_signal(|| DefaultProfileType::Balanced.get_name());
     let profile_types = use_hook(|| {
         vec![
@@ -219,6 +238,82 @@ pub fn SettingsMenu(
 
                 br {}
 
+                div { class: "option-group",
+                    div { class: "option",
+                        label { "Bundle file" }
+                        input {
+                            r#type: "text",
+                            onchange: move |v| {
+                                v.stop_propagation();
+                                bundle_path.set(v.value());
+                            },
+                            initial_value: "{bundle_path}"
+                        }
+                    }
+                    div { class: "option",
+                        label { "Export AC/battery profiles and rules" }
+                        input {
+                            r#type: "checkbox",
+                            onchange: move |v| {
+                                v.stop_propagation();
+                                bundle_include_assignments.set(v.checked());
+                            },
+                            checked: bundle_include_assignments()
+                        }
+                    }
+                    if awaiting_bundle() {
+                        div {
+                            width: "100%",
+                            display: "flex",
+                            justify_content: "center",
+                            div { class: "spinner" }
+                        }
+                    } else {
+                        div { display: "flex",
+                            button {
+                                width: "100%",
+                                r#type: "button",
+                                onclick: move |_| {
+                                    control_routine
+                                        .send((
+                                            ControlAction::ExportBundle {
+                                                path: bundle_path(),
+                                                include_assignments: bundle_include_assignments(),
+                                                status: bundle_status,
+                                            },
+                                            Some(awaiting_bundle),
+                                        ));
+                                },
+                                "Export Profiles"
+                            }
+                            button {
+                                width: "100%",
+                                r#type: "button",
+                                onclick: move |_| {
+                                    control_routine_send_multiple(
+                                        control_routine,
+                                        &[
+                                            ControlAction::ImportBundle {
+                                                path: bundle_path(),
+                                                status: bundle_status,
+                                            },
+                                            ControlAction::GetConfig,
+                                            ControlAction::GetProfilesInfo,
+                                        ],
+                                        Some(awaiting_bundle),
+                                    );
+                                },
+                                "Import Profiles"
+                            }
+                        }
+                    }
+                    if !bundle_status().is_empty() {
+                        p { "{bundle_status}" }
+                    }
+                }
+
+                br {}
+
                 label { "Profiles" }
 
                 table { max_width: "600px",
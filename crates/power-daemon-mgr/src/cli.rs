@@ -0,0 +1,715 @@
+//! Subcommands that drive a running daemon through its control interface,
+//! meant for scripts and machines without a graphical frontend
+
+use std::{collections::BTreeMap, error::Error, fs, path::PathBuf, time::Duration};
+
+use clap::Subcommand;
+use colored::Colorize;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+
+use power_daemon::{
+    communication::client::ControlClient, Bundle, Config, DefaultProfileType, ImportReport,
+    Profile, ProfilesInfo, RevisionedConfig, SystemInfo, SystemRoot, TemporaryOverride,
+    ValidationIssue,
+};
+
+pub type CliResult = Result<(), Box<dyn Error>>;
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ProfilesCommand {
+    /// List profiles in order, marking the active one
+    List,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ProfileCommand {
+    /// Print every setting of a profile
+    Show {
+        name: String,
+        /// Include the settings inherited from the profiles it extends
+        #[arg(long)]
+        resolved: bool,
+    },
+    /// Change settings of a profile, e.g. `cpu.governor=powersave`. Groups
+    /// may leave out the `_settings` suffix and an empty value unsets the
+    /// setting
+    Set {
+        name: String,
+        #[arg(required = true)]
+        assignments: Vec<String>,
+    },
+    /// Generate a new profile of one of the default types
+    Create {
+        /// One of Powersave++, Powersave, Balanced, Performance, Performance++
+        profile_type: String,
+        /// Name of the new profile instead of "New Profile"
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Create a profile that inherits every setting of another one until
+    /// they're overridden with `profile set`
+    Derive {
+        parent: String,
+        /// Name of the new profile instead of "New Profile"
+        #[arg(long)]
+        name: Option<String>,
+    },
+    Rename {
+        name: String,
+        new_name: String,
+    },
+    /// Move a profile to another position in the list, starting at 0
+    Move {
+        name: String,
+        position: usize,
+    },
+    /// Regenerate a profile from the default type it was created from
+    Reset {
+        name: String,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum OverrideCommand {
+    /// Print the temporary override, if any
+    Show,
+    /// Temporarily override the profile selection until the daemon restarts
+    Set {
+        name: String,
+        /// Remove the override after this many minutes
+        #[arg(long)]
+        minutes: Option<u64>,
+    },
+    Clear,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the daemon configuration
+    Show,
+    /// Change an option of the daemon configuration, e.g. `ac-profile
+    /// Powersave`. An empty value unsets optional options
+    Set { key: String, value: String },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum BundleCommand {
+    /// Pack profiles into a single file that can be imported on other machines
+    Export {
+        /// Profiles to export, all of them if none are given
+        profiles: Vec<String>,
+        /// Also export which profiles are used on AC and battery and the
+        /// profile and process rules
+        #[arg(long)]
+        with_config: bool,
+        /// Write the bundle to this file instead of printing it
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Add the profiles of a bundle, renaming those whose name is taken
+    Import { path: PathBuf },
+}
+
+pub async fn profiles(command: ProfilesCommand, json: bool) -> CliResult {
+    let client = ControlClient::new().await?;
+    match command {
+        ProfilesCommand::List => print_profiles_list(&client, json).await,
+    }
+}
+
+pub async fn profile(command: ProfileCommand, json: bool) -> CliResult {
+    let client = ControlClient::new().await?;
+    match command {
+        ProfileCommand::Show {
+            name,
+            resolved: true,
+        } => {
+            let resolved = client.get_resolved_profile(name).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&resolved)?);
+            } else {
+                print!("{}", toml::to_string_pretty(&resolved.profile)?);
+                let inherited: Vec<_> = resolved
+                    .sources
+                    .iter()
+                    .filter(|(_, source)| **source != resolved.profile.profile_name)
+                    .collect();
+                if !inherited.is_empty() {
+                    println!();
+                    println!("{}", "Inherited settings:".bold());
+                    for (setting, source) in inherited {
+                        println!("  {setting} from {source}");
+                    }
+                }
+            }
+        }
+        ProfileCommand::Show { name, .. } => {
+            let profiles_info = client.get_profiles_info().await?;
+            let profile = &profiles_info.profiles[find_profile(&profiles_info, &name)?];
+            if json {
+                println!("{}", serde_json::to_string_pretty(profile)?);
+            } else {
+                print!("{}", toml::to_string_pretty(profile)?);
+            }
+        }
+        ProfileCommand::Set { name, assignments } => {
+            let profiles_info = client.get_profiles_info().await?;
+            let idx = find_profile(&profiles_info, &name)?;
+
+            let assignments = assignments
+                .iter()
+                .map(|assignment| {
+                    assignment.split_once('=').ok_or_else(|| {
+                        format!("Expected <setting>=<value>, got {assignment}").into()
+                    })
+                })
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+            let profile = &profiles_info.profiles[idx];
+            let updated = assign(profile, &assignments, true)?;
+            client
+                .update_profile_full_by_id(
+                    &profile.id,
+                    updated.clone(),
+                    Some(profiles_info.revision_of(&profile.id)),
+                )
+                .await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&updated)?);
+            } else {
+                println!("Updated profile {}", name.bold());
+            }
+        }
+        ProfileCommand::Create { profile_type, name } => {
+            let Some(profile_type) = DefaultProfileType::from_name(profile_type.clone()) else {
+                return Err(format!(
+                    "Unknown profile type {profile_type}, expected one of: {}",
+                    DefaultProfileType::get_name_of_all().join(", ")
+                )
+                .into());
+            };
+
+            let id = client.create_profile(profile_type).await?;
+            name_created_profile(&client, &id, name, json).await?;
+        }
+        ProfileCommand::Derive { parent, name } => {
+            let id = client.create_derived_profile(parent).await?;
+            name_created_profile(&client, &id, name, json).await?;
+        }
+        ProfileCommand::Rename { name, new_name } => {
+            let (id, revision) = find_profile_id(&client.get_profiles_info().await?, &name)?;
+            client
+                .update_profile_name_by_id(&id, new_name, Some(revision))
+                .await?;
+            print_profiles_list(&client, json).await?;
+        }
+        ProfileCommand::Move { name, position } => {
+            let profiles_info = client.get_profiles_info().await?;
+            let mut idx = find_profile(&profiles_info, &name)?;
+            if position >= profiles_info.profiles.len() {
+                return Err(format!(
+                    "Position {position} is out of range, there are {} profiles",
+                    profiles_info.profiles.len()
+                )
+                .into());
+            }
+
+            // The daemon only swaps profiles, so the profile is swapped with
+            // its neighbours until it reaches its new position
+            let mut ids: Vec<_> = profiles_info
+                .profiles
+                .iter()
+                .map(|p| p.id.clone())
+                .collect();
+            while idx != position {
+                let next = if idx < position { idx + 1 } else { idx - 1 };
+                client.swap_profiles_by_id(&ids[idx], &ids[next]).await?;
+                ids.swap(idx, next);
+                idx = next;
+            }
+            print_profiles_list(&client, json).await?;
+        }
+        ProfileCommand::Reset { name } => {
+            let (id, revision) = find_profile_id(&client.get_profiles_info().await?, &name)?;
+            client.reset_profile_by_id(&id, Some(revision)).await?;
+            print_profiles_list(&client, json).await?;
+        }
+        ProfileCommand::Remove { name } => {
+            let (id, revision) = find_profile_id(&client.get_profiles_info().await?, &name)?;
+            client.remove_profile_by_id(&id, Some(revision)).await?;
+            print_profiles_list(&client, json).await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn profile_override(command: OverrideCommand, json: bool) -> CliResult {
+    let client = ControlClient::new().await?;
+    match command {
+        OverrideCommand::Show => {}
+        OverrideCommand::Set { name, minutes } => match minutes {
+            Some(minutes) => {
+                client
+                    .set_timed_profile_override(name, Duration::from_secs(minutes * 60))
+                    .await?
+            }
+            None => client.set_profile_override(name).await?,
+        },
+        OverrideCommand::Clear => client.remove_profile_override().await?,
+    }
+
+    let temporary_override = client.get_temporary_override().await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&temporary_override)?);
+    } else {
+        println!("{}", describe_override(temporary_override.as_ref()));
+    }
+    Ok(())
+}
+
+pub async fn config(command: ConfigCommand, json: bool) -> CliResult {
+    let client = ControlClient::new().await?;
+    let RevisionedConfig {
+        mut config,
+        revision,
+    } = client.get_config_with_revision().await?;
+
+    if let ConfigCommand::Set { key, value } = command {
+        config = assign::<Config>(&config, &[(&key, &value)], false)?;
+        client
+            .update_config_at_revision(config.clone(), revision)
+            .await?;
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&config)?);
+    } else {
+        print!("{}", toml::to_string_pretty(&config)?);
+    }
+    Ok(())
+}
+
+pub async fn diff(left: Option<String>, right: Option<String>, json: bool) -> CliResult {
+    let client = ControlClient::new().await?;
+
+    let (Some(left), Some(right)) = (left, right) else {
+        let diff = client.get_live_diff().await?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+            return Ok(());
+        }
+
+        for difference in &diff.differences {
+            println!(
+                "[{}] {}: {} -> {}",
+                difference.group,
+                difference.path.display(),
+                difference.live.red(),
+                difference.intended.green()
+            );
+        }
+        println!(
+            "{} of {} attributes written by {} hold a different value, {} operations can't be verified",
+            diff.differences.len(),
+            diff.differences.len() + diff.matching,
+            diff.profile_name.bold(),
+            diff.unverifiable.len()
+        );
+        return Ok(());
+    };
+
+    let diff = client.get_profile_diff(left, right).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    let describe = |value: &Option<String>| value.clone().unwrap_or_else(|| "unset".to_string());
+    for difference in &diff.differences {
+        println!(
+            "{}.{}: {} -> {}",
+            difference.group,
+            difference.field,
+            describe(&difference.left).red(),
+            describe(&difference.right).green()
+        );
+    }
+    if diff.is_empty() {
+        println!(
+            "{} and {} apply the same settings",
+            diff.left.bold(),
+            diff.right.bold()
+        );
+    }
+    Ok(())
+}
+
+pub async fn drift(json: bool) -> CliResult {
+    let status = ControlClient::new().await?.get_drift_status().await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+
+    println!(
+        "{} of {} checks found changed values, settings were applied again {} times",
+        status.drifted_checks, status.checks, status.reapplied
+    );
+    for (path, count) in &status.attributes {
+        println!("  {}: {count}", path.display());
+    }
+    Ok(())
+}
+
+pub fn validate(paths: Vec<PathBuf>, json: bool, root: &SystemRoot) -> CliResult {
+    let system_info = SystemInfo::obtain(root);
+
+    let mut results: BTreeMap<PathBuf, Result<Vec<ValidationIssue>, String>> = BTreeMap::new();
+    for path in paths {
+        let result = fs::read_to_string(&path)
+            .map_err(|error| error.to_string())
+            .and_then(|content| toml::from_str::<Profile>(&content).map_err(|e| e.to_string()))
+            .map(|profile| profile.validate(&system_info));
+        results.insert(path, result);
+    }
+
+    if json {
+        let results: BTreeMap<_, _> = results
+            .iter()
+            .map(|(path, result)| {
+                let value = match result {
+                    Ok(issues) => json!({ "issues": issues }),
+                    Err(error) => json!({ "error": error }),
+                };
+                (path.display().to_string(), value)
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        for (path, result) in &results {
+            match result {
+                Ok(issues) if issues.is_empty() => {
+                    println!("{}: {}", path.display(), "ok".green())
+                }
+                Ok(issues) => {
+                    println!("{}: {}", path.display(), "invalid".red());
+                    for issue in issues {
+                        println!("  {issue}");
+                    }
+                }
+                Err(error) => {
+                    println!("{}: {}", path.display(), "unreadable".red());
+                    println!("  {}", error.trim());
+                }
+            }
+        }
+    }
+
+    let invalid = results
+        .values()
+        .filter(|result| !result.as_ref().is_ok_and(Vec::is_empty))
+        .count();
+    if invalid != 0 {
+        return Err(format!("{invalid} of {} profiles are invalid", results.len()).into());
+    }
+    Ok(())
+}
+
+pub async fn bundle(command: BundleCommand, json: bool) -> CliResult {
+    let client = ControlClient::new().await?;
+    match command {
+        BundleCommand::Export {
+            profiles,
+            with_config,
+            output,
+        } => {
+            let bundle = client.export_bundle(&profiles, with_config).await?;
+            let content = if json {
+                serde_json::to_string_pretty(&bundle)?
+            } else {
+                bundle.to_toml()
+            };
+
+            match output {
+                Some(path) => fs::write(path, content)?,
+                None => print!("{content}"),
+            }
+        }
+        BundleCommand::Import { path } => {
+            let content = fs::read_to_string(&path)?;
+            // Bundles exported with --json are accepted too
+            let bundle = Bundle::from_toml(&content)
+                .or_else(|error| serde_json::from_str(&content).map_err(|_| error))
+                .map_err(|error| format!("Could not parse bundle: {error}"))?;
+
+            let report = client.import_bundle(&bundle).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_import_report(&report);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_import_report(report: &ImportReport) {
+    for imported in &report.imported {
+        if imported.name == imported.bundled_name {
+            println!("Imported {}", imported.name.bold());
+        } else {
+            println!(
+                "Imported {} as {}",
+                imported.bundled_name,
+                imported.name.bold()
+            );
+        }
+    }
+    if report.assignments_applied {
+        println!("Applied the AC and battery profiles and rules of the bundle");
+    }
+
+    if !report.hardware_differences.is_empty() {
+        println!(
+            "{}",
+            "The bundle was exported from different hardware:".yellow()
+        );
+        for difference in &report.hardware_differences {
+            println!("  {difference}");
+        }
+    }
+    if !report.unsupported_settings.is_empty() {
+        println!("{}", "Settings unsupported on this system:".yellow());
+        for setting in &report.unsupported_settings {
+            println!(
+                "  {}: {}.{}: {}",
+                setting.profile_name, setting.group, setting.field, setting.reason
+            );
+        }
+    }
+}
+
+/// Renames the profile that was just created if a name was given
+async fn name_created_profile(
+    client: &ControlClient,
+    id: &str,
+    name: Option<String>,
+    json: bool,
+) -> CliResult {
+    let profiles_info = client.get_profiles_info().await?;
+    let Some(profile) = profiles_info.profiles.iter().find(|p| p.id == id) else {
+        return Err(format!("Created profile {id} is gone").into());
+    };
+    let mut created = profile.profile_name.clone();
+    if let Some(name) = name {
+        client
+            .update_profile_name_by_id(id, name.clone(), Some(profiles_info.revision_of(id)))
+            .await?;
+        created = name;
+    }
+
+    if json {
+        println!("{}", json!({ "id": id, "profile_name": created }));
+    } else {
+        println!("Created profile {}", created.bold());
+    }
+    Ok(())
+}
+
+async fn print_profiles_list(client: &ControlClient, json: bool) -> CliResult {
+    let profiles_info = client.get_profiles_info().await?;
+    let active_profile = &profiles_info.get_active_profile().profile_name;
+    let names: Vec<_> = profiles_info
+        .profiles
+        .iter()
+        .map(|p| &p.profile_name)
+        .collect();
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&json!({
+                "active_profile": active_profile,
+                "profiles": names,
+            }))?
+        );
+        return Ok(());
+    }
+
+    for name in names {
+        if name == active_profile {
+            println!("* {}", name.bold());
+        } else {
+            println!("  {name}");
+        }
+    }
+    Ok(())
+}
+
+fn find_profile(profiles_info: &ProfilesInfo, name: &str) -> Result<usize, Box<dyn Error>> {
+    profiles_info
+        .try_find_profile_index_by_name(name)
+        .ok_or_else(|| format!("No profile named {name}").into())
+}
+
+/// The id of the profile along with its current revision
+fn find_profile_id(
+    profiles_info: &ProfilesInfo,
+    name: &str,
+) -> Result<(String, u64), Box<dyn Error>> {
+    let id = &profiles_info.profiles[find_profile(profiles_info, name)?].id;
+    Ok((id.clone(), profiles_info.revision_of(id)))
+}
+
+fn describe_override(temporary_override: Option<&TemporaryOverride>) -> String {
+    match temporary_override {
+        Some(temporary_override) => match temporary_override.remaining() {
+            Some(remaining) => format!(
+                "Overridden to {} for {} more minutes",
+                temporary_override.profile_name.bold(),
+                remaining.as_secs().div_ceil(60)
+            ),
+            None => format!(
+                "Overridden to {} until the daemon restarts",
+                temporary_override.profile_name.bold()
+            ),
+        },
+        None => "No temporary override".to_string(),
+    }
+}
+
+/// Returns a copy of `target` with every `(path, value)` assignment applied.
+/// Paths are dotted keys in snake or kebab case, values are parsed as TOML
+/// and fall back to plain strings, and empty values unset the key. With
+/// `settings_groups` the first key may leave out the `_settings` suffix.
+fn assign<T: Serialize + DeserializeOwned>(
+    target: &T,
+    assignments: &[(&str, &str)],
+    settings_groups: bool,
+) -> Result<T, Box<dyn Error>> {
+    let mut root = toml::Value::try_from(target)?;
+
+    let mut assigned_paths = Vec::new();
+    for (path, raw) in assignments {
+        let mut keys: Vec<String> = path.split('.').map(|k| k.replace('-', "_")).collect();
+        if settings_groups && keys.len() > 1 && !keys[0].ends_with("_settings") {
+            keys[0] = format!("{}_settings", keys[0]);
+        }
+
+        let (last, parents) = keys.split_last().expect("split always yields a key");
+        let mut table = root.as_table_mut().expect("settings serialize to a table");
+        for key in parents {
+            table = table
+                .get_mut(key)
+                .and_then(toml::Value::as_table_mut)
+                .ok_or_else(|| format!("Unknown setting {path}"))?;
+        }
+
+        if raw.is_empty() {
+            table.remove(last);
+        } else {
+            table.insert(last.clone(), parse_value(raw));
+            assigned_paths.push((path, keys));
+        }
+    }
+
+    let updated: T = root
+        .try_into()
+        .map_err(|error| format!("Invalid value: {error}"))?;
+
+    // Unknown keys are silently dropped when deserializing, so they are
+    // caught by looking for them again
+    let check = toml::Value::try_from(&updated)?;
+    for (path, keys) in assigned_paths {
+        if keys
+            .iter()
+            .try_fold(&check, |value, key| value.get(key))
+            .is_none()
+        {
+            return Err(format!("Unknown setting {path}").into());
+        }
+    }
+
+    Ok(updated)
+}
+
+fn parse_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use power_daemon::profiles_generator::create_empty;
+
+    use super::*;
+
+    fn assign_profile(assignments: &[(&str, &str)]) -> Result<Profile, Box<dyn Error>> {
+        assign(&create_empty("quiet", "Quiet"), assignments, true)
+    }
+
+    #[test]
+    fn groups_may_leave_out_the_settings_suffix() {
+        let profile = assign_profile(&[
+            ("cpu.governor", "powersave"),
+            ("cpu_settings.max_freq", "2000"),
+        ])
+        .unwrap();
+
+        assert_eq!(profile.cpu_settings.governor.as_deref(), Some("powersave"));
+        assert_eq!(profile.cpu_settings.max_freq, Some(2000));
+    }
+
+    #[test]
+    fn kebab_and_snake_case_keys_are_the_same() {
+        let kebab = assign_profile(&[("cpu.max-freq", "2000")]).unwrap();
+        let snake = assign_profile(&[("cpu.max_freq", "2000")]).unwrap();
+
+        assert_eq!(kebab.cpu_settings.max_freq, Some(2000));
+        assert_eq!(kebab, snake);
+    }
+
+    #[test]
+    fn empty_values_unset_the_key() {
+        let mut profile = create_empty("quiet", "Quiet");
+        profile.cpu_settings.governor = Some("powersave".to_string());
+
+        let updated = assign(&profile, &[("cpu.governor", "")], true).unwrap();
+
+        assert_eq!(updated.cpu_settings.governor, None);
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        for path in ["cpu.turbo", "turbo.enabled", "cpu.governor.mode"] {
+            let error = assign_profile(&[(path, "1")]).unwrap_err();
+            assert_eq!(error.to_string(), format!("Unknown setting {path}"));
+        }
+    }
+
+    #[test]
+    fn values_that_are_not_toml_are_strings() {
+        assert_eq!(
+            parse_value("balance_power"),
+            toml::Value::String("balance_power".to_string())
+        );
+        assert_eq!(
+            parse_value("\"quoted\""),
+            toml::Value::String("quoted".to_string())
+        );
+        assert_eq!(
+            parse_value("[1, 2"),
+            toml::Value::String("[1, 2".to_string())
+        );
+
+        let error = assign_profile(&[("cpu.max-freq", "fast")]).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid value"));
+    }
+}
@@ -1,20 +1,41 @@
+mod cli;
 mod setup;
 
+use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::{Parser, Subcommand};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 
 use colored::Colorize;
-use log::{error, Level, Log, Metadata, Record};
+use log::{debug, error, info, Level, Log, Metadata, Record};
 use nix::unistd::Uid;
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::UnboundedReceiver,
+    time::{Instant, Interval},
+};
 
-use power_daemon::{communication::client::ControlClient, ReducedUpdate};
+use power_daemon::{
+    communication::{client::ControlClient, ControlError},
+    ReducedUpdate,
+};
 
-use power_daemon::{Instance, SystemInfo};
+use power_daemon::{
+    persistence, BaselineRecorder, Config, GroupReport, Instance, Profile, SettingOutcome,
+    SystemInfo, SystemRoot,
+};
 
+use cli::{
+    BundleCommand, CliResult, ConfigCommand, OverrideCommand, ProfileCommand, ProfilesCommand,
+};
 use power_daemon::communication::server::CommunicationServer;
+use power_daemon::{
+    file_watcher::spawn_file_watcher,
+    uevent::{spawn_uevent_monitor, NetlinkUEventSource, UEvent},
+};
 use setup::{generate_base_files, setup};
 
 #[derive(Parser, Debug)]
@@ -22,6 +43,9 @@ use setup::{generate_base_files, setup};
 struct Args {
     #[command(flatten)]
     verbose: Verbosity<InfoLevel>,
+    /// Resolve all sysfs and procfs paths against this directory instead of /
+    #[arg(long, global = true)]
+    system_root: Option<PathBuf>,
     #[command(subcommand)]
     mode: OpMode,
 }
@@ -38,12 +62,92 @@ enum OpMode {
         /// Make sure the daemon starts with maximum verbosity
         #[arg(long, action=clap::ArgAction::SetTrue)]
         verbose_daemon: bool,
+        /// Also generate an acpid rule that refreshes the daemon on AC adapter
+        /// events. The daemon already follows power supply uevents by itself,
+        /// this is only a fallback for when it can't subscribe to them.
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        acpi_events: bool,
     },
     Daemon,
     RefreshFull,
     RefreshUSB,
     RefreshPCI,
     PrintSystemInfo,
+    /// Write back the values the system had before power-options changed them
+    RestoreBaseline,
+    /// Print the operations applying a profile would perform, without applying it
+    Plan {
+        /// Name of a profile known to the daemon or path to a profile file
+        #[arg(long)]
+        profile: String,
+        /// Print the plan as JSON
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        json: bool,
+    },
+    Profiles {
+        /// Print the result as JSON
+        #[arg(long, global = true, action=clap::ArgAction::SetTrue)]
+        json: bool,
+        #[command(subcommand)]
+        command: ProfilesCommand,
+    },
+    Profile {
+        /// Print the result as JSON
+        #[arg(long, global = true, action=clap::ArgAction::SetTrue)]
+        json: bool,
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Manage the temporary override, which takes precedence over every other
+    /// way of picking the profile
+    Override {
+        /// Print the result as JSON
+        #[arg(long, global = true, action=clap::ArgAction::SetTrue)]
+        json: bool,
+        #[command(subcommand)]
+        command: OverrideCommand,
+    },
+    Config {
+        /// Print the result as JSON
+        #[arg(long, global = true, action=clap::ArgAction::SetTrue)]
+        json: bool,
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Compare the settings of two profiles, or without profiles, the active
+    /// profile with the values the system holds right now
+    Diff {
+        #[arg(requires = "right")]
+        left: Option<String>,
+        right: Option<String>,
+        /// Print the differences as JSON
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Print how often values of the active profile were found changed by
+    /// other software, see `drift_detection` in the config
+    Drift {
+        /// Print the counts as JSON
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Check profile files against the hardware of this system, without a
+    /// running daemon. Fails if any value doesn't fit.
+    Validate {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Print the issues of every file as JSON
+        #[arg(long, action=clap::ArgAction::SetTrue)]
+        json: bool,
+    },
+    /// Share profiles between machines
+    Bundle {
+        /// Export and print the import report as JSON
+        #[arg(long, global = true, action=clap::ArgAction::SetTrue)]
+        json: bool,
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
 }
 
 static LOGGER: StdoutLogger = StdoutLogger;
@@ -79,6 +183,7 @@ impl Log for StdoutLogger {
 
 pub const CONFIG_FILE: &str = "/etc/power-options/config.toml";
 pub const PROFILES_DIRECTORY: &str = "/etc/power-options/profiles";
+pub const BASELINE_FILE: &str = "/var/lib/power-options/baseline.toml";
 
 #[tokio::main]
 async fn main() {
@@ -87,14 +192,17 @@ async fn main() {
     log::set_logger(&LOGGER).expect("Could not set logger");
     log::set_max_level(args.verbose.log_level_filter());
 
+    let root = args.system_root.map(SystemRoot::new).unwrap_or_default();
+
     match args.mode {
-        OpMode::Setup => setup(),
-        OpMode::Daemon => daemon().await,
+        OpMode::Setup => setup(&root),
+        OpMode::Daemon => daemon(root).await,
         OpMode::GenerateBaseFiles {
             path,
             program_path,
             verbose_daemon,
-        } => generate_base_files(path, program_path, verbose_daemon),
+            acpi_events,
+        } => generate_base_files(path, program_path, verbose_daemon, acpi_events),
         OpMode::RefreshFull => refresh_full().await,
         OpMode::RefreshUSB => refresh_reduced(ReducedUpdate::USB).await,
         OpMode::RefreshPCI => {
@@ -102,12 +210,32 @@ async fn main() {
             refresh_reduced(ReducedUpdate::ASPM).await;
         }
         OpMode::PrintSystemInfo => {
-            println!("{:#?}", SystemInfo::obtain());
+            println!("{:#?}", SystemInfo::obtain(&root));
+        }
+        OpMode::RestoreBaseline => restore_baseline(&root).await,
+        OpMode::Plan { profile, json } => plan(profile, json, &root).await,
+        OpMode::Profiles { json, command } => exit_on_error(cli::profiles(command, json).await),
+        OpMode::Profile { json, command } => exit_on_error(cli::profile(command, json).await),
+        OpMode::Override { json, command } => {
+            exit_on_error(cli::profile_override(command, json).await)
         }
+        OpMode::Config { json, command } => exit_on_error(cli::config(command, json).await),
+        OpMode::Diff { left, right, json } => exit_on_error(cli::diff(left, right, json).await),
+        OpMode::Drift { json } => exit_on_error(cli::drift(json).await),
+        OpMode::Validate { paths, json } => exit_on_error(cli::validate(paths, json, &root)),
+        OpMode::Bundle { json, command } => exit_on_error(cli::bundle(command, json).await),
     }
 }
 
-async fn daemon() {
+/// Lets scripts tell whether a client subcommand failed
+fn exit_on_error(result: CliResult) {
+    if let Err(error) = result {
+        error!("{error}");
+        std::process::exit(1);
+    }
+}
+
+async fn daemon(root: SystemRoot) {
     // From now on, we are the daemon
     proctitle::set_title("power-daemon");
 
@@ -118,21 +246,201 @@ async fn daemon() {
 
     let config_path = Path::new(CONFIG_FILE);
     let profiles_path = Path::new(PROFILES_DIRECTORY);
+    let baseline_path = Path::new(BASELINE_FILE);
 
-    let config = power_daemon::parse_config(config_path);
-    let mut handle = Instance::new(config, config_path, profiles_path);
+    // Renames and removals of profiles a crash interrupted are finished
+    // before anything is read
+    if let Err(error) = persistence::recover(&persistence::journal_path(config_path)) {
+        error!("Could not finish interrupted file operations: {error}");
+    }
+
+    // A broken config shouldn't keep the daemon from starting, clients can
+    // ask it why the defaults are used instead
+    let (config, config_error) = match power_daemon::parse_config(config_path) {
+        Ok(config) => (config, None),
+        Err(error) => {
+            error!("{error}, using the default config");
+            (Config::create_default(), Some(error))
+        }
+    };
+    let drift_detection = config.drift_detection.clone();
+    let mut handle = Instance::new(config, config_path, profiles_path, baseline_path, root);
+    if let Some(error) = config_error {
+        handle.record_load_error(error);
+    }
 
     handle.update_full();
 
-    let _com_server = CommunicationServer::new(handle)
+    let com_server = CommunicationServer::new(handle)
         .await
         .expect("Could not initialize communications server");
 
+    // Power supply events cover both AC/battery switches and battery level
+    // changes, drm events cover displays being plugged in. Without the monitor
+    // the power source can still be followed through the optional acpid rule.
+    let mut uevents = match NetlinkUEventSource::new() {
+        Ok(source) => Some(spawn_uevent_monitor(source, &["power_supply", "drm"])),
+        Err(error) => {
+            error!("Could not subscribe to uevents, AC/battery changes won't be detected: {error}");
+            None
+        }
+    };
+
+    // Changes to the config or profiles made by hand, the daemon's own writes
+    // read back the same and don't cause a reload
+    let mut file_changes = match spawn_file_watcher(&[
+        config_path.parent().unwrap_or(Path::new("/")),
+        profiles_path,
+    ]) {
+        Ok(changes) => Some(changes),
+        Err(error) => {
+            error!("Could not watch for changes to the config and profiles, send SIGHUP to reload them: {error}");
+            None
+        }
+    };
+    // Editors and the daemon itself touch several files at once, so changed
+    // files are collected until the edits settle. Kept out of the select
+    // below, which drops whatever branches that didn't fire were doing.
+    let mut changed_files = Vec::new();
+    let mut reload_at = None;
+
+    // Time windows and the lid state in profile rules have no events to
+    // follow, so profiles are also picked again periodically
+    let mut rules_interval = tokio::time::interval(Duration::from_secs(60));
+    // Same goes for processes starting and exiting
+    let mut process_rules_interval = tokio::time::interval(Duration::from_secs(5));
+    let mut override_expiry_interval = tokio::time::interval(Duration::from_secs(5));
+    let mut drift_interval = drift_detection
+        .enabled
+        .then(|| tokio::time::interval(Duration::from_secs(drift_detection.interval.max(1))));
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("Could not listen for SIGTERM");
+    let mut sigint = signal(SignalKind::interrupt()).expect("Could not listen for SIGINT");
+    let mut sighup = signal(SignalKind::hangup()).expect("Could not listen for SIGHUP");
     loop {
-        std::thread::park();
+        tokio::select! {
+            _ = sigterm.recv() => {
+                info!("Received SIGTERM, shutting down");
+                break;
+            }
+            _ = sigint.recv() => {
+                info!("Received SIGINT, shutting down");
+                break;
+            }
+            _ = sighup.recv() => {
+                info!("Received SIGHUP, reloading config and profiles");
+                if let Err(error) = com_server.reload_files().await {
+                    error!("Could not reload config and profiles: {error}");
+                }
+            }
+            changed = recv_file_change(&mut file_changes) => {
+                let Some(path) = changed else {
+                    file_changes = None;
+                    continue;
+                };
+                debug!("{} changed", path.display());
+                changed_files.push(path);
+                reload_at.get_or_insert_with(|| Instant::now() + Duration::from_millis(500));
+            }
+            _ = sleep_until(reload_at) => {
+                reload_at = None;
+                let paths = std::mem::take(&mut changed_files);
+                if let Err(error) = com_server.reload_changed_files(&paths).await {
+                    error!("Could not reload config and profiles: {error}");
+                }
+            }
+            event = recv_uevent(&mut uevents) => {
+                if event.is_none() {
+                    uevents = None;
+                    continue;
+                }
+                if let Err(error) = com_server.update_if_profile_changed().await {
+                    error!("Could not pick profile after uevent: {error}");
+                }
+            }
+            _ = rules_interval.tick() => {
+                if let Err(error) = com_server.update_if_profile_changed().await {
+                    error!("Could not pick profile: {error}");
+                }
+            }
+            _ = process_rules_interval.tick() => {
+                if let Err(error) = com_server.update_if_automatic_override_changed().await {
+                    error!("Could not evaluate process rules: {error}");
+                }
+            }
+            _ = override_expiry_interval.tick() => {
+                if let Err(error) = com_server.remove_expired_profile_override().await {
+                    error!("Could not check profile override expiration: {error}");
+                }
+            }
+            _ = tick(&mut drift_interval) => {
+                if let Err(error) = com_server.check_drift().await {
+                    error!("Could not check for changed values: {error}");
+                }
+            }
+        }
+    }
+
+    match com_server.restore_baseline().await {
+        Ok(report) => print_unsuccessful_settings(&[report]),
+        Err(error) => error!("Could not restore baseline: {error}"),
     }
 }
 
+async fn recv_uevent(uevents: &mut Option<UnboundedReceiver<UEvent>>) -> Option<UEvent> {
+    match uevents {
+        Some(uevents) => uevents.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns None once the watcher stopped
+async fn recv_file_change(changes: &mut Option<UnboundedReceiver<PathBuf>>) -> Option<PathBuf> {
+    match changes {
+        Some(changes) => changes.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn restore_baseline(root: &SystemRoot) {
+    // If the daemon is running it has to be the one restoring, as it keeps
+    // track of the baseline in memory
+    let from_daemon = match ControlClient::new().await {
+        Ok(client) => client.restore_baseline().await.ok(),
+        Err(_) => None,
+    };
+
+    let report = match from_daemon {
+        Some(report) => report,
+        None => {
+            if !Uid::effective().is_root() {
+                error!("Daemon is not running, root priviliges required to restore the baseline");
+                return;
+            }
+            BaselineRecorder::load(Path::new(BASELINE_FILE), root.clone()).restore()
+        }
+    };
+
+    println!("Restored {} values", report.settings.len());
+    print_unsuccessful_settings(&[report]);
+}
+
 async fn refresh_full() {
     let client = ControlClient::new()
         .await
@@ -143,6 +451,69 @@ async fn refresh_full() {
         .expect("Could not reset reducedu update");
 }
 
+async fn plan(profile: String, json: bool, root: &SystemRoot) {
+    let profile_path = Path::new(&profile);
+
+    let plan = if profile_path.is_file() {
+        let content = fs::read_to_string(profile_path).expect("Could not read profile file");
+        let profile: Profile = match toml::from_str(&content) {
+            Ok(profile) => profile,
+            Err(error) => {
+                error!("Could not parse profile file: {error}");
+                return;
+            }
+        };
+        profile.plan(root)
+    } else {
+        let client = ControlClient::new()
+            .await
+            .expect("Could not intialize control client");
+        match client.get_profile_plan(profile.clone()).await {
+            Ok(plan) => plan,
+            Err(ControlError::ProfileNotFound(_)) => {
+                error!("No profile named {profile} and no profile file at that path");
+                return;
+            }
+            Err(error) => panic!("Could not obtain profile plan: {error}"),
+        }
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&plan).expect("Could not serialize plan")
+        );
+        return;
+    }
+
+    println!("Plan for profile {}:", plan.profile_name.bold());
+    for planned in &plan.operations {
+        println!("  [{}] {}", planned.group, planned.operation);
+    }
+
+    print_unsuccessful_settings(&plan.report.groups);
+}
+
+fn print_unsuccessful_settings(groups: &[GroupReport]) {
+    let unsuccessful: Vec<_> = groups
+        .iter()
+        .flat_map(|g| g.settings.iter().map(move |s| (&g.group, s)))
+        .filter_map(|(group, setting)| match setting.outcome {
+            SettingOutcome::Unsupported(ref reason) | SettingOutcome::Failed(ref reason) => {
+                Some((group, &setting.field, reason))
+            }
+            _ => None,
+        })
+        .collect();
+
+    if !unsuccessful.is_empty() {
+        println!("{}", "Unsuccessful settings:".yellow());
+        for (group, field, reason) in unsuccessful {
+            println!("  {group}.{field}: {reason}");
+        }
+    }
+}
+
 async fn refresh_reduced(reduced_update: ReducedUpdate) {
     let client = ControlClient::new()
         .await
@@ -5,9 +5,12 @@ use std::{
 
 use log::{debug, error, trace};
 use nix::unistd::Uid;
-use power_daemon::{profiles_generator, Config, DefaultProfileType, SystemInfo};
+use power_daemon::{
+    communication::authorization::Action, profiles_generator, Config, DefaultProfileType,
+    SystemInfo, SystemRoot,
+};
 
-pub fn setup() {
+pub fn setup(root: &SystemRoot) {
     if !Uid::effective().is_root() {
         error!("Root priviliges required");
         return;
@@ -22,22 +25,30 @@ pub fn setup() {
     ]).spawn().expect("Could not spawn popup").wait().expect("Could not wait from popup").success();
 
     if agreed {
-        generate_config_files("/".into());
+        generate_config_files("/".into(), root);
     } else {
         generate_empty_config_files("/".into());
     }
 }
 
-pub fn generate_base_files(path: PathBuf, program_path: PathBuf, verbose_daemon: bool) {
+pub fn generate_base_files(
+    path: PathBuf,
+    program_path: PathBuf,
+    verbose_daemon: bool,
+    acpi_events: bool,
+) {
     generate_udev_file(&path, &program_path);
-    generate_acpi_file(&path, &program_path);
+    if acpi_events {
+        generate_acpi_file(&path, &program_path);
+    }
     generate_dbus_file(&path);
+    generate_polkit_file(&path);
     genereate_systemd_file(&path, &program_path, verbose_daemon);
 }
 
-fn generate_config_files(path: PathBuf) {
+fn generate_config_files(path: PathBuf, root: &SystemRoot) {
     create_config(&path, &Config::create_default());
-    generate_profiles(&path);
+    generate_profiles(&path, root);
 }
 
 fn generate_empty_config_files(path: PathBuf) {
@@ -57,14 +68,14 @@ fn create_config(path: &Path, config: &Config) {
     fs::write(dir.join("config.toml"), content).expect("Could not write to file");
 }
 
-fn generate_profiles(path: &Path) {
+fn generate_profiles(path: &Path, root: &SystemRoot) {
     debug!("Creating default profiles");
 
     let dir = path.join("etc/power-options/profiles/");
 
     fs::create_dir_all(&dir).expect("Could not create directory");
 
-    let system_info = SystemInfo::obtain();
+    let system_info = SystemInfo::obtain(root);
 
     power_daemon::profiles_generator::create_profile_file(
         &dir,
@@ -100,7 +111,7 @@ fn generate_empty_profile(path: &Path) {
 
     fs::create_dir_all(&dir).expect("Could not create directory");
 
-    profiles_generator::create_empty_profile_file_with_name(dir, "Default");
+    profiles_generator::create_empty_profile_file_with_name(dir, "default", "Default");
 }
 
 fn generate_udev_file(path: &Path, program_path: &Path) {
@@ -158,10 +169,14 @@ fn generate_dbus_file(path: &Path) {
     <allow own="io.github.thealexdev23.power_daemon"/>
     <allow send_destination="io.github.thealexdev23.power_daemon"/>
     <allow send_interface="io.github.thealexdev23.power_daemon.system_info"/>
+    <allow own="net.hadess.PowerProfiles"/>
+    <allow own="org.freedesktop.UPower.PowerProfiles"/>
   </policy>
 
   <policy context="default">
     <allow send_destination="io.github.thealexdev23.power_daemon"/>
+    <allow send_destination="net.hadess.PowerProfiles"/>
+    <allow send_destination="org.freedesktop.UPower.PowerProfiles"/>
   </policy>
 </busconfig>
 "#;
@@ -171,6 +186,55 @@ fn generate_dbus_file(path: &Path) {
     fs::write(dir.join("power-daemon.conf"), content).expect("Could not write to file");
 }
 
+fn generate_polkit_file(path: &Path) {
+    debug!("Generating polkit file");
+
+    let dir = path.join("usr/share/polkit-1/actions/");
+    fs::create_dir_all(&dir).expect("Could not create directory");
+
+    let mut actions = String::new();
+    for action in Action::ALL {
+        let [allow_any, allow_inactive, allow_active] = action.defaults();
+        actions += &format!(
+            r#"
+  <action id="{}">
+    <description>{}</description>
+    <message>{}</message>
+    <defaults>
+      <allow_any>{allow_any}</allow_any>
+      <allow_inactive>{allow_inactive}</allow_inactive>
+      <allow_active>{allow_active}</allow_active>
+    </defaults>
+  </action>
+"#,
+            action.id(),
+            action.description(),
+            action.message(),
+        );
+    }
+
+    let content = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- Actions the power-options daemon checks before serving requests. Defaults can be overriden with polkit rules. -->
+
+<!DOCTYPE policyconfig PUBLIC "-//freedesktop//DTD PolicyKit Policy Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/PolicyKit/1/policyconfig.dtd">
+<policyconfig>
+  <vendor>power-options</vendor>
+  <vendor_url>https://github.com/TheAlexDev23/power-options</vendor_url>
+{actions}</policyconfig>
+"#
+    );
+
+    trace!("{content}");
+
+    fs::write(
+        dir.join("io.github.thealexdev23.power_daemon.policy"),
+        content,
+    )
+    .expect("Could not write to file");
+}
+
 fn genereate_systemd_file(path: &Path, program_path: &Path, verbose_daemon: bool) {
     debug!("Generating systemd file");
 
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sysfs::writing::WriteError;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum SettingOutcome {
+    Applied,
+    /// The setting is not set in the profile so it was left untouched
+    Skipped,
+    /// The system lacks the hardware, driver or program this setting needs
+    Unsupported(String),
+    Failed(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SettingReport {
+    /// Name of the profile field, per-core fields are prefixed with the core
+    /// id, e.g. `cpu3.governor`
+    pub field: String,
+    pub outcome: SettingOutcome,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GroupReport {
+    /// Name of the settings group as it appears in the profile, e.g. `cpu_settings`
+    pub group: String,
+    pub settings: Vec<SettingReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ApplyReport {
+    pub profile_name: String,
+    pub groups: Vec<GroupReport>,
+}
+
+impl ApplyReport {
+    pub fn new(profile_name: &str) -> ApplyReport {
+        ApplyReport {
+            profile_name: profile_name.to_string(),
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.groups.iter().any(GroupReport::has_failures)
+    }
+}
+
+impl GroupReport {
+    pub fn new(group: &str) -> GroupReport {
+        GroupReport {
+            group: group.to_string(),
+            settings: Vec::new(),
+        }
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.settings
+            .iter()
+            .any(|s| matches!(s.outcome, SettingOutcome::Failed(_)))
+    }
+
+    pub fn applied(&mut self, field: &str) {
+        self.push(field, SettingOutcome::Applied);
+    }
+    pub fn skipped(&mut self, field: &str) {
+        self.push(field, SettingOutcome::Skipped);
+    }
+    pub fn unsupported(&mut self, field: &str, reason: impl Into<String>) {
+        self.push(field, SettingOutcome::Unsupported(reason.into()));
+    }
+    pub fn failed(&mut self, field: &str, reason: impl Into<String>) {
+        self.push(field, SettingOutcome::Failed(reason.into()));
+    }
+
+    /// Records the outcome of a single sysfs write. A missing attribute is
+    /// considered unsupported rather than a failure.
+    pub fn record_write(&mut self, field: &str, result: Result<(), WriteError>) {
+        self.record_writes(field, [result]);
+    }
+
+    /// Records the combined outcome of writing the same setting into multiple
+    /// attributes (e.g. every CPU core or every PCI device)
+    pub fn record_writes(
+        &mut self,
+        field: &str,
+        results: impl IntoIterator<Item = Result<(), WriteError>>,
+    ) {
+        let mut any_written = false;
+        let mut missing = Vec::new();
+        let mut failures = Vec::new();
+
+        for result in results {
+            match result {
+                Ok(()) => any_written = true,
+                Err(WriteError::NotFound(path)) => missing.push(path.display().to_string()),
+                Err(error) => failures.push(error.to_string()),
+            }
+        }
+
+        if !failures.is_empty() {
+            self.failed(field, failures.join("; "));
+        } else if !any_written && !missing.is_empty() {
+            self.unsupported(field, format!("{} does not exist", missing.join(", ")));
+        } else {
+            self.applied(field);
+        }
+    }
+
+    pub fn record_command(&mut self, field: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.applied(field),
+            Err(reason) => self.failed(field, reason),
+        }
+    }
+
+    pub fn append(&mut self, other: GroupReport) {
+        self.settings.extend(other.settings);
+    }
+
+    fn push(&mut self, field: &str, outcome: SettingOutcome) {
+        self.settings.push(SettingReport {
+            field: field.to_string(),
+            outcome,
+        });
+    }
+}
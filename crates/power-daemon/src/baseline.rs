@@ -0,0 +1,221 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Child,
+    sync::Mutex,
+};
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apply_report::GroupReport,
+    executor::{Executor, SystemExecutor},
+    persistence::write_atomically,
+    sysfs::{root::SystemRoot, writing::WriteError},
+};
+
+/// Values every attribute had before the daemon first wrote into it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Baseline {
+    pub values: BTreeMap<PathBuf, String>,
+}
+
+/// Executor that applies everything onto the system, but first remembers the
+/// value of every attribute it hasn't touched before so it can be restored
+/// later. The snapshot is persisted so the original values survive restarts of
+/// the daemon.
+///
+/// Only attribute writes are captured, what commands do (loading kernel
+/// modules, xrandr, ifconfig...) can't be snapshotted and isn't restored.
+pub struct BaselineRecorder {
+    path: PathBuf,
+    system: SystemExecutor,
+    state: Mutex<BaselineState>,
+}
+
+#[derive(Default)]
+struct BaselineState {
+    baseline: Baseline,
+    modified: bool,
+}
+
+impl BaselineRecorder {
+    /// Picks up the snapshot stored at the given path if there's one, so
+    /// values captured before a restart are not overwritten with ours
+    pub fn load(path: &Path, root: SystemRoot) -> BaselineRecorder {
+        let baseline = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|error| {
+                error!("Could not parse baseline file, starting a new one: {error}");
+                Baseline::default()
+            }),
+            Err(_) => {
+                debug!("No baseline file found, starting a new one");
+                Baseline::default()
+            }
+        };
+
+        BaselineRecorder {
+            path: PathBuf::from(path),
+            system: SystemExecutor::new(root),
+            state: Mutex::new(BaselineState {
+                baseline,
+                modified: false,
+            }),
+        }
+    }
+
+    /// Writes the snapshot to disk if new attributes were captured since the
+    /// last time it was saved
+    pub fn save(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.modified {
+            return;
+        }
+
+        debug!("Saving baseline to {}", self.path.display());
+
+        if let Some(parent) = self.path.parent() {
+            if let Err(error) = fs::create_dir_all(parent) {
+                error!("Could not create baseline directory: {error}");
+                return;
+            }
+        }
+
+        let content = toml::to_string(&state.baseline).expect("Could not serialize baseline");
+        match write_atomically(&self.path, content) {
+            Ok(()) => state.modified = false,
+            Err(error) => error!("Could not write baseline file: {error}"),
+        }
+    }
+
+    /// Writes every captured value back and forgets about them, so the next
+    /// time something is applied the snapshot starts over
+    pub fn restore(&self) -> GroupReport {
+        let mut state = self.state.lock().unwrap();
+        let values = std::mem::take(&mut state.baseline.values);
+        state.modified = false;
+
+        info!("Restoring {} values from the baseline", values.len());
+        let report = write_values(&self.system, values.into_iter().collect());
+
+        if let Err(error) = fs::remove_file(&self.path) {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                warn!("Could not remove baseline file: {error}");
+            }
+        }
+
+        report
+    }
+
+    /// Writes back the captured value of every attribute that's not in
+    /// `keep` and doesn't already hold it. The snapshot itself is kept.
+    pub fn restore_all_except(&self, keep: &HashSet<&Path>) -> GroupReport {
+        let values: Vec<_> = {
+            let state = self.state.lock().unwrap();
+            state
+                .baseline
+                .values
+                .iter()
+                .filter(|(path, _)| !keep.contains(path.as_path()))
+                // Attributes that are gone, like those of unplugged devices,
+                // are left alone
+                .filter(|(path, value)| {
+                    fs::read_to_string(path)
+                        .map(|content| parse_current_value(&content) != **value)
+                        .unwrap_or(false)
+                })
+                .map(|(path, value)| (path.clone(), value.clone()))
+                .collect()
+        };
+
+        debug!("Restoring {} values from the baseline", values.len());
+        write_values(&self.system, values)
+    }
+
+    fn capture(&self, path: &Path) {
+        let mut state = self.state.lock().unwrap();
+        if state.baseline.values.contains_key(path) {
+            return;
+        }
+
+        // Write-only or missing attributes can't be restored anyway
+        let Ok(content) = fs::read_to_string(path) else {
+            return;
+        };
+
+        state
+            .baseline
+            .values
+            .insert(PathBuf::from(path), parse_current_value(&content));
+        state.modified = true;
+    }
+}
+
+impl Executor for BaselineRecorder {
+    fn root(&self) -> &SystemRoot {
+        self.system.root()
+    }
+
+    fn write(&self, path: &Path, value: &str) -> Result<(), WriteError> {
+        self.capture(path);
+        self.system.write(path, value)
+    }
+
+    fn run_command(&self, command: &str) -> Result<(), String> {
+        self.system.run_command(command)
+    }
+
+    fn run_graphical_command(&self, command: &str) -> Result<(), String> {
+        self.system.run_graphical_command(command)
+    }
+
+    fn spawn_graphical_command(&self, command: &str) -> Option<Child> {
+        self.system.spawn_graphical_command(command)
+    }
+}
+
+fn write_values(system: &SystemExecutor, values: Vec<(PathBuf, String)>) -> GroupReport {
+    // Cores have to be brought back online before anything else, as their
+    // cpufreq attributes don't exist while they are offline
+    let (online, rest): (Vec<_>, Vec<_>) = values
+        .into_iter()
+        .partition(|(path, _)| path.file_name().is_some_and(|n| n == "online"));
+
+    let results: Vec<_> = online
+        .into_iter()
+        .chain(rest)
+        .map(|(path, value)| {
+            let result = system.write(&path, &value);
+            (path, value, result)
+        })
+        .collect();
+
+    // Some values depend on others, e.g. a minimum frequency can't be set
+    // above the current maximum. A second pass takes care of those.
+    let mut report = GroupReport::new("baseline");
+    for (path, value, result) in results {
+        let result = match result {
+            Err(WriteError::NotFound(_)) | Ok(()) => result,
+            Err(_) => system.write(&path, &value),
+        };
+        report.record_write(&path.display().to_string(), result);
+    }
+
+    report
+}
+
+// Attributes that offer a choice, like the ASPM policy, read as every option
+// with the selected one between brackets: "default [powersave] performance"
+pub(crate) fn parse_current_value(content: &str) -> String {
+    let content = content.trim();
+
+    if let (Some(start), Some(end)) = (content.find('['), content.find(']')) {
+        if start < end {
+            return content[start + 1..end].to_string();
+        }
+    }
+
+    content.to_string()
+}
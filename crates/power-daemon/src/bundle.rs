@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    process_rules::ProcessRule, profile_rules::ProfileRule, sysfs::root::SystemRoot, CPUFreqDriver,
+    Profile, SettingOutcome, SystemInfo,
+};
+
+/// One or more profiles packed into a single file, so that they can be moved
+/// between machines
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Bundle {
+    /// Hardware the profiles were exported from
+    pub fingerprint: HardwareFingerprint,
+    /// Which profiles are picked when, only present if requested when
+    /// exporting
+    pub assignments: Option<BundleAssignments>,
+    pub profiles: Vec<Profile>,
+}
+
+impl Bundle {
+    pub fn from_toml(content: &str) -> Result<Bundle, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("Could not serialize bundle")
+    }
+
+    /// Returns a description of the first problem found that would prevent
+    /// importing the bundle
+    pub fn validate(&self) -> Result<(), String> {
+        if self.profiles.is_empty() {
+            return Err("the bundle has no profiles".to_string());
+        }
+
+        for (idx, profile) in self.profiles.iter().enumerate() {
+            let name = &profile.profile_name;
+            if name.is_empty() || name.contains('/') {
+                return Err(format!("{name:?} is not a valid profile name"));
+            }
+            if self.profiles[..idx].iter().any(|p| p.profile_name == *name) {
+                return Err(format!("the bundle has more than one profile named {name}"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The parts of the config that refer to profiles by name
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BundleAssignments {
+    pub ac_profile: String,
+    pub bat_profile: String,
+    pub profile_rules: Vec<ProfileRule>,
+    pub process_rules: Vec<ProcessRule>,
+}
+
+impl BundleAssignments {
+    /// Every profile name the assignments refer to
+    pub fn profile_names(&self) -> Vec<&String> {
+        [&self.ac_profile, &self.bat_profile]
+            .into_iter()
+            .chain(self.profile_rules.iter().map(|r| &r.profile))
+            .chain(self.process_rules.iter().map(|r| &r.profile))
+            .collect()
+    }
+
+    /// Updates every reference to a profile according to `rename`
+    pub fn rename_profiles(&mut self, rename: impl Fn(&str) -> String) {
+        self.ac_profile = rename(&self.ac_profile);
+        self.bat_profile = rename(&self.bat_profile);
+        for rule in &mut self.profile_rules {
+            rule.profile = rename(&rule.profile);
+        }
+        for rule in &mut self.process_rules {
+            rule.profile = rename(&rule.profile);
+        }
+    }
+}
+
+/// What in [`SystemInfo`] decides which settings of a profile are supported
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HardwareFingerprint {
+    pub cpu_driver: CPUFreqDriver,
+    pub cpu_cores: usize,
+    pub hybrid_cpu: bool,
+    /// PCI addresses, sorted
+    pub pci_devices: Vec<String>,
+    /// USB vendor and product ids, sorted
+    pub usb_devices: Vec<String>,
+    pub sata_hosts: u32,
+    pub platform_profiles: Option<Vec<String>>,
+    pub intel_gpu: bool,
+    pub amd_gpu: bool,
+}
+
+impl HardwareFingerprint {
+    pub fn from_system_info(system_info: &SystemInfo) -> HardwareFingerprint {
+        let mut pci_devices: Vec<_> = system_info
+            .pci_info
+            .pci_devices
+            .iter()
+            .map(|d| d.pci_address.clone())
+            .collect();
+        pci_devices.sort();
+
+        let mut usb_devices: Vec<_> = system_info
+            .usb_info
+            .usb_devices
+            .iter()
+            .map(|d| d.id.clone())
+            .collect();
+        usb_devices.sort();
+
+        HardwareFingerprint {
+            cpu_driver: system_info.cpu_info.driver.clone(),
+            cpu_cores: system_info.cpu_info.cores.len(),
+            hybrid_cpu: system_info.cpu_info.hybrid,
+            pci_devices,
+            usb_devices,
+            sata_hosts: system_info.sata_info.hosts,
+            platform_profiles: system_info.firmware_info.platform_profiles.clone(),
+            intel_gpu: system_info.gpu_info.intel_info.is_some(),
+            amd_gpu: system_info.gpu_info.amd_info.is_some(),
+        }
+    }
+
+    /// Describes every way `self` differs from `other`, empty if the hardware
+    /// is the same
+    pub fn differences(&self, other: &HardwareFingerprint) -> Vec<String> {
+        let mut differences = Vec::new();
+        let mut compare = |what: &str, ours: String, theirs: String| {
+            if ours != theirs {
+                differences.push(format!("{what}: {ours} in the bundle, {theirs} here"));
+            }
+        };
+
+        compare(
+            "CPU frequency driver",
+            format!("{:?}", self.cpu_driver),
+            format!("{:?}", other.cpu_driver),
+        );
+        compare(
+            "CPU cores",
+            self.cpu_cores.to_string(),
+            other.cpu_cores.to_string(),
+        );
+        compare(
+            "Hybrid CPU",
+            self.hybrid_cpu.to_string(),
+            other.hybrid_cpu.to_string(),
+        );
+        compare(
+            "PCI devices",
+            self.pci_devices.join(", "),
+            other.pci_devices.join(", "),
+        );
+        compare(
+            "USB devices",
+            self.usb_devices.join(", "),
+            other.usb_devices.join(", "),
+        );
+        compare(
+            "SATA hosts",
+            self.sata_hosts.to_string(),
+            other.sata_hosts.to_string(),
+        );
+        compare(
+            "Platform profiles",
+            format!("{:?}", self.platform_profiles),
+            format!("{:?}", other.platform_profiles),
+        );
+        compare(
+            "Intel GPU",
+            self.intel_gpu.to_string(),
+            other.intel_gpu.to_string(),
+        );
+        compare(
+            "AMD GPU",
+            self.amd_gpu.to_string(),
+            other.amd_gpu.to_string(),
+        );
+
+        differences
+    }
+}
+
+/// Outcome of importing a bundle
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct ImportReport {
+    pub imported: Vec<ImportedProfile>,
+    pub assignments_applied: bool,
+    /// Empty if the bundle was exported from the same hardware
+    pub hardware_differences: Vec<String>,
+    /// Settings of the imported profiles this system doesn't support. Only
+    /// checked when the hardware differs.
+    pub unsupported_settings: Vec<UnsupportedSetting>,
+}
+
+impl ImportReport {
+    /// Short description for frontends, which show it in a single line
+    pub fn summary(&self) -> String {
+        let renamed: Vec<_> = self
+            .imported
+            .iter()
+            .filter(|i| i.name != i.bundled_name)
+            .map(|i| format!("{} as {}", i.bundled_name, i.name))
+            .collect();
+
+        let mut summary = format!("Imported {} profiles", self.imported.len());
+        if !renamed.is_empty() {
+            summary += &format!(" ({})", renamed.join(", "));
+        }
+        if self.assignments_applied {
+            summary += ", along with their AC, battery and rule assignments";
+        }
+        if !self.hardware_differences.is_empty() {
+            summary += &format!(
+                ". The bundle comes from different hardware, {} settings are unsupported here",
+                self.unsupported_settings.len()
+            );
+        }
+        summary
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImportedProfile {
+    pub bundled_name: String,
+    /// Differs from the bundled name if a profile with that name already
+    /// existed
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UnsupportedSetting {
+    pub profile_name: String,
+    pub group: String,
+    pub field: String,
+    pub reason: String,
+}
+
+impl UnsupportedSetting {
+    /// Settings of `profile` that would be unsupported if applied on the
+    /// system under `root`
+    pub fn of_profile(profile: &Profile, root: &SystemRoot) -> Vec<UnsupportedSetting> {
+        profile
+            .plan(root)
+            .report
+            .groups
+            .into_iter()
+            .flat_map(|group| {
+                group
+                    .settings
+                    .into_iter()
+                    .filter_map(move |setting| match setting.outcome {
+                        SettingOutcome::Unsupported(reason) => Some(UnsupportedSetting {
+                            profile_name: profile.profile_name.clone(),
+                            group: group.group.clone(),
+                            field: setting.field,
+                            reason,
+                        }),
+                        _ => None,
+                    })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,132 @@
+use std::{collections::HashMap, future::Future, pin::Pin};
+
+use log::{debug, error};
+use zbus::{names::UniqueName, proxy, zvariant::Value, Connection};
+
+/// Polkit actions guarding the control interface. Every method belongs to
+/// exactly one of them, so that e.g. users can be allowed to switch profiles
+/// but not to rewrite them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    ReadState,
+    SwitchProfile,
+    EditProfiles,
+    EditConfig,
+}
+
+impl Action {
+    pub const ALL: [Action; 4] = [
+        Action::ReadState,
+        Action::SwitchProfile,
+        Action::EditProfiles,
+        Action::EditConfig,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Action::ReadState => "io.github.thealexdev23.power_daemon.read-state",
+            Action::SwitchProfile => "io.github.thealexdev23.power_daemon.switch-profile",
+            Action::EditProfiles => "io.github.thealexdev23.power_daemon.edit-profiles",
+            Action::EditConfig => "io.github.thealexdev23.power_daemon.edit-config",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::ReadState => "Read power-options profiles and configuration",
+            Action::SwitchProfile => "Switch the active power-options profile",
+            Action::EditProfiles => "Create, edit and remove power-options profiles",
+            Action::EditConfig => "Edit the power-options daemon configuration",
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Action::ReadState => "Authentication is required to read power settings",
+            Action::SwitchProfile => "Authentication is required to switch the power profile",
+            Action::EditProfiles => "Authentication is required to edit power profiles",
+            Action::EditConfig => "Authentication is required to configure power-options",
+        }
+    }
+
+    /// Implicit authorizations for any session, inactive sessions and active
+    /// sessions, in that order
+    pub fn defaults(&self) -> [&'static str; 3] {
+        match self {
+            Action::ReadState => ["yes", "yes", "yes"],
+            Action::SwitchProfile => ["auth_admin_keep", "auth_admin_keep", "yes"],
+            Action::EditProfiles | Action::EditConfig => {
+                ["auth_admin_keep", "auth_admin_keep", "auth_admin_keep"]
+            }
+        }
+    }
+}
+
+pub type AuthorizationFuture<'a> = Pin<Box<dyn Future<Output = zbus::Result<bool>> + Send + 'a>>;
+
+/// Decides whether the sender of a D-Bus message may perform an action
+pub trait Authority: Send + Sync {
+    fn check<'a>(&'a self, sender: &'a UniqueName<'_>, action: Action) -> AuthorizationFuture<'a>;
+}
+
+#[proxy(
+    default_service = "org.freedesktop.PolicyKit1",
+    interface = "org.freedesktop.PolicyKit1.Authority",
+    default_path = "/org/freedesktop/PolicyKit1/Authority"
+)]
+trait PolkitAuthorityDBus {
+    fn check_authorization(
+        &self,
+        subject: &(&str, HashMap<&str, Value<'_>>),
+        action_id: &str,
+        details: HashMap<&str, &str>,
+        flags: u32,
+        cancellation_id: &str,
+    ) -> zbus::Result<(bool, bool, HashMap<String, String>)>;
+}
+
+/// Asks polkit, which lets the user authenticate if the action requires it
+pub struct PolkitAuthority {
+    con: Connection,
+}
+
+impl PolkitAuthority {
+    const ALLOW_USER_INTERACTION: u32 = 1;
+
+    pub async fn new() -> zbus::Result<PolkitAuthority> {
+        Ok(PolkitAuthority {
+            con: Connection::system().await?,
+        })
+    }
+}
+
+impl Authority for PolkitAuthority {
+    fn check<'a>(&'a self, sender: &'a UniqueName<'_>, action: Action) -> AuthorizationFuture<'a> {
+        Box::pin(async move {
+            let proxy = PolkitAuthorityDBusProxy::new(&self.con).await?;
+
+            let subject = (
+                "system-bus-name",
+                HashMap::from([("name", Value::from(sender.as_str()))]),
+            );
+
+            let (authorized, challenge, _) = proxy
+                .check_authorization(
+                    &subject,
+                    action.id(),
+                    HashMap::new(),
+                    Self::ALLOW_USER_INTERACTION,
+                    "",
+                )
+                .await
+                .inspect_err(|error| error!("Could not check polkit authorization: {error}"))?;
+
+            debug!(
+                "{sender} authorized for {}: {authorized} (challenge: {challenge})",
+                action.id()
+            );
+
+            Ok(authorized)
+        })
+    }
+}
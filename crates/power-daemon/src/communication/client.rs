@@ -1,8 +1,17 @@
+use super::ControlError;
 use crate::{
     systeminfo::{CPUInfo, SystemInfo},
-    Config, DefaultProfileType, FirmwareInfo, GpuInfo, OptionalFeaturesInfo, PCIInfo, Profile,
-    ProfilesInfo, ReducedUpdate, SATAInfo, USBInfo,
+    ApplyReport, Bundle, Config, DefaultProfileType, DriftStatus, FirmwareInfo, GpuInfo,
+    GroupReport, ImportReport, LiveDiff, LoadError, OptionalFeaturesInfo, PCIInfo, Plan,
+    ProcessRule, Profile, ProfileDiff, ProfilesInfo, ReducedUpdate, ResolvedProfile,
+    RevisionedConfig, SATAInfo, TemporaryOverride, USBInfo, ValidationIssue,
 };
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::{stream, StreamExt};
+use log::error;
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
 use zbus::proxy;
 
 #[proxy(
@@ -86,37 +95,121 @@ impl SystemInfoClient {
     default_path = "/io/github/thealexdev23/power_daemon/control"
 )]
 trait ControlDBus {
-    async fn get_config(&self) -> zbus::Result<String>;
-    async fn get_profiles_info(&self) -> zbus::Result<String>;
-
-    async fn update_full(&self) -> zbus::Result<()>;
-    async fn update_reduced(&self, partial_update: String) -> zbus::Result<()>;
-
-    async fn update_config(&self, updated: String) -> zbus::Result<()>;
-
-    async fn get_active_profile_name(&self) -> zbus::Result<String>;
-
-    async fn create_profile(&self, profile_type: String) -> zbus::Result<()>;
-    async fn remove_profile(&self, idx: u32) -> zbus::Result<()>;
-    async fn reset_profile(&self, idx: u32) -> zbus::Result<()>;
-
-    async fn swap_profiles(&self, idx: u32, new_idx: u32) -> zbus::Result<()>;
-    async fn update_profile_name(&self, idx: u32, new_name: String) -> zbus::Result<()>;
-
-    async fn update_profile_full(&self, idx: u32, updated: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn active_profile_changed(&self, profile_name: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn config_updated(&self, config: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn profiles_changed(&self, profiles_info: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn profile_override_changed(&self, temporary_override: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn apply_finished(&self, report: String) -> zbus::Result<()>;
+    #[zbus(signal)]
+    fn drift_detected(&self, status: String) -> zbus::Result<()>;
+
+    async fn get_config(&self) -> Result<String, ControlError>;
+    async fn get_profiles_info(&self) -> Result<String, ControlError>;
+
+    async fn update_full(&self) -> Result<(), ControlError>;
+    async fn update_reduced(&self, partial_update: String) -> Result<(), ControlError>;
+
+    async fn update_config(&self, updated: String) -> Result<(), ControlError>;
+    async fn get_config_with_revision(&self) -> Result<String, ControlError>;
+    async fn update_config_at_revision(
+        &self,
+        updated: String,
+        expected_revision: u64,
+    ) -> Result<(), ControlError>;
+
+    async fn get_active_profile_name(&self) -> Result<String, ControlError>;
+    async fn get_last_apply_report(&self) -> Result<String, ControlError>;
+    async fn get_profile_plan(&self, profile_name: String) -> Result<String, ControlError>;
+    async fn get_resolved_profile(&self, profile_name: String) -> Result<String, ControlError>;
+    async fn get_profile_diff(&self, left: String, right: String) -> Result<String, ControlError>;
+    async fn get_live_diff(&self) -> Result<String, ControlError>;
+    async fn validate_profile(&self, profile: String) -> Result<String, ControlError>;
+    async fn get_drift_status(&self) -> Result<String, ControlError>;
+    async fn get_load_errors(&self) -> Result<String, ControlError>;
+    async fn export_bundle(
+        &self,
+        profile_names: String,
+        include_assignments: bool,
+    ) -> Result<String, ControlError>;
+    async fn import_bundle(&self, bundle: String) -> Result<String, ControlError>;
+    async fn restore_baseline(&self) -> Result<String, ControlError>;
+    async fn get_automatic_override(&self) -> Result<String, ControlError>;
+
+    async fn create_profile(&self, profile_type: String) -> Result<String, ControlError>;
+    async fn create_derived_profile(&self, parent: String) -> Result<String, ControlError>;
+    async fn remove_profile(&self, idx: u32) -> Result<(), ControlError>;
+    async fn reset_profile(&self, idx: u32) -> Result<(), ControlError>;
+
+    async fn swap_profiles(&self, idx: u32, new_idx: u32) -> Result<(), ControlError>;
+    async fn update_profile_name(&self, idx: u32, new_name: String) -> Result<(), ControlError>;
+
+    async fn update_profile_full(&self, idx: u32, updated: String) -> Result<(), ControlError>;
     async fn update_profile_reduced(
         &self,
         idx: u32,
         updated: String,
         reduced_update: String,
-    ) -> zbus::Result<()>;
+    ) -> Result<(), ControlError>;
 
-    async fn set_reduced_update(&self, reduced_update: String) -> zbus::Result<()>;
-    async fn reset_reduced_update(&self) -> zbus::Result<()>;
+    async fn reset_profile_by_id(
+        &self,
+        id: String,
+        expected_revision: u64,
+    ) -> Result<(), ControlError>;
+    async fn remove_profile_by_id(
+        &self,
+        id: String,
+        expected_revision: u64,
+    ) -> Result<(), ControlError>;
+    async fn swap_profiles_by_id(&self, id: String, other_id: String) -> Result<(), ControlError>;
+    async fn update_profile_name_by_id(
+        &self,
+        id: String,
+        new_name: String,
+        expected_revision: u64,
+    ) -> Result<(), ControlError>;
+    async fn update_profile_full_by_id(
+        &self,
+        id: String,
+        updated: String,
+        expected_revision: u64,
+    ) -> Result<(), ControlError>;
+    async fn update_profile_reduced_by_id(
+        &self,
+        id: String,
+        updated: String,
+        reduced_update: String,
+        expected_revision: u64,
+    ) -> Result<(), ControlError>;
+
+    async fn set_reduced_update(&self, reduced_update: String) -> Result<(), ControlError>;
+    async fn reset_reduced_update(&self) -> Result<(), ControlError>;
+
+    async fn get_profile_override(&self) -> Result<String, ControlError>;
+    async fn set_profile_override(&self, profile_name: String) -> Result<(), ControlError>;
+    async fn get_temporary_override(&self) -> Result<String, ControlError>;
+    async fn set_profile_override_until(
+        &self,
+        profile_name: String,
+        expires_at: u64,
+    ) -> Result<(), ControlError>;
+    async fn remove_profile_override(&self) -> Result<(), ControlError>;
+}
 
-    async fn get_profile_override(&self) -> zbus::Result<String>;
-    async fn set_profile_override(&self, profile_name: String) -> zbus::Result<()>;
-    async fn remove_profile_override(&self) -> zbus::Result<()>;
+/// Changes the daemon notifies about, see [`ControlClient::subscribe`]
+#[derive(Clone, Debug)]
+pub enum DaemonEvent {
+    ActiveProfileChanged(String),
+    ConfigUpdated(Config),
+    ProfilesChanged(ProfilesInfo),
+    ProfileOverrideChanged(Option<TemporaryOverride>),
+    ApplyFinished(ApplyReport),
+    DriftDetected(DriftStatus),
 }
 
 #[derive(Clone)]
@@ -130,17 +223,17 @@ impl ControlClient {
         Ok(Self { dbus_con: con })
     }
 
-    pub async fn get_config(&self) -> zbus::Result<Config> {
-        Ok(serde_json::from_str(&self.get_proxy().await?.get_config().await?).unwrap())
+    pub async fn get_config(&self) -> Result<Config, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_config().await?)
     }
-    pub async fn get_profiles_info(&self) -> zbus::Result<ProfilesInfo> {
-        Ok(serde_json::from_str(&self.get_proxy().await?.get_profiles_info().await?).unwrap())
+    pub async fn get_profiles_info(&self) -> Result<ProfilesInfo, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_profiles_info().await?)
     }
 
-    pub async fn update_full(&self) -> zbus::Result<()> {
+    pub async fn update_full(&self) -> Result<(), ControlError> {
         self.get_proxy().await?.update_full().await
     }
-    pub async fn update_reduced(&self, reduced_update: ReducedUpdate) -> zbus::Result<()> {
+    pub async fn update_reduced(&self, reduced_update: ReducedUpdate) -> Result<(), ControlError> {
         self.get_proxy()
             .await?
             .update_reduced(
@@ -149,40 +242,184 @@ impl ControlClient {
             .await
     }
 
-    pub async fn update_config(&self, config: Config) -> zbus::Result<()> {
+    pub async fn update_config(&self, config: Config) -> Result<(), ControlError> {
         self.get_proxy()
             .await?
             .update_config(serde_json::to_string(&config).expect("Could not serialize config"))
             .await
     }
+    pub async fn get_config_with_revision(&self) -> Result<RevisionedConfig, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_config_with_revision().await?)
+    }
+    /// Fails with `ControlError::RevisionConflict` if the config changed since
+    /// `expected_revision` was read
+    pub async fn update_config_at_revision(
+        &self,
+        config: Config,
+        expected_revision: u64,
+    ) -> Result<(), ControlError> {
+        self.get_proxy()
+            .await?
+            .update_config_at_revision(
+                serde_json::to_string(&config).expect("Could not serialize config"),
+                expected_revision,
+            )
+            .await
+    }
 
-    pub async fn get_active_profile_name(&self) -> zbus::Result<String> {
+    pub async fn get_active_profile_name(&self) -> Result<String, ControlError> {
         self.get_proxy().await?.get_active_profile_name().await
     }
-    pub async fn create_profile(&self, profile_type: DefaultProfileType) -> zbus::Result<()> {
+    pub async fn get_last_apply_report(&self) -> Result<Option<ApplyReport>, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_last_apply_report().await?)
+    }
+    pub async fn get_profile_plan(&self, profile_name: String) -> Result<Plan, ControlError> {
+        parse_reply(
+            &self
+                .get_proxy()
+                .await?
+                .get_profile_plan(profile_name)
+                .await?,
+        )
+    }
+
+    pub async fn get_profile_diff(
+        &self,
+        left: String,
+        right: String,
+    ) -> Result<ProfileDiff, ControlError> {
+        parse_reply(
+            &self
+                .get_proxy()
+                .await?
+                .get_profile_diff(left, right)
+                .await?,
+        )
+    }
+
+    /// Compares the active profile with the values the system holds
+    pub async fn get_live_diff(&self) -> Result<LiveDiff, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_live_diff().await?)
+    }
+
+    /// Checks the profile against the hardware of the system the daemon runs
+    /// on, without storing it
+    pub async fn validate_profile(
+        &self,
+        profile: &Profile,
+    ) -> Result<Vec<ValidationIssue>, ControlError> {
+        parse_reply(
+            &self
+                .get_proxy()
+                .await?
+                .validate_profile(
+                    serde_json::to_string(profile).expect("Could not serialize profile"),
+                )
+                .await?,
+        )
+    }
+
+    pub async fn get_drift_status(&self) -> Result<DriftStatus, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_drift_status().await?)
+    }
+
+    /// Config and profile files the daemon could not load, it uses defaults
+    /// and empty profiles in their place
+    pub async fn get_load_errors(&self) -> Result<Vec<LoadError>, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_load_errors().await?)
+    }
+
+    /// Returns the profile as it would be applied, with the settings it
+    /// inherits filled in
+    pub async fn get_resolved_profile(
+        &self,
+        profile_name: String,
+    ) -> Result<ResolvedProfile, ControlError> {
+        parse_reply(
+            &self
+                .get_proxy()
+                .await?
+                .get_resolved_profile(profile_name)
+                .await?,
+        )
+    }
+
+    /// Returns the process rule currently overriding the profile, if any
+    pub async fn get_automatic_override(&self) -> Result<Option<ProcessRule>, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_automatic_override().await?)
+    }
+
+    /// Exports every profile if `profile_names` is empty
+    pub async fn export_bundle(
+        &self,
+        profile_names: &[String],
+        include_assignments: bool,
+    ) -> Result<Bundle, ControlError> {
+        parse_reply(
+            &self
+                .get_proxy()
+                .await?
+                .export_bundle(
+                    serde_json::to_string(profile_names).unwrap(),
+                    include_assignments,
+                )
+                .await?,
+        )
+    }
+    pub async fn import_bundle(&self, bundle: &Bundle) -> Result<ImportReport, ControlError> {
+        parse_reply(
+            &self
+                .get_proxy()
+                .await?
+                .import_bundle(serde_json::to_string(bundle).expect("Could not serialize bundle"))
+                .await?,
+        )
+    }
+
+    pub async fn restore_baseline(&self) -> Result<GroupReport, ControlError> {
+        parse_reply(&self.get_proxy().await?.restore_baseline().await?)
+    }
+    /// Returns the id of the new profile
+    pub async fn create_profile(
+        &self,
+        profile_type: DefaultProfileType,
+    ) -> Result<String, ControlError> {
         self.get_proxy()
             .await?
             .create_profile(serde_json::to_string(&profile_type).unwrap())
             .await
     }
-    pub async fn remove_profile(&self, idx: u32) -> zbus::Result<()> {
+    /// Creates a profile that inherits every setting of `parent`, returning
+    /// its id
+    pub async fn create_derived_profile(&self, parent: String) -> Result<String, ControlError> {
+        self.get_proxy().await?.create_derived_profile(parent).await
+    }
+    pub async fn remove_profile(&self, idx: u32) -> Result<(), ControlError> {
         self.get_proxy().await?.remove_profile(idx).await
     }
-    pub async fn reset_profile(&self, idx: u32) -> zbus::Result<()> {
+    pub async fn reset_profile(&self, idx: u32) -> Result<(), ControlError> {
         self.get_proxy().await?.reset_profile(idx).await
     }
 
-    pub async fn swap_profiles(&self, idx: u32, new_idx: u32) -> zbus::Result<()> {
+    pub async fn swap_profiles(&self, idx: u32, new_idx: u32) -> Result<(), ControlError> {
         self.get_proxy().await?.swap_profiles(idx, new_idx).await
     }
-    pub async fn update_profile_name(&self, idx: u32, new_name: String) -> zbus::Result<()> {
+    pub async fn update_profile_name(
+        &self,
+        idx: u32,
+        new_name: String,
+    ) -> Result<(), ControlError> {
         self.get_proxy()
             .await?
             .update_profile_name(idx, new_name)
             .await
     }
 
-    pub async fn update_profile_full(&self, idx: u32, updated: Profile) -> zbus::Result<()> {
+    pub async fn update_profile_full(
+        &self,
+        idx: u32,
+        updated: Profile,
+    ) -> Result<(), ControlError> {
         self.get_proxy()
             .await?
             .update_profile_full(
@@ -196,7 +433,7 @@ impl ControlClient {
         idx: u32,
         updated: Profile,
         reduced_update: ReducedUpdate,
-    ) -> zbus::Result<()> {
+    ) -> Result<(), ControlError> {
         self.get_proxy()
             .await?
             .update_profile_reduced(
@@ -207,7 +444,88 @@ impl ControlClient {
             .await
     }
 
-    pub async fn get_profile_override(&self) -> zbus::Result<Option<String>> {
+    // Unlike indices, ids keep addressing the same profile while other
+    // clients reorder, rename or remove profiles. If an expected revision,
+    // from `ProfilesInfo::revisions`, is given the change fails with
+    // `ControlError::RevisionConflict` once the profile changed since.
+
+    pub async fn reset_profile_by_id(
+        &self,
+        id: &str,
+        expected_revision: Option<u64>,
+    ) -> Result<(), ControlError> {
+        self.get_proxy()
+            .await?
+            .reset_profile_by_id(id.to_string(), expected_revision.unwrap_or_default())
+            .await
+    }
+    pub async fn remove_profile_by_id(
+        &self,
+        id: &str,
+        expected_revision: Option<u64>,
+    ) -> Result<(), ControlError> {
+        self.get_proxy()
+            .await?
+            .remove_profile_by_id(id.to_string(), expected_revision.unwrap_or_default())
+            .await
+    }
+
+    pub async fn swap_profiles_by_id(&self, id: &str, other_id: &str) -> Result<(), ControlError> {
+        self.get_proxy()
+            .await?
+            .swap_profiles_by_id(id.to_string(), other_id.to_string())
+            .await
+    }
+    pub async fn update_profile_name_by_id(
+        &self,
+        id: &str,
+        new_name: String,
+        expected_revision: Option<u64>,
+    ) -> Result<(), ControlError> {
+        self.get_proxy()
+            .await?
+            .update_profile_name_by_id(
+                id.to_string(),
+                new_name,
+                expected_revision.unwrap_or_default(),
+            )
+            .await
+    }
+
+    pub async fn update_profile_full_by_id(
+        &self,
+        id: &str,
+        updated: Profile,
+        expected_revision: Option<u64>,
+    ) -> Result<(), ControlError> {
+        self.get_proxy()
+            .await?
+            .update_profile_full_by_id(
+                id.to_string(),
+                serde_json::to_string(&updated).expect("Could not serialize profile"),
+                expected_revision.unwrap_or_default(),
+            )
+            .await
+    }
+    pub async fn update_profile_reduced_by_id(
+        &self,
+        id: &str,
+        updated: Profile,
+        reduced_update: ReducedUpdate,
+        expected_revision: Option<u64>,
+    ) -> Result<(), ControlError> {
+        self.get_proxy()
+            .await?
+            .update_profile_reduced_by_id(
+                id.to_string(),
+                serde_json::to_string(&updated).expect("Could not serialize profile"),
+                serde_json::to_string(&reduced_update).expect("Could not serialize reduced update"),
+                expected_revision.unwrap_or_default(),
+            )
+            .await
+    }
+
+    pub async fn get_profile_override(&self) -> Result<Option<String>, ControlError> {
         self.get_proxy()
             .await?
             .get_profile_override()
@@ -215,17 +533,143 @@ impl ControlClient {
             .map(|p| if p.is_empty() { None } else { Some(p) })
     }
 
-    pub async fn set_profile_override(&self, profile_name: String) -> zbus::Result<()> {
+    pub async fn set_profile_override(&self, profile_name: String) -> Result<(), ControlError> {
         self.get_proxy()
             .await?
             .set_profile_override(profile_name)
             .await
     }
-    pub async fn remove_profile_override(&self) -> zbus::Result<()> {
+    /// Like [`ControlClient::get_profile_override`] but including when the
+    /// override expires
+    pub async fn get_temporary_override(&self) -> Result<Option<TemporaryOverride>, ControlError> {
+        parse_reply(&self.get_proxy().await?.get_temporary_override().await?)
+    }
+    /// Overrides the profile until the given time, after which the daemon
+    /// removes the override by itself
+    pub async fn set_profile_override_until(
+        &self,
+        profile_name: String,
+        expires_at: SystemTime,
+    ) -> Result<(), ControlError> {
+        let expires_at = expires_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.get_proxy()
+            .await?
+            .set_profile_override_until(profile_name, expires_at)
+            .await
+    }
+    /// Overrides the profile for the given amount of time
+    pub async fn set_timed_profile_override(
+        &self,
+        profile_name: String,
+        duration: Duration,
+    ) -> Result<(), ControlError> {
+        let expires_at = SystemTime::now()
+            .checked_add(duration)
+            .ok_or_else(|| ControlError::InvalidArgs("duration is out of range".to_string()))?;
+        self.set_profile_override_until(profile_name, expires_at)
+            .await
+    }
+    pub async fn remove_profile_override(&self) -> Result<(), ControlError> {
         self.get_proxy().await?.remove_profile_override().await
     }
 
+    /// Returns a receiver of every change the daemon notifies about from now
+    /// on, in the order they happen. Events stop once the receiver is dropped.
+    pub async fn subscribe(&self) -> Result<UnboundedReceiver<DaemonEvent>, ControlError> {
+        let proxy = self.get_proxy().await?;
+
+        let active_profile_changed = proxy
+            .receive_active_profile_changed()
+            .await?
+            .filter_map(|signal| async move {
+                Some(DaemonEvent::ActiveProfileChanged(
+                    signal.args().ok()?.profile_name,
+                ))
+            })
+            .boxed();
+        let config_updated = proxy
+            .receive_config_updated()
+            .await?
+            .filter_map(|signal| async move {
+                Some(DaemonEvent::ConfigUpdated(parse_signal_payload(
+                    &signal.args().ok()?.config,
+                )?))
+            })
+            .boxed();
+        let profiles_changed = proxy
+            .receive_profiles_changed()
+            .await?
+            .filter_map(|signal| async move {
+                Some(DaemonEvent::ProfilesChanged(parse_signal_payload(
+                    &signal.args().ok()?.profiles_info,
+                )?))
+            })
+            .boxed();
+        let profile_override_changed = proxy
+            .receive_profile_override_changed()
+            .await?
+            .filter_map(|signal| async move {
+                Some(DaemonEvent::ProfileOverrideChanged(parse_signal_payload(
+                    &signal.args().ok()?.temporary_override,
+                )?))
+            })
+            .boxed();
+        let apply_finished = proxy
+            .receive_apply_finished()
+            .await?
+            .filter_map(|signal| async move {
+                Some(DaemonEvent::ApplyFinished(parse_signal_payload(
+                    &signal.args().ok()?.report,
+                )?))
+            })
+            .boxed();
+
+        let drift_detected = proxy
+            .receive_drift_detected()
+            .await?
+            .filter_map(|signal| async move {
+                Some(DaemonEvent::DriftDetected(parse_signal_payload(
+                    &signal.args().ok()?.status,
+                )?))
+            })
+            .boxed();
+
+        let mut events = stream::select_all([
+            active_profile_changed,
+            config_updated,
+            profiles_changed,
+            profile_override_changed,
+            apply_finished,
+            drift_detected,
+        ]);
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                if sender.send(event).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(receiver)
+    }
+
     async fn get_proxy(&self) -> zbus::Result<ControlDBusProxy> {
         ControlDBusProxy::new(&self.dbus_con).await
     }
 }
+
+fn parse_reply<T: DeserializeOwned>(reply: &str) -> Result<T, ControlError> {
+    serde_json::from_str(reply)
+        .map_err(|error| ControlError::InvalidReply(format!("could not parse reply: {error}")))
+}
+
+fn parse_signal_payload<T: DeserializeOwned>(payload: &str) -> Option<T> {
+    serde_json::from_str(payload)
+        .inspect_err(|error| error!("Could not parse signal payload: {error}"))
+        .ok()
+}
@@ -1,4 +1,71 @@
+#[cfg(feature = "server")]
+pub mod authorization;
 #[cfg(feature = "client")]
 pub mod client;
 #[cfg(feature = "server")]
 pub mod server;
+
+use zbus::DBusError;
+
+use crate::InstanceError;
+
+/// Errors replied by the control interface. Every variant is sent as
+/// `io.github.thealexdev23.power_daemon.Error.<Variant>` along with a
+/// human readable description.
+#[derive(DBusError, Debug)]
+#[zbus(prefix = "io.github.thealexdev23.power_daemon.Error")]
+pub enum ControlError {
+    /// Errors from the D-Bus connection itself
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    /// An argument could not be parsed, usually malformed JSON
+    InvalidArgs(String),
+    ProfileNotFound(String),
+    ProfileIdNotFound(String),
+    ProfileIndexOutOfRange(String),
+    ProfileNameTaken(String),
+    ProfileNotLoaded(String),
+    /// Someone else changed what was being edited, the client should read it
+    /// again and let the user decide what to keep
+    RevisionConflict(String),
+    CannotRemoveActiveProfile(String),
+    CannotRemoveLastProfile(String),
+    InvalidBundle(String),
+    InheritanceCycle(String),
+    /// Lists every offending value, `validate_profile` returns them
+    /// individually
+    InvalidProfile(String),
+    /// The caller is not allowed to perform the action, see
+    /// `authorization::Action`
+    NotAuthorized(String),
+    /// The reply of the daemon could not be parsed, usually because the
+    /// daemon and the client are different versions
+    InvalidReply(String),
+}
+
+impl From<InstanceError> for ControlError {
+    fn from(error: InstanceError) -> Self {
+        let description = error.to_string();
+        match error {
+            InstanceError::ProfileNotFound(_) => ControlError::ProfileNotFound(description),
+            InstanceError::ProfileIndexOutOfRange(_) => {
+                ControlError::ProfileIndexOutOfRange(description)
+            }
+            InstanceError::ProfileIdNotFound(_) => ControlError::ProfileIdNotFound(description),
+            InstanceError::CoreIndexOutOfRange(_) => ControlError::InvalidArgs(description),
+            InstanceError::ProfileNameTaken(_) => ControlError::ProfileNameTaken(description),
+            InstanceError::ProfileNameChanged(..) => ControlError::InvalidArgs(description),
+            InstanceError::ProfileNotLoaded(_) => ControlError::ProfileNotLoaded(description),
+            InstanceError::RevisionConflict(..) => ControlError::RevisionConflict(description),
+            InstanceError::CannotRemoveActiveProfile => {
+                ControlError::CannotRemoveActiveProfile(description)
+            }
+            InstanceError::CannotRemoveLastProfile => {
+                ControlError::CannotRemoveLastProfile(description)
+            }
+            InstanceError::InvalidBundle(_) => ControlError::InvalidBundle(description),
+            InstanceError::InheritanceCycle(_) => ControlError::InheritanceCycle(description),
+            InstanceError::InvalidProfile(..) => ControlError::InvalidProfile(description),
+        }
+    }
+}
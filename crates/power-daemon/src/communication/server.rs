@@ -1,212 +1,1212 @@
+mod power_profiles;
+
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use log::{debug, error, info, trace};
+use serde::de::DeserializeOwned;
 
 use tokio::sync::Mutex;
-use zbus::{conn::Builder, interface, Connection, Error};
+use zbus::{
+    conn::Builder, interface, message::Header, object_server::InterfaceRef, Connection, Error,
+    SignalContext,
+};
+
+use power_profiles::{
+    notify_power_profiles_changed, serve_power_profiles, PowerProfilesState,
+    SharedPowerProfilesState,
+};
 
+use super::{
+    authorization::{Action, Authority, PolkitAuthority},
+    ControlError,
+};
 use crate::{
     systeminfo::{CPUInfo, SystemInfo},
-    FirmwareInfo, GpuInfo, Instance, OptionalFeaturesInfo, PCIInfo, SATAInfo, USBInfo,
+    Bundle, FirmwareInfo, GpuInfo, GroupReport, Instance, InstanceEvent, OptionalFeaturesInfo,
+    PCIInfo, Profile, SATAInfo, SystemRoot, USBInfo,
 };
 
+const CONTROL_PATH: &str = "/io/github/thealexdev23/power_daemon/control";
+
 pub struct CommunicationServer {
-    _con: Connection,
+    con: Connection,
 }
 
 impl CommunicationServer {
+    /// Serves the instance, asking polkit whether callers are authorized
     pub async fn new(instance: Instance) -> Result<CommunicationServer, Error> {
+        Self::with_authority(instance, Box::new(PolkitAuthority::new().await?)).await
+    }
+
+    pub async fn with_authority(
+        instance: Instance,
+        authority: Box<dyn Authority>,
+    ) -> Result<CommunicationServer, Error> {
         debug!("Initializing communications server");
+        let power_profiles = instance
+            .config
+            .power_profiles_compat
+            .enabled
+            .then(|| PowerProfilesState::new(&instance));
+        let root = instance.get_system_root().clone();
+
         let con = Builder::system()?
             .name("io.github.thealexdev23.power_daemon")?
             .serve_at(
-                "/io/github/thealexdev23/power_daemon/control",
+                CONTROL_PATH,
                 ControlServer {
+                    root: root.clone(),
                     instance: instance.into(),
+                    authority,
+                    power_profiles: power_profiles.clone(),
                 },
             )?
             .serve_at(
                 "/io/github/thealexdev23/power_daemon/system_info",
-                SystemInfoServer,
+                SystemInfoServer { root },
             )?
             .build()
             .await?;
+        if let Some(power_profiles) = power_profiles {
+            serve_power_profiles(&con, power_profiles).await;
+        }
         debug!("Finished setting up communications server connection");
-        Ok(CommunicationServer { _con: con })
+        Ok(CommunicationServer { con })
+    }
+
+    /// Applies the profile that should be active if it changed, used when the
+    /// daemon itself notices the system changed
+    pub async fn update_if_profile_changed(&self) -> Result<(), Error> {
+        let control = self.get_control().await?;
+        let server = control.get().await;
+        let mut instance = server.instance.lock().await;
+        instance.update_if_profile_changed();
+        server
+            .emit_events(&mut instance, control.signal_context())
+            .await;
+        Ok(())
+    }
+
+    /// Restores the baseline of the served instance, used when the daemon is
+    /// shutting down
+    pub async fn restore_baseline(&self) -> Result<GroupReport, Error> {
+        let control = self.get_control().await?;
+        let server = control.get().await;
+        let report = server.instance.lock().await.restore_baseline();
+        Ok(report)
+    }
+
+    /// Removes the temporary override if it expired, used periodically by the
+    /// daemon
+    pub async fn remove_expired_profile_override(&self) -> Result<(), Error> {
+        let control = self.get_control().await?;
+        let server = control.get().await;
+        let mut instance = server.instance.lock().await;
+        instance.remove_expired_profile_override();
+        server
+            .emit_events(&mut instance, control.signal_context())
+            .await;
+        Ok(())
+    }
+
+    /// Re-evaluates process rules, used periodically by the daemon
+    pub async fn update_if_automatic_override_changed(&self) -> Result<(), Error> {
+        let control = self.get_control().await?;
+        let server = control.get().await;
+        let mut instance = server.instance.lock().await;
+        instance.update_if_automatic_override_changed();
+        server
+            .emit_events(&mut instance, control.signal_context())
+            .await;
+        Ok(())
+    }
+
+    /// Looks for values of the active profile changed by something else,
+    /// used periodically by the daemon if drift detection is enabled
+    pub async fn check_drift(&self) -> Result<(), Error> {
+        let control = self.get_control().await?;
+        let server = control.get().await;
+        let mut instance = server.instance.lock().await;
+        instance.check_drift();
+        server
+            .emit_events(&mut instance, control.signal_context())
+            .await;
+        Ok(())
+    }
+
+    /// Reads the config and profiles again, used when the daemon receives
+    /// SIGHUP
+    pub async fn reload_files(&self) -> Result<(), Error> {
+        let control = self.get_control().await?;
+        let server = control.get().await;
+        let mut instance = server.instance.lock().await;
+        instance.reload_files();
+        server
+            .emit_events(&mut instance, control.signal_context())
+            .await;
+        Ok(())
+    }
+
+    /// Same as `reload_files`, but skipped if the daemon itself wrote the
+    /// files, used when the daemon notices they changed
+    pub async fn reload_changed_files(&self, paths: &[PathBuf]) -> Result<(), Error> {
+        let control = self.get_control().await?;
+        let server = control.get().await;
+        let mut instance = server.instance.lock().await;
+        instance.reload_changed_files(paths);
+        server
+            .emit_events(&mut instance, control.signal_context())
+            .await;
+        Ok(())
+    }
+
+    async fn get_control(&self) -> Result<InterfaceRef<ControlServer>, Error> {
+        self.con
+            .object_server()
+            .interface::<_, ControlServer>(CONTROL_PATH)
+            .await
     }
 }
 
-struct SystemInfoServer;
+struct SystemInfoServer {
+    root: SystemRoot,
+}
 
 #[interface(name = "io.github.thealexdev23.power_daemon.system_info")]
 impl SystemInfoServer {
     async fn get_system_info(&self) -> String {
-        serde_json::to_string(&SystemInfo::obtain()).unwrap()
+        serde_json::to_string(&SystemInfo::obtain(&self.root)).unwrap()
     }
 
     async fn get_cpu_info(&self) -> String {
-        serde_json::to_string(&CPUInfo::obtain()).unwrap()
+        serde_json::to_string(&CPUInfo::obtain(&self.root)).unwrap()
     }
 
     async fn get_pci_info(&self) -> String {
-        serde_json::to_string(&PCIInfo::obtain()).unwrap()
+        serde_json::to_string(&PCIInfo::obtain(&self.root)).unwrap()
     }
 
     async fn get_usb_info(&self) -> String {
-        serde_json::to_string(&USBInfo::obtain()).unwrap()
+        serde_json::to_string(&USBInfo::obtain(&self.root)).unwrap()
     }
 
     async fn get_sata_info(&self) -> String {
-        serde_json::to_string(&SATAInfo::obtain()).unwrap()
+        serde_json::to_string(&SATAInfo::obtain(&self.root)).unwrap()
     }
 
     async fn get_firmware_info(&self) -> String {
-        serde_json::to_string(&FirmwareInfo::obtain()).unwrap()
+        serde_json::to_string(&FirmwareInfo::obtain(&self.root)).unwrap()
     }
 
     async fn get_gpu_info(&self) -> String {
-        serde_json::to_string(&GpuInfo::obtain()).unwrap()
+        serde_json::to_string(&GpuInfo::obtain(&self.root)).unwrap()
     }
 
     async fn get_optional_features_info(&self) -> String {
-        serde_json::to_string(&OptionalFeaturesInfo::obtain()).unwrap()
+        serde_json::to_string(&OptionalFeaturesInfo::obtain(&self.root)).unwrap()
     }
 }
 
 struct ControlServer {
+    root: SystemRoot,
     instance: Mutex<Instance>,
+    authority: Box<dyn Authority>,
+    /// Set when the power-profiles-daemon compatible interfaces are served
+    power_profiles: Option<SharedPowerProfilesState>,
+}
+
+impl ControlServer {
+    async fn authorize(&self, header: &Header<'_>, action: Action) -> Result<(), ControlError> {
+        let Some(sender) = header.sender() else {
+            return Err(ControlError::NotAuthorized(
+                "the request has no sender".to_string(),
+            ));
+        };
+
+        match self.authority.check(sender, action).await {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                info!(target: "D-BUS", "{sender} is not authorized for {}", action.id());
+                Err(ControlError::NotAuthorized(format!(
+                    "not authorized for {}",
+                    action.id()
+                )))
+            }
+            Err(error) => Err(ControlError::NotAuthorized(format!(
+                "could not check authorization for {}: {error}",
+                action.id()
+            ))),
+        }
+    }
+
+    /// Emits a signal for every event that happened in the instance since
+    /// the last time this was called
+    async fn emit_events(&self, instance: &mut Instance, ctxt: &SignalContext<'_>) {
+        let events = instance.take_events();
+
+        if let Some(ref power_profiles) = self.power_profiles {
+            let affects_power_profiles = events.iter().any(|event| {
+                matches!(
+                    event,
+                    InstanceEvent::ActiveProfileChanged
+                        | InstanceEvent::ConfigUpdated
+                        | InstanceEvent::ProfilesChanged
+                )
+            });
+            if affects_power_profiles && power_profiles.lock().unwrap().refresh(instance) {
+                notify_power_profiles_changed(power_profiles).await;
+            }
+        }
+
+        for event in events {
+            let result = match event {
+                InstanceEvent::ActiveProfileChanged => {
+                    Self::active_profile_changed(ctxt, &instance.get_active_profile_name()).await
+                }
+                InstanceEvent::ConfigUpdated => {
+                    Self::config_updated(ctxt, &serde_json::to_string(&instance.config).unwrap())
+                        .await
+                }
+                InstanceEvent::ProfilesChanged => {
+                    Self::profiles_changed(
+                        ctxt,
+                        &serde_json::to_string(&instance.profiles_info).unwrap(),
+                    )
+                    .await
+                }
+                InstanceEvent::ProfileOverrideChanged => {
+                    Self::profile_override_changed(
+                        ctxt,
+                        &serde_json::to_string(&instance.get_temporary_override()).unwrap(),
+                    )
+                    .await
+                }
+                InstanceEvent::ApplyFinished => {
+                    Self::apply_finished(
+                        ctxt,
+                        &serde_json::to_string(&instance.get_last_apply_report()).unwrap(),
+                    )
+                    .await
+                }
+                InstanceEvent::DriftDetected => {
+                    Self::drift_detected(
+                        ctxt,
+                        &serde_json::to_string(instance.get_drift_status()).unwrap(),
+                    )
+                    .await
+                }
+            };
+
+            if let Err(error) = result {
+                error!("Could not emit signal for {event:?}: {error}");
+            }
+        }
+    }
 }
 
 #[interface(name = "io.github.thealexdev23.power_daemon.control")]
 impl ControlServer {
-    async fn get_config(&self) -> String {
+    #[zbus(signal)]
+    async fn active_profile_changed(
+        ctxt: &SignalContext<'_>,
+        profile_name: &str,
+    ) -> zbus::Result<()>;
+    /// Carries the JSON encoded `Config`
+    #[zbus(signal)]
+    async fn config_updated(ctxt: &SignalContext<'_>, config: &str) -> zbus::Result<()>;
+    /// Carries the JSON encoded `ProfilesInfo`
+    #[zbus(signal)]
+    async fn profiles_changed(ctxt: &SignalContext<'_>, profiles_info: &str) -> zbus::Result<()>;
+    /// Carries the JSON encoded `Option<TemporaryOverride>`
+    #[zbus(signal)]
+    async fn profile_override_changed(
+        ctxt: &SignalContext<'_>,
+        temporary_override: &str,
+    ) -> zbus::Result<()>;
+    /// Carries the JSON encoded `ApplyReport` of the apply that just finished
+    #[zbus(signal)]
+    async fn apply_finished(ctxt: &SignalContext<'_>, report: &str) -> zbus::Result<()>;
+    /// Carries the JSON encoded `DriftStatus`, emitted whenever a check finds
+    /// changed values
+    #[zbus(signal)]
+    async fn drift_detected(ctxt: &SignalContext<'_>, status: &str) -> zbus::Result<()>;
+
+    async fn get_config(&self, #[zbus(header)] header: Header<'_>) -> Result<String, ControlError> {
         info!(target: "D-BUS", "get_config");
-        serde_json::to_string(&self.instance.lock().await.config).unwrap()
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.config).unwrap())
     }
-    async fn get_profiles_info(&self) -> String {
+    async fn get_profiles_info(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
         info!(target: "D-BUS", "get_profiles_info");
-        serde_json::to_string(&self.instance.lock().await.profiles_info).unwrap()
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.profiles_info).unwrap())
     }
 
-    async fn update_full(&mut self) {
+    async fn update_full(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "update_full");
-        self.instance.get_mut().update_full();
+        self.authorize(&header, Action::SwitchProfile).await?;
+        let mut instance = self.instance.lock().await;
+        instance.update_full();
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(())
     }
-    async fn update_reduced(&mut self, reduced_update: String) {
+    async fn update_reduced(
+        &self,
+        reduced_update: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "update_reduced: {reduced_update}");
-        let reduced_update = match serde_json::from_str(&reduced_update) {
-            Ok(reduced_update) => reduced_update,
-            Err(error) => {
-                error!("Could not parse reduced update: {error}");
-                return;
-            }
-        };
-        self.instance.get_mut().update_reduced(reduced_update);
+        self.authorize(&header, Action::SwitchProfile).await?;
+        let reduced_update = parse_arg(&reduced_update, "reduced update")?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.update_reduced(reduced_update);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
 
-    async fn update_config(&mut self, updated: String) {
+    /// Returns a JSON encoded `RevisionedConfig`
+    async fn get_config_with_revision(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "get_config_with_revision");
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.get_config_with_revision()).unwrap())
+    }
+
+    /// Fails if the config's revision isn't `expected_revision` anymore
+    async fn update_config_at_revision(
+        &self,
+        updated: String,
+        expected_revision: u64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "update_config_at_revision: {expected_revision} {updated}");
+        self.authorize(&header, Action::EditConfig).await?;
+        let conf = parse_arg(&updated, "new requested config")?;
+        let mut instance = self.instance.lock().await;
+        let result = instance
+            .check_config_revision(expected_revision)
+            .and_then(|()| instance.update_config(conf));
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+
+    async fn update_config(
+        &self,
+        updated: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "update_config: {updated}");
-        match serde_json::from_str(&updated) {
-            Ok(conf) => {
-                self.instance.get_mut().update_config(conf);
-            }
-            Err(error) => {
-                error!("Could not parse new requested config: {error}")
-            }
+        self.authorize(&header, Action::EditConfig).await?;
+        let conf = parse_arg(&updated, "new requested config")?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.update_config(conf);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+
+    /// Returns a JSON encoded `Option<ApplyReport>`
+    async fn get_last_apply_report(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        debug!(target: "D-BUS", "get_last_apply_report");
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.get_last_apply_report()).unwrap())
+    }
+
+    /// Returns a JSON encoded `Plan`
+    async fn get_profile_plan(
+        &self,
+        profile_name: String,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "get_profile_plan: {profile_name}");
+        self.authorize(&header, Action::ReadState).await?;
+        let plan = self.instance.lock().await.plan_profile(&profile_name)?;
+        Ok(serde_json::to_string(&plan).unwrap())
+    }
+
+    /// Returns a JSON encoded `ProfileDiff`
+    async fn get_profile_diff(
+        &self,
+        left: String,
+        right: String,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "get_profile_diff: {left} {right}");
+        self.authorize(&header, Action::ReadState).await?;
+        let diff = self.instance.lock().await.diff_profiles(&left, &right)?;
+        Ok(serde_json::to_string(&diff).unwrap())
+    }
+
+    /// `profile` is a JSON encoded `Profile`. Returns a JSON encoded
+    /// `Vec<ValidationIssue>`, empty if the profile fits this system
+    async fn validate_profile(
+        &self,
+        profile: String,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "validate_profile");
+        self.authorize(&header, Action::ReadState).await?;
+        let profile: Profile = parse_arg(&profile, "profile")?;
+        Ok(serde_json::to_string(&profile.validate(&SystemInfo::obtain(&self.root))).unwrap())
+    }
+
+    /// Returns a JSON encoded `Vec<LoadError>` of the config and profile
+    /// files that could not be loaded
+    async fn get_load_errors(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "get_load_errors");
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.get_load_errors()).unwrap())
+    }
+
+    /// Returns a JSON encoded `DriftStatus`
+    async fn get_drift_status(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "get_drift_status");
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(self.instance.lock().await.get_drift_status()).unwrap())
+    }
+
+    /// Returns a JSON encoded `LiveDiff` of the active profile
+    async fn get_live_diff(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "get_live_diff");
+        self.authorize(&header, Action::ReadState).await?;
+        let diff = self.instance.lock().await.diff_active_profile_with_system();
+        Ok(serde_json::to_string(&diff).unwrap())
+    }
+
+    /// Returns a JSON encoded `ResolvedProfile`, which tells apart the
+    /// settings the profile sets itself from those it inherits
+    async fn get_resolved_profile(
+        &self,
+        profile_name: String,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "get_resolved_profile: {profile_name}");
+        self.authorize(&header, Action::ReadState).await?;
+        let resolved = self.instance.lock().await.resolve_profile(&profile_name)?;
+        Ok(serde_json::to_string(&resolved).unwrap())
+    }
+
+    /// `profile_names` is a JSON encoded `Vec<String>`, empty to export every
+    /// profile. Returns a JSON encoded `Bundle`
+    async fn export_bundle(
+        &self,
+        profile_names: String,
+        include_assignments: bool,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "export_bundle: {profile_names} {include_assignments}");
+        self.authorize(&header, Action::ReadState).await?;
+        let profile_names: Vec<String> = parse_arg(&profile_names, "profile names")?;
+        let bundle = self
+            .instance
+            .lock()
+            .await
+            .export_bundle(&profile_names, include_assignments)?;
+        Ok(serde_json::to_string(&bundle).unwrap())
+    }
+
+    /// Takes a JSON encoded `Bundle` and returns a JSON encoded `ImportReport`
+    async fn import_bundle(
+        &self,
+        bundle: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "import_bundle");
+        trace!("Bundle: {bundle}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        let bundle: Bundle = parse_arg(&bundle, "bundle")?;
+        if bundle.assignments.is_some() {
+            self.authorize(&header, Action::EditConfig).await?;
         }
+        let mut instance = self.instance.lock().await;
+        let result = instance.import_bundle(bundle);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(serde_json::to_string(&result?).unwrap())
+    }
+
+    /// Returns a JSON encoded `GroupReport` with the outcome of every restored value
+    async fn restore_baseline(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "restore_baseline");
+        self.authorize(&header, Action::SwitchProfile).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.restore_baseline()).unwrap())
     }
 
-    async fn get_active_profile_name(&mut self) -> String {
+    /// Returns a JSON encoded `Option<ProcessRule>`, the process rule currently
+    /// overriding the profile
+    async fn get_automatic_override(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        debug!(target: "D-BUS", "get_automatic_override");
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.get_automatic_override()).unwrap())
+    }
+
+    async fn get_active_profile_name(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
         debug!(target: "D-BUS", "get_active_profile_name");
-        self.instance.get_mut().get_active_profile_name()
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(self.instance.lock().await.get_active_profile_name())
     }
 
-    async fn create_profile(&mut self, profile_type: String) {
+    /// Returns the id of the new profile
+    async fn create_profile(
+        &self,
+        profile_type: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
         info!(target: "D-BUS", "create_profile: {profile_type}");
-        match serde_json::from_str(&profile_type) {
-            Ok(profile_type) => self.instance.get_mut().create_profile(profile_type),
-            Err(error) => {
-                error!("Could not parse new requested profile type: {error}")
-            }
-        }
+        self.authorize(&header, Action::EditProfiles).await?;
+        let profile_type = parse_arg(&profile_type, "new requested profile type")?;
+        let mut instance = self.instance.lock().await;
+        let id = instance.create_profile(profile_type);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(id)
+    }
+    /// Returns the id of the new profile
+    async fn create_derived_profile(
+        &self,
+        parent: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        info!(target: "D-BUS", "create_derived_profile: {parent}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.create_derived_profile(parent);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
-    async fn reset_profile(&mut self, idx: u32) {
+    async fn reset_profile(
+        &self,
+        idx: u32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "reset_profile: {idx}");
-        self.instance.get_mut().reset_profile(idx as usize);
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.reset_profile(idx as usize);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
-    async fn remove_profile(&mut self, idx: u32) {
+    async fn remove_profile(
+        &self,
+        idx: u32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "remove_profile: {idx}");
-        self.instance.get_mut().remove_profile(idx as usize);
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.remove_profile(idx as usize);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
 
-    async fn swap_profiles(&mut self, idx: u32, new_idx: u32) {
+    async fn swap_profiles(
+        &self,
+        idx: u32,
+        new_idx: u32,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "swap_profiles: {idx} with {new_idx}");
-        self.instance
-            .get_mut()
-            .swap_profile_order(idx as usize, new_idx as usize);
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.swap_profile_order(idx as usize, new_idx as usize);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
-    async fn update_profile_name(&mut self, idx: u32, new_name: String) {
+    async fn update_profile_name(
+        &self,
+        idx: u32,
+        new_name: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "update_profile_name: {idx} with {new_name}");
-        self.instance
-            .get_mut()
-            .update_profile_name(idx as usize, new_name);
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.update_profile_name(idx as usize, new_name);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
 
-    async fn update_profile_full(&mut self, idx: u32, updated: String) {
+    async fn update_profile_full(
+        &self,
+        idx: u32,
+        updated: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "update_profile_full: {idx}");
+        self.authorize(&header, Action::EditProfiles).await?;
         trace!("New profile: {updated}");
 
-        match serde_json::from_str(&updated) {
-            Ok(profile) => {
-                self.instance
-                    .get_mut()
-                    .update_profile_full(idx as usize, profile);
-            }
-            Err(error) => {
-                error!("Could not parse updated profile: {error}")
-            }
-        }
+        let profile = parse_arg(&updated, "updated profile")?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.update_profile_full(idx as usize, profile);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
-    async fn update_profile_reduced(&mut self, idx: u32, updated: String, reduced_update: String) {
+    async fn update_profile_reduced(
+        &self,
+        idx: u32,
+        updated: String,
+        reduced_update: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "update_profile_reduced: {idx} {reduced_update}");
+        self.authorize(&header, Action::EditProfiles).await?;
         trace!("New profile: {updated}");
 
-        let reduced_update = match serde_json::from_str(&reduced_update) {
-            Ok(reduced_update) => reduced_update,
-            Err(error) => {
-                error!("Could not parse reduced update: {error}");
-                return;
-            }
-        };
+        let reduced_update = parse_arg(&reduced_update, "reduced update")?;
+        let profile = parse_arg(&updated, "updated profile")?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.update_profile_reduced(idx as usize, profile, reduced_update);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
 
-        match serde_json::from_str(&updated) {
-            Ok(profile) => {
-                self.instance.get_mut().update_profile_reduced(
-                    idx as usize,
-                    profile,
-                    reduced_update,
-                );
-            }
-            Err(error) => {
-                error!("Could not parse updated profile: {error}")
-            }
-        }
+    // The same changes addressing profiles by id, which unlike the index
+    // can't point to another profile after a different client changed them.
+    // Unless `expected_revision` is 0 they fail if the profile's revision
+    // isn't that one anymore.
+
+    async fn reset_profile_by_id(
+        &self,
+        id: String,
+        expected_revision: u64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "reset_profile_by_id: {id} at {expected_revision}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance
+            .checked_profile_index(&id, expected(expected_revision))
+            .and_then(|idx| instance.reset_profile(idx));
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+    async fn remove_profile_by_id(
+        &self,
+        id: String,
+        expected_revision: u64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "remove_profile_by_id: {id} at {expected_revision}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance
+            .checked_profile_index(&id, expected(expected_revision))
+            .and_then(|idx| instance.remove_profile(idx));
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+
+    async fn swap_profiles_by_id(
+        &self,
+        id: String,
+        other_id: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "swap_profiles_by_id: {id} with {other_id}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.profile_index(&id).and_then(|idx| {
+            let other_idx = instance.profile_index(&other_id)?;
+            instance.swap_profile_order(idx, other_idx)
+        });
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+    async fn update_profile_name_by_id(
+        &self,
+        id: String,
+        new_name: String,
+        expected_revision: u64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "update_profile_name_by_id: {id} with {new_name} at {expected_revision}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance
+            .checked_profile_index(&id, expected(expected_revision))
+            .and_then(|idx| instance.update_profile_name(idx, new_name));
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
 
-    async fn get_profile_override(&mut self) -> String {
+    async fn update_profile_full_by_id(
+        &self,
+        id: String,
+        updated: String,
+        expected_revision: u64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "update_profile_full_by_id: {id} at {expected_revision}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        trace!("New profile: {updated}");
+
+        let profile = parse_arg(&updated, "updated profile")?;
+        let mut instance = self.instance.lock().await;
+        let result = instance
+            .checked_profile_index(&id, expected(expected_revision))
+            .and_then(|idx| instance.update_profile_full(idx, profile));
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+    async fn update_profile_reduced_by_id(
+        &self,
+        id: String,
+        updated: String,
+        reduced_update: String,
+        expected_revision: u64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "update_profile_reduced_by_id: {id} {reduced_update} at {expected_revision}");
+        self.authorize(&header, Action::EditProfiles).await?;
+        trace!("New profile: {updated}");
+
+        let reduced_update = parse_arg(&reduced_update, "reduced update")?;
+        let profile = parse_arg(&updated, "updated profile")?;
+        let mut instance = self.instance.lock().await;
+        let result = instance
+            .checked_profile_index(&id, expected(expected_revision))
+            .and_then(|idx| instance.update_profile_reduced(idx, profile, reduced_update));
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+
+    async fn get_profile_override(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
         info!(target: "D-BUS", "get_profile_override");
-        self.instance
-            .get_mut()
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(self
+            .instance
+            .lock()
+            .await
             .temporary_override
-            .clone()
-            .unwrap_or_default()
+            .as_ref()
+            .map(|o| o.profile_name.clone())
+            .unwrap_or_default())
+    }
+    /// Returns a JSON encoded `Option<TemporaryOverride>`, which includes when
+    /// the override expires
+    async fn get_temporary_override(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<String, ControlError> {
+        debug!(target: "D-BUS", "get_temporary_override");
+        self.authorize(&header, Action::ReadState).await?;
+        Ok(serde_json::to_string(&self.instance.lock().await.get_temporary_override()).unwrap())
     }
-    async fn set_profile_override(&mut self, profile_name: String) {
+    async fn set_profile_override(
+        &self,
+        profile_name: String,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "set_profile_override: {profile_name}");
-        self.instance
-            .get_mut()
-            .try_set_profile_override(profile_name);
+        self.authorize(&header, Action::SwitchProfile).await?;
+        let mut instance = self.instance.lock().await;
+        let result = instance.try_set_profile_override(profile_name);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
+    }
+    /// `expires_at` is in seconds since the unix epoch
+    async fn set_profile_override_until(
+        &self,
+        profile_name: String,
+        expires_at: u64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
+        info!(target: "D-BUS", "set_profile_override_until: {profile_name} {expires_at}");
+        self.authorize(&header, Action::SwitchProfile).await?;
+        let expires_at = UNIX_EPOCH
+            .checked_add(Duration::from_secs(expires_at))
+            .ok_or_else(|| {
+                ControlError::InvalidArgs(format!("expiration time {expires_at} is out of range"))
+            })?;
+        if expires_at <= SystemTime::now() {
+            return Err(ControlError::InvalidArgs(
+                "expiration time is in the past".to_string(),
+            ));
+        }
+        let mut instance = self.instance.lock().await;
+        let result = instance.try_set_timed_profile_override(profile_name, expires_at);
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(result?)
     }
-    async fn remove_profile_override(&mut self) {
+    async fn remove_profile_override(
+        &self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(), ControlError> {
         info!(target: "D-BUS", "remove_profile_override");
-        self.instance.get_mut().remove_profile_override();
+        self.authorize(&header, Action::SwitchProfile).await?;
+        let mut instance = self.instance.lock().await;
+        instance.remove_profile_override();
+        self.emit_events(&mut instance, &ctxt).await;
+        Ok(())
+    }
+}
+
+/// Revisions start at 1, 0 stands for not checking them
+fn expected(revision: u64) -> Option<u64> {
+    (revision != 0).then_some(revision)
+}
+
+fn parse_arg<T: DeserializeOwned>(json: &str, what: &str) -> Result<T, ControlError> {
+    serde_json::from_str(json).map_err(|error| {
+        error!("Could not parse {what}: {error}");
+        ControlError::InvalidArgs(format!("could not parse {what}: {error}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        os::unix::net::UnixStream,
+        sync::{Arc, Mutex as StdMutex},
+    };
+
+    use tempfile::TempDir;
+    use zbus::{message::Message, names::UniqueName, Guid};
+
+    use super::*;
+    use crate::{
+        communication::authorization::AuthorizationFuture, config::Config, sysfs::root::SystemRoot,
+    };
+
+    /// Denies every request, remembering which action it was asked about
+    struct DenyingAuthority {
+        checked: Arc<StdMutex<Vec<Action>>>,
+    }
+
+    impl Authority for DenyingAuthority {
+        fn check<'a>(&'a self, _: &'a UniqueName<'_>, action: Action) -> AuthorizationFuture<'a> {
+            self.checked.lock().unwrap().push(action);
+            Box::pin(async { Ok(false) })
+        }
+    }
+
+    /// A connection to nobody, which is enough to serve objects and build
+    /// signal contexts
+    pub(super) async fn p2p_connection() -> Connection {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let (con, _) = tokio::join!(
+            async {
+                zbus::conn::Builder::unix_stream(server_stream)
+                    .server(Guid::generate())
+                    .unwrap()
+                    .p2p()
+                    .build()
+                    .await
+                    .unwrap()
+            },
+            async {
+                zbus::conn::Builder::unix_stream(client_stream)
+                    .p2p()
+                    .build()
+                    .await
+                    .unwrap()
+            }
+        );
+        con
+    }
+
+    /// Serves a default instance kept in `dir`, returning the actions the
+    /// server asked to be authorized for
+    pub(super) fn denying_control_server(
+        dir: &TempDir,
+    ) -> (ControlServer, Arc<StdMutex<Vec<Action>>>) {
+        let root = SystemRoot::new(dir.path());
+        let instance = Instance::new(
+            Config::create_default(),
+            &dir.path().join("config.toml"),
+            &dir.path().join("profiles"),
+            &dir.path().join("baseline.json"),
+            root.clone(),
+        );
+        let checked = Arc::new(StdMutex::new(Vec::new()));
+        let server = ControlServer {
+            root,
+            instance: instance.into(),
+            authority: Box::new(DenyingAuthority {
+                checked: checked.clone(),
+            }),
+            power_profiles: None,
+        };
+        (server, checked)
+    }
+
+    /// A method call from another client
+    pub(super) fn call<B>(path: &str, interface: Option<&str>, member: &str, body: &B) -> Message
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        let mut builder = Message::method(path, member)
+            .unwrap()
+            .sender(":1.42")
+            .unwrap();
+        if let Some(interface) = interface {
+            builder = builder.interface(interface).unwrap();
+        }
+        builder.build(body).unwrap()
+    }
+
+    struct Fixture {
+        _dir: TempDir,
+        server: ControlServer,
+        checked: Arc<StdMutex<Vec<Action>>>,
+        /// Only needed for the signal context, denied requests never emit
+        con: Connection,
+        message: Message,
+    }
+
+    impl Fixture {
+        async fn new() -> Fixture {
+            let dir = TempDir::new().unwrap();
+            let (server, checked) = denying_control_server(&dir);
+
+            Fixture {
+                _dir: dir,
+                server,
+                checked,
+                con: p2p_connection().await,
+                message: call(CONTROL_PATH, None, "Test", &()),
+            }
+        }
+
+        fn header(&self) -> Header<'_> {
+            self.message.header()
+        }
+
+        fn ctxt(&self) -> SignalContext<'_> {
+            SignalContext::new(&self.con, CONTROL_PATH).unwrap()
+        }
+
+        /// The instance stays locked by the caller while the request runs, so
+        /// a method that locks it before authorizing never finishes
+        async fn assert_denied<T: std::fmt::Debug>(
+            &self,
+            action: Action,
+            request: impl Future<Output = Result<T, ControlError>>,
+        ) {
+            let result = tokio::time::timeout(Duration::from_secs(5), request)
+                .await
+                .expect("the request waited for the instance before being authorized");
+            assert!(
+                matches!(result, Err(ControlError::NotAuthorized(_))),
+                "{result:?}"
+            );
+            assert_eq!(self.checked.lock().unwrap().pop(), Some(action));
+        }
+    }
+
+    #[tokio::test]
+    async fn reading_state_is_denied() {
+        let f = Fixture::new().await;
+        let s = &f.server;
+        let _instance = s.instance.lock().await;
+        let action = Action::ReadState;
+
+        f.assert_denied(action, s.get_config(f.header())).await;
+        f.assert_denied(action, s.get_profiles_info(f.header()))
+            .await;
+        f.assert_denied(action, s.get_config_with_revision(f.header()))
+            .await;
+        f.assert_denied(action, s.get_last_apply_report(f.header()))
+            .await;
+        f.assert_denied(action, s.get_profile_plan("a".into(), f.header()))
+            .await;
+        f.assert_denied(
+            action,
+            s.get_profile_diff("a".into(), "b".into(), f.header()),
+        )
+        .await;
+        f.assert_denied(action, s.validate_profile("{}".into(), f.header()))
+            .await;
+        f.assert_denied(action, s.get_load_errors(f.header())).await;
+        f.assert_denied(action, s.get_drift_status(f.header()))
+            .await;
+        f.assert_denied(action, s.get_live_diff(f.header())).await;
+        f.assert_denied(action, s.get_resolved_profile("a".into(), f.header()))
+            .await;
+        f.assert_denied(action, s.export_bundle("[]".into(), true, f.header()))
+            .await;
+        f.assert_denied(action, s.get_automatic_override(f.header()))
+            .await;
+        f.assert_denied(action, s.get_active_profile_name(f.header()))
+            .await;
+        f.assert_denied(action, s.get_profile_override(f.header()))
+            .await;
+        f.assert_denied(action, s.get_temporary_override(f.header()))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn switching_profiles_is_denied() {
+        let f = Fixture::new().await;
+        let s = &f.server;
+        let _instance = s.instance.lock().await;
+        let action = Action::SwitchProfile;
+
+        f.assert_denied(action, s.update_full(f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(action, s.update_reduced("{}".into(), f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(action, s.restore_baseline(f.header()))
+            .await;
+        f.assert_denied(
+            action,
+            s.set_profile_override("a".into(), f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.set_profile_override_until("a".into(), u64::MAX, f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(action, s.remove_profile_override(f.ctxt(), f.header()))
+            .await;
+    }
+
+    #[tokio::test]
+    async fn editing_the_config_is_denied() {
+        let f = Fixture::new().await;
+        let s = &f.server;
+        let _instance = s.instance.lock().await;
+        let action = Action::EditConfig;
+
+        f.assert_denied(action, s.update_config("{}".into(), f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(
+            action,
+            s.update_config_at_revision("{}".into(), 1, f.ctxt(), f.header()),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn editing_profiles_is_denied() {
+        let f = Fixture::new().await;
+        let s = &f.server;
+        let _instance = s.instance.lock().await;
+        let action = Action::EditProfiles;
+
+        f.assert_denied(action, s.import_bundle("{}".into(), f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(action, s.create_profile("{}".into(), f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(
+            action,
+            s.create_derived_profile("a".into(), f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(action, s.reset_profile(0, f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(action, s.remove_profile(0, f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(action, s.swap_profiles(0, 1, f.ctxt(), f.header()))
+            .await;
+        f.assert_denied(
+            action,
+            s.update_profile_name(0, "b".into(), f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.update_profile_full(0, "{}".into(), f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.update_profile_reduced(0, "{}".into(), "{}".into(), f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.reset_profile_by_id("a".into(), 0, f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.remove_profile_by_id("a".into(), 0, f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.swap_profiles_by_id("a".into(), "b".into(), f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.update_profile_name_by_id("a".into(), "b".into(), 0, f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.update_profile_full_by_id("a".into(), "{}".into(), 0, f.ctxt(), f.header()),
+        )
+        .await;
+        f.assert_denied(
+            action,
+            s.update_profile_reduced_by_id(
+                "a".into(),
+                "{}".into(),
+                "{}".into(),
+                0,
+                f.ctxt(),
+                f.header(),
+            ),
+        )
+        .await;
     }
 }
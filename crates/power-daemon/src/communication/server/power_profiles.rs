@@ -0,0 +1,549 @@
+//! Interfaces of power-profiles-daemon, which is what desktop environments
+//! use for their power mode switchers. Switching a power mode sets a temporary
+//! override to the power-options profile it's mapped to in
+//! [`PowerProfilesCompat`](crate::PowerProfilesCompat).
+//!
+//! Desktops switch by setting the `ActiveProfile` property, and property
+//! setters of the zbus object server aren't told who the caller is, which is
+//! needed to check the switch against polkit. So the interfaces are served on
+//! a connection of their own, whose method calls are all handled here.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::StreamExt;
+use log::{debug, error, info, warn};
+use zbus::{
+    fdo::{self, RequestNameFlags, RequestNameReply},
+    message::{Header, Type},
+    object_server::InterfaceRef,
+    zvariant::{OwnedValue, Value},
+    Connection, MessageStream,
+};
+
+use super::{ControlServer, CONTROL_PATH};
+use crate::{communication::authorization::Action, Instance};
+
+/// Bus names and object paths power-profiles-daemon is reachable at. The
+/// first is the legacy one, which some desktops still use. Both have the
+/// name as their interface name too.
+const POWER_PROFILES_SERVICES: [(&str, &str); 2] = [
+    ("net.hadess.PowerProfiles", "/net/hadess/PowerProfiles"),
+    (
+        "org.freedesktop.UPower.PowerProfiles",
+        "/org/freedesktop/UPower/PowerProfiles",
+    ),
+];
+
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+const INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER_INTERFACE: &str = "org.freedesktop.DBus.Peer";
+
+/// What the interfaces report, kept up to date by the control server so that
+/// reading the properties never has to wait on it
+#[derive(Debug)]
+pub(super) struct PowerProfilesState {
+    active_profile: &'static str,
+    profiles: Vec<&'static str>,
+    /// Power mode that was switched to last
+    requested_profile: &'static str,
+    /// Set once the interfaces are served
+    con: Option<Connection>,
+}
+
+pub(super) type SharedPowerProfilesState = Arc<Mutex<PowerProfilesState>>;
+
+impl PowerProfilesState {
+    pub(super) fn new(instance: &Instance) -> SharedPowerProfilesState {
+        let mut state = PowerProfilesState {
+            active_profile: "balanced",
+            profiles: Vec::new(),
+            requested_profile: "balanced",
+            con: None,
+        };
+        state.refresh(instance);
+        Arc::new(Mutex::new(state))
+    }
+
+    /// Returns whether anything that's reported changed
+    pub(super) fn refresh(&mut self, instance: &Instance) -> bool {
+        let compat = &instance.config.power_profiles_compat;
+        let active_name = instance.get_active_profile_name();
+
+        let profiles = compat
+            .mappings()
+            .into_iter()
+            .filter(|(_, profile)| {
+                instance
+                    .profiles_info
+                    .try_find_profile_index_by_name(profile)
+                    .is_some()
+            })
+            .map(|(power_profile, _)| power_profile)
+            .collect();
+
+        // Several power modes can map to the same profile, in which case the
+        // one that was picked last is reported
+        let active_profile = if compat.profile_for(self.requested_profile) == Some(&active_name) {
+            self.requested_profile
+        } else {
+            compat.power_profile_of(&active_name).unwrap_or("balanced")
+        };
+
+        let changed = self.active_profile != active_profile || self.profiles != profiles;
+        self.active_profile = active_profile;
+        self.profiles = profiles;
+        changed
+    }
+
+    fn properties(&self) -> HashMap<&'static str, Value<'static>> {
+        let profiles: Vec<HashMap<String, Value<'static>>> = self
+            .profiles
+            .iter()
+            .map(|profile| {
+                HashMap::from([
+                    ("Profile".to_string(), Value::from(*profile)),
+                    ("Driver".to_string(), Value::from("power-options")),
+                ])
+            })
+            .collect();
+        // Holds aren't supported, see `HoldProfile`
+        let holds: Vec<HashMap<String, Value<'static>>> = Vec::new();
+
+        HashMap::from([
+            ("ActiveProfile", Value::from(self.active_profile)),
+            ("Profiles", Value::from(profiles)),
+            ("ActiveProfileHolds", Value::from(holds)),
+            ("PerformanceDegraded", Value::from("")),
+            // Deprecated upstream, still read by older desktops
+            ("PerformanceInhibited", Value::from("")),
+            ("Actions", Value::from(Vec::<String>::new())),
+            ("Version", Value::from(env!("CARGO_PKG_VERSION"))),
+        ])
+    }
+}
+
+/// Serves both interfaces and claims their names, which fails if
+/// power-profiles-daemon itself is running
+pub(super) async fn serve_power_profiles(
+    control_con: &Connection,
+    state: SharedPowerProfilesState,
+) {
+    let control = match control_con
+        .object_server()
+        .interface::<_, ControlServer>(CONTROL_PATH)
+        .await
+    {
+        Ok(control) => control,
+        Err(error) => {
+            error!("Could not find the control interface for power-profiles-daemon: {error}");
+            return;
+        }
+    };
+    // Never given an object server, so that method calls are only answered
+    // by `handle_call`
+    let con = match zbus::conn::Builder::system() {
+        Ok(builder) => builder.build().await,
+        Err(error) => Err(error),
+    };
+    let con = match con {
+        Ok(con) => con,
+        Err(error) => {
+            error!("Could not connect to serve power-profiles-daemon interfaces: {error}");
+            return;
+        }
+    };
+
+    let mut calls = MessageStream::from(&con);
+    state.lock().unwrap().con = Some(con.clone());
+
+    for (name, _) in POWER_PROFILES_SERVICES {
+        match con
+            .request_name_with_flags(name, RequestNameFlags::DoNotQueue.into())
+            .await
+        {
+            Ok(RequestNameReply::PrimaryOwner | RequestNameReply::AlreadyOwner) => {
+                info!("Serving power-profiles-daemon compatible interface as {name}")
+            }
+            Ok(_) => warn!("{name} is already owned, is power-profiles-daemon running?"),
+            Err(error) => error!("Could not request {name}: {error}"),
+        }
+    }
+
+    tokio::spawn(async move {
+        while let Some(msg) = calls.next().await {
+            let Ok(msg) = msg else {
+                continue;
+            };
+            if msg.message_type() != Type::MethodCall {
+                continue;
+            }
+
+            // Switching can wait on the user authenticating
+            let con = con.clone();
+            let control = control.clone();
+            let state = state.clone();
+            tokio::spawn(async move {
+                let header = msg.header();
+                let result = handle_call(&con, &control, &state, &msg).await;
+                let sent = match result {
+                    Ok(()) => Ok(()),
+                    Err(error) => con.reply_dbus_error(&header, error).await,
+                };
+                if let Err(error) = sent {
+                    error!("Could not reply to power-profiles-daemon call: {error}");
+                }
+            });
+        }
+    });
+}
+
+/// Tells clients of both interfaces that the reported state changed
+pub(super) async fn notify_power_profiles_changed(state: &SharedPowerProfilesState) {
+    let (con, properties) = {
+        let state = state.lock().unwrap();
+        let Some(con) = state.con.clone() else {
+            return;
+        };
+        let mut properties = state.properties();
+        properties.retain(|name, _| matches!(*name, "ActiveProfile" | "Profiles"));
+        (con, properties)
+    };
+
+    for (name, path) in POWER_PROFILES_SERVICES {
+        let invalidated: Vec<&str> = Vec::new();
+        if let Err(error) = con
+            .emit_signal(
+                None::<&str>,
+                path,
+                PROPERTIES_INTERFACE,
+                "PropertiesChanged",
+                &(name, &properties, invalidated),
+            )
+            .await
+        {
+            error!("Could not emit property changes of {name}: {error}");
+        }
+    }
+}
+
+/// Replies to the call unless it failed, in which case the caller replies
+/// with the error
+async fn handle_call(
+    con: &Connection,
+    control: &InterfaceRef<ControlServer>,
+    state: &SharedPowerProfilesState,
+    msg: &zbus::Message,
+) -> fdo::Result<()> {
+    let header = msg.header();
+    let (Some(path), Some(member)) = (header.path(), header.member()) else {
+        return Err(fdo::Error::InvalidArgs(
+            "missing path or member".to_string(),
+        ));
+    };
+    let Some((name, _)) = POWER_PROFILES_SERVICES
+        .into_iter()
+        .find(|(_, served_path)| path.as_str() == *served_path)
+    else {
+        return Err(fdo::Error::UnknownObject(format!("unknown object {path}")));
+    };
+    let interface = header.interface().map(|i| i.as_str()).unwrap_or(name);
+    debug!(target: "D-BUS", "power-profiles-daemon call: {interface}.{member}");
+
+    match (interface, member.as_str()) {
+        (PROPERTIES_INTERFACE, "Get") => {
+            let (interface, property): (String, String) = msg.body().deserialize()?;
+            check_interface(&interface, name)?;
+            let value = state
+                .lock()
+                .unwrap()
+                .properties()
+                .remove(property.as_str())
+                .ok_or_else(|| {
+                    fdo::Error::UnknownProperty(format!("unknown property {property}"))
+                })?;
+            Ok(con.reply(msg, &value).await?)
+        }
+        (PROPERTIES_INTERFACE, "GetAll") => {
+            let (interface,): (String,) = msg.body().deserialize()?;
+            check_interface(&interface, name)?;
+            let properties = state.lock().unwrap().properties();
+            Ok(con.reply(msg, &properties).await?)
+        }
+        (PROPERTIES_INTERFACE, "Set") => {
+            let (interface, property, value): (String, String, OwnedValue) =
+                msg.body().deserialize()?;
+            check_interface(&interface, name)?;
+            match property.as_str() {
+                "ActiveProfile" => {
+                    let power_profile = String::try_from(value).map_err(|_| {
+                        fdo::Error::InvalidArgs("ActiveProfile must be a string".to_string())
+                    })?;
+                    switch_power_profile(control, state, &header, power_profile).await?;
+                    Ok(con.reply(msg, &()).await?)
+                }
+                property if state.lock().unwrap().properties().contains_key(property) => Err(
+                    fdo::Error::PropertyReadOnly(format!("{property} is read only")),
+                ),
+                property => Err(fdo::Error::UnknownProperty(format!(
+                    "unknown property {property}"
+                ))),
+            }
+        }
+        (INTROSPECTABLE_INTERFACE, "Introspect") => {
+            Ok(con.reply(msg, &introspection_xml(name)).await?)
+        }
+        (PEER_INTERFACE, "Ping") => Ok(con.reply(msg, &()).await?),
+        (interface, "HoldProfile" | "ReleaseProfile") if interface == name => Err(
+            fdo::Error::NotSupported("power-options does not support profile holds".to_string()),
+        ),
+        (interface, member) => Err(fdo::Error::UnknownMethod(format!(
+            "unknown method {interface}.{member}"
+        ))),
+    }
+}
+
+fn check_interface(interface: &str, served: &str) -> fdo::Result<()> {
+    if interface == served {
+        Ok(())
+    } else {
+        Err(fdo::Error::UnknownInterface(format!(
+            "unknown interface {interface}"
+        )))
+    }
+}
+
+/// Switches the power mode through the control server, so that it's checked
+/// against the same polkit action as switching profiles and the change is
+/// signalled to power-options clients too
+async fn switch_power_profile(
+    control: &InterfaceRef<ControlServer>,
+    state: &SharedPowerProfilesState,
+    header: &Header<'_>,
+    power_profile: String,
+) -> fdo::Result<()> {
+    info!(target: "D-BUS", "power-profiles-daemon set_active_profile: {power_profile}");
+
+    let server = control.get().await;
+    server
+        .authorize(header, Action::SwitchProfile)
+        .await
+        .map_err(|error| fdo::Error::AccessDenied(error.to_string()))?;
+
+    let mut instance = server.instance.lock().await;
+
+    let Some((requested_profile, profile_name)) = instance
+        .config
+        .power_profiles_compat
+        .mappings()
+        .into_iter()
+        .find(|(name, _)| *name == power_profile)
+        .map(|(name, profile)| (name, profile.to_string()))
+    else {
+        return Err(fdo::Error::InvalidArgs(format!(
+            "unknown power profile {power_profile}"
+        )));
+    };
+
+    state.lock().unwrap().requested_profile = requested_profile;
+    let result = instance.try_set_profile_override(profile_name);
+    server
+        .emit_events(&mut instance, control.signal_context())
+        .await;
+
+    result.map_err(|error| fdo::Error::Failed(error.to_string()))
+}
+
+fn introspection_xml(name: &str) -> String {
+    format!(
+        r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="{PEER_INTERFACE}">
+    <method name="Ping"/>
+  </interface>
+  <interface name="{INTROSPECTABLE_INTERFACE}">
+    <method name="Introspect">
+      <arg type="s" name="xml_data" direction="out"/>
+    </method>
+  </interface>
+  <interface name="{PROPERTIES_INTERFACE}">
+    <method name="Get">
+      <arg type="s" name="interface_name" direction="in"/>
+      <arg type="s" name="property_name" direction="in"/>
+      <arg type="v" name="value" direction="out"/>
+    </method>
+    <method name="GetAll">
+      <arg type="s" name="interface_name" direction="in"/>
+      <arg type="a{{sv}}" name="properties" direction="out"/>
+    </method>
+    <method name="Set">
+      <arg type="s" name="interface_name" direction="in"/>
+      <arg type="s" name="property_name" direction="in"/>
+      <arg type="v" name="value" direction="in"/>
+    </method>
+    <signal name="PropertiesChanged">
+      <arg type="s" name="interface_name"/>
+      <arg type="a{{sv}}" name="changed_properties"/>
+      <arg type="as" name="invalidated_properties"/>
+    </signal>
+  </interface>
+  <interface name="{name}">
+    <method name="HoldProfile">
+      <arg type="s" name="profile" direction="in"/>
+      <arg type="s" name="reason" direction="in"/>
+      <arg type="s" name="application_id" direction="in"/>
+      <arg type="u" name="cookie" direction="out"/>
+    </method>
+    <method name="ReleaseProfile">
+      <arg type="u" name="cookie" direction="in"/>
+    </method>
+    <signal name="ProfileReleased">
+      <arg type="u" name="cookie"/>
+    </signal>
+    <property name="ActiveProfile" type="s" access="readwrite"/>
+    <property name="Profiles" type="aa{{sv}}" access="read"/>
+    <property name="ActiveProfileHolds" type="aa{{sv}}" access="read"/>
+    <property name="PerformanceDegraded" type="s" access="read"/>
+    <property name="PerformanceInhibited" type="s" access="read"/>
+    <property name="Actions" type="as" access="read"/>
+    <property name="Version" type="s" access="read"/>
+  </interface>
+</node>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::communication::server::tests::{call, denying_control_server, p2p_connection};
+
+    const NAME: &str = POWER_PROFILES_SERVICES[0].0;
+    const PATH: &str = POWER_PROFILES_SERVICES[0].1;
+
+    struct Fixture {
+        _dir: TempDir,
+        con: Connection,
+        control: InterfaceRef<ControlServer>,
+        state: SharedPowerProfilesState,
+    }
+
+    impl Fixture {
+        async fn new() -> Fixture {
+            let dir = TempDir::new().unwrap();
+            let (mut server, _) = denying_control_server(&dir);
+            let state = PowerProfilesState::new(&*server.instance.lock().await);
+            server.power_profiles = Some(state.clone());
+
+            let con = p2p_connection().await;
+            con.object_server().at(CONTROL_PATH, server).await.unwrap();
+            let control = con
+                .object_server()
+                .interface::<_, ControlServer>(CONTROL_PATH)
+                .await
+                .unwrap();
+
+            Fixture {
+                _dir: dir,
+                con,
+                control,
+                state,
+            }
+        }
+
+        async fn handle<B>(&self, interface: &str, member: &str, body: &B) -> fdo::Result<()>
+        where
+            B: serde::Serialize + zbus::zvariant::DynamicType,
+        {
+            let msg = call(PATH, Some(interface), member, body);
+            handle_call(&self.con, &self.control, &self.state, &msg).await
+        }
+    }
+
+    #[tokio::test]
+    async fn switching_is_authorized() {
+        let f = Fixture::new().await;
+
+        let result = f
+            .handle(
+                PROPERTIES_INTERFACE,
+                "Set",
+                &(NAME, "ActiveProfile", Value::from("performance")),
+            )
+            .await;
+
+        assert!(
+            matches!(result, Err(fdo::Error::AccessDenied(_))),
+            "{result:?}"
+        );
+        let control = f.control.get().await;
+        assert!(control.instance.lock().await.temporary_override.is_none());
+    }
+
+    #[tokio::test]
+    async fn other_properties_are_read_only() {
+        let f = Fixture::new().await;
+
+        let result = f
+            .handle(
+                PROPERTIES_INTERFACE,
+                "Set",
+                &(NAME, "Version", Value::from("0")),
+            )
+            .await;
+        assert!(
+            matches!(result, Err(fdo::Error::PropertyReadOnly(_))),
+            "{result:?}"
+        );
+
+        let result = f
+            .handle(
+                PROPERTIES_INTERFACE,
+                "Set",
+                &(
+                    "org.example.Other",
+                    "ActiveProfile",
+                    Value::from("balanced"),
+                ),
+            )
+            .await;
+        assert!(
+            matches!(result, Err(fdo::Error::UnknownInterface(_))),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn holds_are_not_supported() {
+        let f = Fixture::new().await;
+
+        let result = f
+            .handle(
+                NAME,
+                "HoldProfile",
+                &("performance", "gaming", "org.example"),
+            )
+            .await;
+        assert!(
+            matches!(result, Err(fdo::Error::NotSupported(_))),
+            "{result:?}"
+        );
+
+        let result = f.handle(NAME, "ReleaseProfile", &(1u32,)).await;
+        assert!(
+            matches!(result, Err(fdo::Error::NotSupported(_))),
+            "{result:?}"
+        );
+
+        let properties = f.state.lock().unwrap().properties();
+        assert_eq!(
+            properties["ActiveProfileHolds"],
+            Value::from(Vec::<HashMap<String, Value>>::new())
+        );
+    }
+}
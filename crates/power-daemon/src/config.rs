@@ -1,65 +1,186 @@
-use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 
-use crate::profiles_generator::DefaultProfileType;
-
-use itertools::Itertools;
+use crate::{
+    process_rules::ProcessRule, profile_rules::ProfileRule, profiles_generator::DefaultProfileType,
+    schema::SCHEMA_VERSION,
+};
 
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 pub struct Config {
+    /// Version of the file format, see `schema::SCHEMA_VERSION`. Files
+    /// written before schema versions lack it.
+    #[serde(default)]
+    pub schema_version: u32,
+
     pub ac_profile: String,
     pub bat_profile: String,
 
     pub profile_override: Option<String>,
 
+    /// Checked in order before falling back to the AC and battery profiles,
+    /// overrides always take precedence
+    #[serde(default)]
+    pub profile_rules: Vec<ProfileRule>,
+
+    /// Automatically override the profile while certain programs run. Takes
+    /// precedence over everything except temporary overrides.
+    #[serde(default)]
+    pub process_rules: Vec<ProcessRule>,
+
+    /// Ids of the profiles, in the order they're listed
     pub profiles: Vec<String>,
+
+    /// When enabled, settings a profile leaves unset are reverted to the
+    /// values the system had before the daemon first changed them, instead
+    /// of keeping whatever the previous profile applied
+    #[serde(default)]
+    pub unset_restores_baseline: bool,
+
+    #[serde(default)]
+    pub power_profiles_compat: PowerProfilesCompat,
+
+    #[serde(default)]
+    pub drift_detection: DriftDetection,
 }
 
 impl Config {
+    /// Every profile name the config refers to besides its list of profiles
+    pub fn profile_names(&self) -> Vec<&str> {
+        [self.ac_profile.as_str(), &self.bat_profile]
+            .into_iter()
+            .chain(self.profile_override.as_deref())
+            .chain(self.profile_rules.iter().map(|r| r.profile.as_str()))
+            .chain(self.process_rules.iter().map(|r| r.profile.as_str()))
+            .chain(
+                self.power_profiles_compat
+                    .mappings()
+                    .into_iter()
+                    .map(|(_, profile)| profile),
+            )
+            .collect()
+    }
+
     pub fn create_default() -> Config {
         Config {
+            schema_version: SCHEMA_VERSION,
+
             ac_profile: DefaultProfileType::Performance.get_name(),
             bat_profile: DefaultProfileType::Powersave.get_name(),
 
             profile_override: None,
 
-            profiles: DefaultProfileType::get_name_of_all(),
+            profile_rules: Vec::new(),
+            process_rules: Vec::new(),
+
+            profiles: DefaultProfileType::get_id_of_all(),
+
+            unset_restores_baseline: false,
+
+            power_profiles_compat: PowerProfilesCompat::default(),
+
+            drift_detection: DriftDetection::default(),
         }
     }
     pub fn create_empty() -> Config {
         Config {
+            schema_version: SCHEMA_VERSION,
+
             ac_profile: "Default".to_string(),
             bat_profile: "Default".to_string(),
 
             profile_override: None,
 
-            profiles: vec!["Default".to_string()],
+            profile_rules: Vec::new(),
+            process_rules: Vec::new(),
+
+            profiles: vec!["default".to_string()],
+
+            unset_restores_baseline: false,
+
+            power_profiles_compat: PowerProfilesCompat {
+                enabled: false,
+                power_saver: "Default".to_string(),
+                balanced: "Default".to_string(),
+                performance: "Default".to_string(),
+            },
+
+            drift_detection: DriftDetection::default(),
         }
     }
+}
 
-    /// Will attempt to parse `contentent`, if it fails will merge `content`
-    /// with the deafult config and attempt to merge that too
-    pub fn parse_or_default(content: &str) -> Config {
-        match toml::from_str::<Config>(content) {
-            Ok(c) => c,
-            Err(_) => {
-                warn!("Failed to parse config, attempting to migrate to newer version");
+/// Maps the profiles of power-profiles-daemon, which is what most desktop
+/// environments know how to switch, onto power-options profiles
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct PowerProfilesCompat {
+    /// Serve the `net.hadess.PowerProfiles` and
+    /// `org.freedesktop.UPower.PowerProfiles` interfaces. Only read when the
+    /// daemon starts, and power-profiles-daemon itself must not be running.
+    pub enabled: bool,
+    pub power_saver: String,
+    pub balanced: String,
+    pub performance: String,
+}
+
+impl Default for PowerProfilesCompat {
+    fn default() -> Self {
+        PowerProfilesCompat {
+            enabled: false,
+            power_saver: DefaultProfileType::Powersave.get_name(),
+            balanced: DefaultProfileType::Balanced.get_name(),
+            performance: DefaultProfileType::Performance.get_name(),
+        }
+    }
+}
 
-                let default_content = toml::to_string(&Config::create_default()).unwrap();
-                let merged = serde_toml_merge::merge(
-                    default_content.parse::<toml::Value>().unwrap(),
-                    content.parse::<toml::Value>().unwrap(),
-                )
-                .expect("Could not merge default config and user config");
+impl PowerProfilesCompat {
+    /// Every power-profiles-daemon profile name along with the power-options
+    /// profile it maps to, from the least to the most power hungry
+    pub fn mappings(&self) -> [(&'static str, &str); 3] {
+        [
+            ("power-saver", &self.power_saver),
+            ("balanced", &self.balanced),
+            ("performance", &self.performance),
+        ]
+    }
 
-                debug!("Merged config: {merged:?}");
+    pub fn profile_for(&self, power_profile: &str) -> Option<&str> {
+        self.mappings()
+            .into_iter()
+            .find(|(name, _)| *name == power_profile)
+            .map(|(_, profile)| profile)
+    }
 
-                let mut config = Config::deserialize(merged).unwrap();
+    /// The power-profiles-daemon profile reported while `profile_name` is
+    /// active, if any maps to it
+    pub fn power_profile_of(&self, profile_name: &str) -> Option<&'static str> {
+        self.mappings()
+            .into_iter()
+            .find(|(_, profile)| *profile == profile_name)
+            .map(|(name, _)| name)
+    }
+}
 
-                config.profiles = config.profiles.into_iter().unique().collect();
+/// Other software (thermald, vendor scripts, leftovers of other power
+/// managers...) may overwrite the values the active profile applied. The
+/// daemon can periodically look for those values and apply them again.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct DriftDetection {
+    /// Only read when the daemon starts
+    pub enabled: bool,
+    /// Seconds between checks
+    pub interval: u64,
+    /// Apply the settings groups with changed values again, instead of only
+    /// logging them
+    pub reapply: bool,
+}
 
-                config
-            }
+impl Default for DriftDetection {
+    fn default() -> Self {
+        DriftDetection {
+            enabled: false,
+            interval: 60,
+            reapply: true,
         }
     }
 }
@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    baseline::parse_current_value, inheritance::settings_table, Operation, Plan, PlannedOperation,
+    Profile,
+};
+
+/// Every setting that differs between two profiles
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ProfileDiff {
+    pub left: String,
+    pub right: String,
+    pub differences: Vec<SettingDifference>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SettingDifference {
+    /// Name of the settings group, e.g. `cpu_settings`
+    pub group: String,
+    /// Per-core fields are prefixed with the core id, e.g. `cpu3.governor`.
+    /// Entries of lists like `whiteblacklist.items` are compared one by one,
+    /// each entry that's only in one of the profiles is a difference.
+    pub field: String,
+    /// `None` if the left profile leaves the setting unset
+    pub left: Option<String>,
+    /// `None` if the right profile leaves the setting unset
+    pub right: Option<String>,
+}
+
+impl ProfileDiff {
+    pub fn between(left: &Profile, right: &Profile) -> ProfileDiff {
+        let left_groups = settings_table(left);
+        let right_groups = settings_table(right);
+
+        let groups: BTreeSet<_> = left_groups.keys().chain(right_groups.keys()).collect();
+
+        let mut differences = Vec::new();
+        for group in groups {
+            let left_fields = flatten_group(left_groups.get(group));
+            let right_fields = flatten_group(right_groups.get(group));
+
+            let fields: BTreeSet<_> = left_fields.keys().chain(right_fields.keys()).collect();
+            for field in fields {
+                let mut push = |left: Option<&String>, right: Option<&String>| {
+                    differences.push(SettingDifference {
+                        group: group.clone(),
+                        field: field.clone(),
+                        left: left.cloned(),
+                        right: right.cloned(),
+                    })
+                };
+
+                match (left_fields.get(field), right_fields.get(field)) {
+                    (Some(FlatValue::Set(left)), Some(FlatValue::Set(right))) => {
+                        for entry in left.difference(right) {
+                            push(Some(entry), None);
+                        }
+                        for entry in right.difference(left) {
+                            push(None, Some(entry));
+                        }
+                    }
+                    (left, right) if left != right => push(
+                        left.map(FlatValue::describe).as_ref(),
+                        right.map(FlatValue::describe).as_ref(),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+
+        ProfileDiff {
+            left: left.profile_name.clone(),
+            right: right.profile_name.clone(),
+            differences,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// How the system compares to what applying a profile would write
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct LiveDiff {
+    pub profile_name: String,
+    /// Number of attributes that already hold the value the profile sets
+    pub matching: usize,
+    pub differences: Vec<LiveDifference>,
+    /// Operations whose effect can't be read back, like commands or
+    /// write-only attributes
+    pub unverifiable: Vec<PlannedOperation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LiveDifference {
+    /// Name of the settings group that writes the attribute, e.g.
+    /// `cpu_settings`
+    pub group: String,
+    pub path: PathBuf,
+    pub intended: String,
+    pub live: String,
+}
+
+impl LiveDiff {
+    /// Reads back every attribute the plan writes. Only the last value
+    /// written into each attribute is compared, as earlier writes can be
+    /// intermediate steps.
+    pub fn of_plan(plan: &Plan) -> LiveDiff {
+        let mut diff = LiveDiff {
+            profile_name: plan.profile_name.clone(),
+            ..Default::default()
+        };
+
+        let mut intended: Vec<(&String, &PathBuf, &String)> = Vec::new();
+        for planned in &plan.operations {
+            match planned.operation {
+                Operation::Write {
+                    ref path,
+                    ref value,
+                } => {
+                    intended.retain(|(_, p, _)| *p != path);
+                    intended.push((&planned.group, path, value));
+                }
+                _ => diff.unverifiable.push(planned.clone()),
+            }
+        }
+
+        for (group, path, value) in intended {
+            let Ok(content) = fs::read_to_string(path) else {
+                diff.unverifiable.push(PlannedOperation {
+                    group: group.clone(),
+                    operation: Operation::Write {
+                        path: path.clone(),
+                        value: value.clone(),
+                    },
+                });
+                continue;
+            };
+
+            let live = parse_current_value(&content);
+            if live == value.trim() {
+                diff.matching += 1;
+            } else {
+                diff.differences.push(LiveDifference {
+                    group: group.clone(),
+                    path: path.clone(),
+                    intended: value.clone(),
+                    live,
+                });
+            }
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// How often the values the active profile applied were found changed by
+/// something else since the daemon started
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DriftStatus {
+    pub checks: u64,
+    /// Checks that found at least one changed value
+    pub drifted_checks: u64,
+    /// Times settings groups were applied again because of changed values
+    pub reapplied: u64,
+    /// Number of checks that found values of each settings group changed
+    pub groups: BTreeMap<String, u64>,
+    /// Number of checks that found each attribute changed
+    pub attributes: BTreeMap<PathBuf, u64>,
+    /// Changed values found by the last check that found any
+    pub last_differences: Vec<LiveDifference>,
+}
+
+impl DriftStatus {
+    pub fn record(&mut self, differences: Vec<LiveDifference>) {
+        self.checks += 1;
+        if differences.is_empty() {
+            return;
+        }
+
+        self.drifted_checks += 1;
+        let groups: BTreeSet<_> = differences.iter().map(|d| &d.group).collect();
+        for group in groups {
+            *self.groups.entry(group.clone()).or_default() += 1;
+        }
+        for difference in &differences {
+            *self.attributes.entry(difference.path.clone()).or_default() += 1;
+        }
+        self.last_differences = differences;
+    }
+}
+
+#[derive(PartialEq)]
+enum FlatValue {
+    Single(String),
+    Set(BTreeSet<String>),
+}
+
+impl FlatValue {
+    fn describe(&self) -> String {
+        match self {
+            FlatValue::Single(value) => value.clone(),
+            FlatValue::Set(entries) => format!(
+                "[{}]",
+                entries.iter().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+}
+
+/// Flattens the settings of a group into dotted fields
+fn flatten_group(group: Option<&toml::Value>) -> BTreeMap<String, FlatValue> {
+    let mut fields = BTreeMap::new();
+    if let Some(toml::Value::Table(table)) = group {
+        for (key, value) in table {
+            flatten(key.clone(), value, &mut fields);
+        }
+    }
+    fields
+}
+
+fn flatten(field: String, value: &toml::Value, fields: &mut BTreeMap<String, FlatValue>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                flatten(format!("{field}.{key}"), value, fields);
+            }
+        }
+        // Per-core settings, keyed by core rather than by position so that
+        // listing cores in another order is not a difference
+        toml::Value::Array(array)
+            if !array.is_empty() && array.iter().all(toml::Value::is_table) =>
+        {
+            for (idx, entry) in array.iter().enumerate() {
+                let toml::Value::Table(entry) = entry else {
+                    unreachable!()
+                };
+                let prefix = match entry.get("cpu_id") {
+                    Some(cpu_id) => format!("cpu{cpu_id}"),
+                    None => format!("{field}.{idx}"),
+                };
+                for (key, value) in entry.iter().filter(|(key, _)| *key != "cpu_id") {
+                    flatten(format!("{prefix}.{key}"), value, fields);
+                }
+            }
+        }
+        toml::Value::Array(array) => {
+            fields.insert(field, FlatValue::Set(array.iter().map(describe).collect()));
+        }
+        value => {
+            fields.insert(field, FlatValue::Single(describe(value)));
+        }
+    }
+}
+
+fn describe(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
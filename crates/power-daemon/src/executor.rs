@@ -0,0 +1,87 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Child,
+};
+
+use crate::{
+    helpers::{run_command, run_graphical_command, run_graphical_command_in_background},
+    sysfs::{
+        root::SystemRoot,
+        writing::{write_value, WriteError},
+    },
+};
+
+/// Every change settings make to the system goes through an executor, which
+/// allows the exact same code to either apply a profile or just record what
+/// applying it would do.
+pub trait Executor: Sync {
+    /// Where the sysfs and procfs paths settings write into are resolved
+    fn root(&self) -> &SystemRoot;
+
+    fn write(&self, path: &Path, value: &str) -> Result<(), WriteError>;
+
+    fn run_command(&self, command: &str) -> Result<(), String>;
+
+    fn run_graphical_command(&self, command: &str) -> Result<(), String>;
+
+    /// Returns the spawned process, or None if nothing was actually spawned
+    fn spawn_graphical_command(&self, command: &str) -> Option<Child>;
+
+    /// Whether this executor does not actually modify the system. Settings
+    /// that keep their own state outside of the system (like the xautolock
+    /// process) should leave it untouched when true.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+/// Applies everything directly onto the system
+#[derive(Default)]
+pub struct SystemExecutor {
+    root: SystemRoot,
+}
+
+impl SystemExecutor {
+    pub fn new(root: SystemRoot) -> SystemExecutor {
+        SystemExecutor { root }
+    }
+}
+
+impl Executor for SystemExecutor {
+    fn root(&self) -> &SystemRoot {
+        &self.root
+    }
+
+    fn write(&self, path: &Path, value: &str) -> Result<(), WriteError> {
+        write_value(path, value)
+    }
+
+    fn run_command(&self, command: &str) -> Result<(), String> {
+        run_command(command)
+    }
+
+    fn run_graphical_command(&self, command: &str) -> Result<(), String> {
+        run_graphical_command(command)
+    }
+
+    fn spawn_graphical_command(&self, command: &str) -> Option<Child> {
+        Some(run_graphical_command_in_background(command))
+    }
+}
+
+impl dyn Executor + '_ {
+    /// Resolves an absolute system path against the root of the executor
+    pub fn rooted(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.root().rooted(path)
+    }
+
+    /// Convenience wrapper around [`Executor::write`] for any displayable value
+    pub fn write_value(
+        &self,
+        path: impl AsRef<Path>,
+        value: impl Display,
+    ) -> Result<(), WriteError> {
+        self.write(path.as_ref(), &value.to_string())
+    }
+}
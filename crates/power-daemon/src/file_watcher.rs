@@ -0,0 +1,61 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use log::{debug, error, trace};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// Watches the directories on its own thread, the path of every TOML file
+/// written, moved in, moved out or removed is sent through the returned
+/// channel. Temporary files, backups and journals are left out. The channel
+/// closes if watching fails.
+pub fn spawn_file_watcher(directories: &[&Path]) -> io::Result<UnboundedReceiver<PathBuf>> {
+    let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+
+    let mut watched = HashMap::new();
+    for directory in directories {
+        let descriptor = inotify.add_watch(
+            *directory,
+            AddWatchFlags::IN_CLOSE_WRITE
+                | AddWatchFlags::IN_MOVED_TO
+                | AddWatchFlags::IN_MOVED_FROM
+                | AddWatchFlags::IN_DELETE,
+        )?;
+        watched.insert(descriptor, directory.to_path_buf());
+    }
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    thread::spawn(move || loop {
+        let events = match inotify.read_events() {
+            Ok(events) => events,
+            Err(error) => {
+                error!("Could not watch for file changes, stopping watcher: {error}");
+                return;
+            }
+        };
+
+        for event in events {
+            let (Some(directory), Some(name)) = (watched.get(&event.wd), event.name) else {
+                continue;
+            };
+            let path = directory.join(name);
+            if path.extension() != Some(OsStr::new("toml")) {
+                continue;
+            }
+
+            trace!("{} changed: {:?}", path.display(), event.mask);
+            if sender.send(path).is_err() {
+                debug!("File change receiver dropped, stopping watcher");
+                return;
+            }
+        }
+    });
+
+    Ok(receiver)
+}
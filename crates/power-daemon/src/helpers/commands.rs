@@ -12,26 +12,22 @@ pub fn command_exists(command: &str) -> bool {
         .map_or(false, |output| output.status.success())
 }
 
-pub fn run_command(command: &str) {
+/// Runs the command in a shell, returning an error describing the exit status
+/// if it did not succeed
+pub fn run_command(command: &str) -> Result<(), String> {
     debug!("running: {command}");
-    let output = Command::new("zsh")
+    let status = Command::new("sh")
         .args(["-c", command])
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
-        .spawn()
-        .expect("Could not run command")
-        .wait_with_output()
-        .expect("Could not wait command");
-
-    trace!(
-        "Command output: {}",
-        String::from_utf8(output.stdout).unwrap()
-    );
-    if !output.stderr.is_empty() {
-        error!(
-            "Command returned with stderr: {}",
-            String::from_utf8(output.stderr).unwrap()
-        );
+        .status()
+        .expect("Could not run command");
+
+    if status.success() {
+        Ok(())
+    } else {
+        error!("Command {command} returned with {status}");
+        Err(format!("`{command}` exited with {status}"))
     }
 }
 
@@ -53,35 +49,29 @@ pub fn run_command_with_output(command: &str) -> (String, String) {
     (stdout, stderr)
 }
 
-pub fn run_graphical_command(command: &str) {
+pub fn run_graphical_command(command: &str) -> Result<(), String> {
     debug!("running graphical command: {command}");
 
-    let output = Command::new("zsh")
+    let status = Command::new("sh")
         .args(["-c", command])
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .env("DISPLAY", ":0")
         .env("XAUTHORITY", get_xauthority())
-        .spawn()
-        .expect("Could not run command")
-        .wait_with_output()
-        .expect("Could not wait command");
-
-    trace!(
-        "Command output: {}",
-        String::from_utf8(output.stdout).unwrap()
-    );
-    if !output.stderr.is_empty() {
-        error!(
-            "Command returned with stderr: {}",
-            String::from_utf8(output.stderr).unwrap()
-        );
+        .status()
+        .expect("Could not run command");
+
+    if status.success() {
+        Ok(())
+    } else {
+        error!("Command {command} returned with {status}");
+        Err(format!("`{command}` exited with {status}"))
     }
 }
 
 pub fn run_graphical_command_in_background(command: &str) -> std::process::Child {
     debug!("running graphical command in background: {command}");
-    Command::new("zsh")
+    Command::new("sh")
         .args(["-c", command])
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
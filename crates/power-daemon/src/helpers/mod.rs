@@ -2,6 +2,8 @@ use std::fs;
 
 use serde::{Deserialize, Serialize};
 
+use crate::sysfs::root::SystemRoot;
+
 pub mod commands;
 
 pub use commands::*;
@@ -58,10 +60,10 @@ impl WhiteBlackListType {
     }
 }
 
-pub fn system_on_ac() -> bool {
+pub fn system_on_ac(root: &SystemRoot) -> bool {
     let mut ac_online = false;
 
-    if let Ok(entries) = fs::read_dir("/sys/class/power_supply/") {
+    if let Ok(entries) = fs::read_dir(root.rooted("/sys/class/power_supply/")) {
         for entry in entries.flatten() {
             let entry_path = entry.path();
             if let Ok(type_path) = fs::read_to_string(entry_path.join("type")) {
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{InstanceError, Profile, ProfilesInfo};
+
+/// A profile along with the settings it inherits from the profiles it
+/// extends, which is what gets applied
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ResolvedProfile {
+    pub profile: Profile,
+    /// Names of the profiles it inherits from, starting with the one it
+    /// extends directly
+    pub ancestors: Vec<String>,
+    /// Name of the profile each set setting comes from, keyed by
+    /// `<group>.<field>`, e.g. `cpu_settings.governor`
+    pub sources: BTreeMap<String, String>,
+}
+
+impl ResolvedProfile {
+    /// Name of the profile the setting is taken from, `None` if no profile
+    /// in the chain sets it
+    pub fn source_of(&self, group: &str, field: &str) -> Option<&str> {
+        self.sources
+            .get(&format!("{group}.{field}"))
+            .map(String::as_str)
+    }
+
+    /// Whether the setting is set by one of the ancestors rather than the
+    /// profile itself
+    pub fn is_inherited(&self, group: &str, field: &str) -> bool {
+        self.source_of(group, field)
+            .is_some_and(|source| source != self.profile.profile_name)
+    }
+}
+
+impl ProfilesInfo {
+    /// Indices of the profile at `idx` and of every profile it inherits
+    /// from, nearest first
+    pub fn inheritance_chain(&self, idx: usize) -> Result<Vec<usize>, InstanceError> {
+        let mut chain = vec![idx];
+        while let Some(ref parent) = self.profiles[*chain.last().unwrap()].extends {
+            let parent_idx = self
+                .try_find_profile_index_by_name(parent)
+                .ok_or_else(|| InstanceError::ProfileNotFound(parent.clone()))?;
+
+            if chain.contains(&parent_idx) {
+                let mut cycle: Vec<_> = chain
+                    .iter()
+                    .map(|&i| self.profiles[i].profile_name.clone())
+                    .collect();
+                cycle.push(parent.clone());
+                return Err(InstanceError::InheritanceCycle(cycle));
+            }
+            chain.push(parent_idx);
+        }
+        Ok(chain)
+    }
+
+    /// Fills in the settings the profile at `idx` leaves unset with those of
+    /// the profiles it extends
+    pub fn resolve_profile(&self, idx: usize) -> Result<ResolvedProfile, InstanceError> {
+        let chain = self.inheritance_chain(idx)?;
+
+        let mut sources = BTreeMap::new();
+        let mut resolved: Option<Profile> = None;
+        // Furthest ancestor first, so that nearer profiles take precedence
+        for &ancestor in chain.iter().rev() {
+            let profile = &self.profiles[ancestor];
+            for (group, fields) in settings_table(profile) {
+                if let toml::Value::Table(fields) = fields {
+                    for field in fields.keys() {
+                        sources.insert(format!("{group}.{field}"), profile.profile_name.clone());
+                    }
+                }
+            }
+            resolved = Some(match resolved {
+                Some(parent) => profile.merged_over(&parent),
+                None => profile.clone(),
+            });
+        }
+
+        Ok(ResolvedProfile {
+            profile: resolved.expect("The chain always contains the profile itself"),
+            ancestors: chain[1..]
+                .iter()
+                .map(|&i| self.profiles[i].profile_name.clone())
+                .collect(),
+            sources,
+        })
+    }
+}
+
+impl Profile {
+    /// Returns `self` with the settings it leaves unset taken from `parent`.
+    /// Id, name, base profile and parent are always those of `self`.
+    pub fn merged_over(&self, parent: &Profile) -> Profile {
+        let mut merged = settings_table(parent);
+        for (group, fields) in settings_table(self) {
+            match (merged.get_mut(&group), fields) {
+                (Some(toml::Value::Table(merged_fields)), toml::Value::Table(fields)) => {
+                    merged_fields.extend(fields)
+                }
+                (_, fields) => {
+                    merged.insert(group, fields);
+                }
+            }
+        }
+
+        merged.insert(
+            "schema_version".to_string(),
+            toml::Value::Integer(self.schema_version.into()),
+        );
+        merged.insert("id".to_string(), toml::Value::String(self.id.clone()));
+        merged.insert(
+            "profile_name".to_string(),
+            toml::Value::String(self.profile_name.clone()),
+        );
+
+        let mut profile: Profile = toml::Value::Table(merged)
+            .try_into()
+            .expect("Could not deserialize merged profile");
+        profile.base_profile = self.base_profile;
+        profile.extends = self.extends.clone();
+        profile
+    }
+}
+
+/// The settings groups of a profile, unset settings are left out
+pub(crate) fn settings_table(profile: &Profile) -> toml::Table {
+    match toml::Value::try_from(profile).expect("Could not serialize profile") {
+        toml::Value::Table(table) => table
+            .into_iter()
+            .filter(|(_, value)| value.is_table())
+            .collect(),
+        _ => unreachable!("Profiles serialize to a table"),
+    }
+}
@@ -1,28 +1,61 @@
+pub mod apply_report;
+pub mod baseline;
+pub mod bundle;
 #[cfg(feature = "communication")]
 pub mod communication;
 pub mod config;
+pub mod diff;
+pub mod executor;
+pub mod file_watcher;
+pub mod inheritance;
+pub mod persistence;
+pub mod plan;
+pub mod process_rules;
 pub mod profile;
+pub mod profile_rules;
 pub mod profiles_generator;
+pub mod schema;
 pub mod sysfs;
 pub mod systeminfo;
+pub mod uevent;
+pub mod validation;
 
 mod helpers;
 
 use serde::{Deserialize, Serialize};
 
+pub use apply_report::*;
+pub use baseline::{Baseline, BaselineRecorder};
+pub use bundle::{
+    Bundle, BundleAssignments, HardwareFingerprint, ImportReport, ImportedProfile,
+    UnsupportedSetting,
+};
 pub use config::*;
+pub use diff::{DriftStatus, LiveDiff, LiveDifference, ProfileDiff, SettingDifference};
 pub use helpers::{WhiteBlackList, WhiteBlackListType};
+pub use inheritance::ResolvedProfile;
+pub use plan::*;
+pub use process_rules::{ProcessMatcher, ProcessRule};
 pub use profile::*;
+pub use profile_rules::{
+    Clock, PowerSource, ProfileRule, RuleCondition, SystemClock, SystemState, TimeOfDay,
+};
 pub use profiles_generator::DefaultProfileType;
+pub use schema::{LoadError, SCHEMA_VERSION};
+pub use sysfs::root::SystemRoot;
 pub use systeminfo::*;
+pub use validation::ValidationIssue;
 
 use std::{
-    fs,
-    io::{Read, Write},
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
-use log::{debug, error, trace};
+use log::{debug, error, info, trace, warn};
+
+use persistence::{write_atomically, Transaction};
 
 #[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
 pub enum ReducedUpdate {
@@ -45,153 +78,1044 @@ pub enum ReducedUpdate {
     Gpu,
 }
 
+impl ReducedUpdate {
+    /// The update that applies only the settings group with the given name,
+    /// as it appears in the profile
+    pub fn for_group(group: &str) -> Option<ReducedUpdate> {
+        Some(match group {
+            "sleep_settings" => ReducedUpdate::Sleep,
+            "cpu_settings" => ReducedUpdate::CPU,
+            "cpu_core_settings" => ReducedUpdate::CPUCores,
+            "screen_settings" => ReducedUpdate::Screen,
+            "radio_settings" => ReducedUpdate::Radio,
+            "network_settings" => ReducedUpdate::Network,
+            "aspm_settings" => ReducedUpdate::ASPM,
+            "pci_settings" => ReducedUpdate::PCI,
+            "usb_settings" => ReducedUpdate::USB,
+            "sata_settings" => ReducedUpdate::SATA,
+            "kernel_settings" => ReducedUpdate::Kernel,
+            "firmware_settings" => ReducedUpdate::Firmware,
+            "audio_settings" => ReducedUpdate::Audio,
+            "gpu_settings" => ReducedUpdate::Gpu,
+            _ => return None,
+        })
+    }
+
+    /// Indices into the cores of `CPUCoreSettings` the update applies
+    fn core_indices(&self) -> &[u32] {
+        match self {
+            ReducedUpdate::SingleCPUCore(idx) => std::slice::from_ref(idx),
+            ReducedUpdate::MultipleCPUCores(indices) => indices,
+            _ => &[],
+        }
+    }
+}
+
+/// Profile picked by hand that takes precedence over everything else until
+/// it's removed, the daemon restarts or, if set, it expires
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct TemporaryOverride {
+    pub profile_name: String,
+    /// Wall clock time, so that time spent suspended counts too
+    pub expires_at: Option<SystemTime>,
+}
+
+impl TemporaryOverride {
+    /// Returns None if the override does not expire
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires_at.map(|expires_at| {
+            expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO)
+        })
+    }
+
+    pub fn has_expired(&self) -> bool {
+        self.remaining().is_some_and(|r| r.is_zero())
+    }
+}
+
+/// The config along with its revision, see [`ProfilesInfo::revisions`]
+#[derive(Clone, Deserialize, Serialize, PartialEq, Debug)]
+pub struct RevisionedConfig {
+    pub revision: u64,
+    pub config: Config,
+}
+
+/// Something that changed in the instance that clients may want to know
+/// about, see [`Instance::take_events`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InstanceEvent {
+    ActiveProfileChanged,
+    ConfigUpdated,
+    ProfilesChanged,
+    ProfileOverrideChanged,
+    ApplyFinished,
+    DriftDetected,
+}
+
+/// Why a request to change the instance was refused, nothing is changed when
+/// one of these is returned
+#[derive(Clone, PartialEq, Debug)]
+pub enum InstanceError {
+    ProfileNotFound(String),
+    ProfileIdNotFound(String),
+    ProfileIndexOutOfRange(usize),
+    /// Index into the cores of a profile's `CPUCoreSettings`
+    CoreIndexOutOfRange(u32),
+    ProfileNameTaken(String),
+    /// The name a profile has and the one it was sent with. Other profiles
+    /// and the config refer to profiles by name, so renaming goes through
+    /// `Instance::update_profile_name`.
+    ProfileNameChanged(String, String),
+    /// Name of a profile whose file could not be loaded, so that it can't be
+    /// changed until it's fixed or reset
+    ProfileNotLoaded(String),
+    /// What was changed by someone else since the expected revision was read,
+    /// and its current revision
+    RevisionConflict(String, u64),
+    CannotRemoveActiveProfile,
+    CannotRemoveLastProfile,
+    InvalidBundle(String),
+    /// Names of the profiles that extend each other, starting and ending
+    /// with the same one
+    InheritanceCycle(Vec<String>),
+    /// Name of the profile and every value of it that doesn't fit the
+    /// system
+    InvalidProfile(String, Vec<ValidationIssue>),
+}
+
+impl Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::ProfileNotFound(name) => write!(f, "there's no profile named {name}"),
+            InstanceError::ProfileIdNotFound(id) => write!(f, "there's no profile with id {id}"),
+            InstanceError::ProfileIndexOutOfRange(idx) => {
+                write!(f, "there's no profile with index {idx}")
+            }
+            InstanceError::CoreIndexOutOfRange(idx) => {
+                write!(f, "the profile has no core settings with index {idx}")
+            }
+            InstanceError::ProfileNameTaken(name) => {
+                write!(f, "a profile named {name} already exists")
+            }
+            InstanceError::ProfileNameChanged(name, new_name) => {
+                write!(
+                    f,
+                    "{name} can't be renamed to {new_name} along with its settings, rename it on its own"
+                )
+            }
+            InstanceError::ProfileNotLoaded(name) => {
+                write!(
+                    f,
+                    "{name} could not be loaded, fix its file or reset it first"
+                )
+            }
+            InstanceError::RevisionConflict(what, revision) => write!(
+                f,
+                "{what} was changed by someone else in the meantime (now at revision {revision}), reload it and try again"
+            ),
+            InstanceError::CannotRemoveActiveProfile => {
+                write!(f, "the currently active profile cannot be removed")
+            }
+            InstanceError::CannotRemoveLastProfile => {
+                write!(f, "the only remaining profile cannot be removed")
+            }
+            InstanceError::InvalidBundle(reason) => write!(f, "invalid bundle: {reason}"),
+            InstanceError::InheritanceCycle(cycle) => {
+                write!(
+                    f,
+                    "profiles can't extend each other: {}",
+                    cycle.join(" -> ")
+                )
+            }
+            InstanceError::InvalidProfile(name, issues) => {
+                let issues: Vec<_> = issues.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "{name} can't be used on this system: {}",
+                    issues.join("; ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
+
 pub struct Instance {
+    /// Where sysfs and procfs are read from and written into
+    root: SystemRoot,
+    /// Where profile rules get the time of the day from
+    clock: Box<dyn Clock>,
     profiles_path: PathBuf,
     config_path: PathBuf,
     config: Config,
     profiles_info: ProfilesInfo,
-    temporary_override: Option<String>,
+    temporary_override: Option<TemporaryOverride>,
+    /// The process rule currently overriding the profile, if any
+    automatic_override: Option<ProcessRule>,
+    last_apply_report: Option<ApplyReport>,
+    baseline: BaselineRecorder,
+    events: Vec<InstanceEvent>,
+    drift: DriftStatus,
+    /// Attributes that didn't hold the value written into them right after
+    /// applying, so they would always look changed
+    drift_ignored: HashSet<PathBuf>,
+    /// Files that could not be loaded. Profiles among them are kept as empty
+    /// placeholders that aren't written back until they're replaced.
+    load_errors: Vec<LoadError>,
+    /// Edits `reload_files` read fine but didn't take, because they don't fit
+    /// the system or refer to unknown profiles. Unlike load errors they don't
+    /// keep the files from being written, which replaces the edits.
+    rejected_files: Vec<LoadError>,
+    /// What edited files are validated against, obtained on the first reload
+    /// since the hardware stays the same in between
+    system_info: Option<SystemInfo>,
+    /// Shared by the config and every profile, so that a profile that's
+    /// removed and created again doesn't get a revision it had before
+    latest_revision: u64,
+    config_revision: u64,
+    /// What the config and the profiles were when their revisions were last
+    /// given out
+    revisioned_config: Config,
+    revisioned_profiles: HashMap<String, Profile>,
 }
 
 impl Instance {
-    pub fn new(config: Config, config_path: &Path, profiles_path: &Path) -> Instance {
-        let profiles = parse_profiles(&config, profiles_path);
-        Instance {
+    pub fn new(
+        config: Config,
+        config_path: &Path,
+        profiles_path: &Path,
+        baseline_path: &Path,
+        root: SystemRoot,
+    ) -> Instance {
+        let (profiles, load_errors) = parse_profiles(&config, profiles_path, &root);
+        let mut instance = Instance {
+            root: root.clone(),
+            clock: Box::new(SystemClock),
             profiles_path: PathBuf::from(profiles_path),
             config_path: PathBuf::from(config_path),
+            revisioned_config: config.clone(),
             config,
             profiles_info: ProfilesInfo {
                 profiles,
                 ..Default::default()
             },
             temporary_override: None,
-        }
+            automatic_override: None,
+            last_apply_report: None,
+            baseline: BaselineRecorder::load(baseline_path, root),
+            events: Vec::new(),
+            drift: DriftStatus::default(),
+            drift_ignored: HashSet::new(),
+            load_errors,
+            rejected_files: Vec::new(),
+            system_info: None,
+            latest_revision: 1,
+            config_revision: 1,
+            revisioned_profiles: HashMap::new(),
+        };
+        instance.update_revisions();
+        instance
+    }
+
+    pub fn get_system_root(&self) -> &SystemRoot {
+        &self.root
+    }
+
+    /// Replaces the clock profile rules are evaluated with
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Files that could not be loaded, along with why, followed by the
+    /// rejected edits that are still on disk
+    pub fn get_load_errors(&self) -> Vec<LoadError> {
+        self.load_errors
+            .iter()
+            .chain(
+                self.rejected_files
+                    .iter()
+                    .filter(|error| !self.file_is_current(&error.path)),
+            )
+            .cloned()
+            .collect()
+    }
+
+    /// For when the config could not be loaded and the instance was created
+    /// with a default one
+    pub fn record_load_error(&mut self, error: LoadError) {
+        self.load_errors.push(error);
     }
 
     pub fn set_profile_override(&mut self, name: String) {
-        self.temporary_override = Some(name);
+        self.temporary_override = Some(TemporaryOverride {
+            profile_name: name,
+            expires_at: None,
+        });
+        self.push_event(InstanceEvent::ProfileOverrideChanged);
         self.update_full();
     }
-    pub fn try_set_profile_override(&mut self, name: String) {
+    pub fn try_set_profile_override(&mut self, name: String) -> Result<(), InstanceError> {
         if self
             .profiles_info
             .try_find_profile_index_by_name(&name)
             .is_none()
         {
             debug!("Not updating profile override because profile name does not match with any existing profiles");
+            Err(InstanceError::ProfileNotFound(name))
         } else {
             self.set_profile_override(name);
+            Ok(())
+        }
+    }
+    pub fn set_timed_profile_override(&mut self, name: String, expires_at: SystemTime) {
+        self.temporary_override = Some(TemporaryOverride {
+            profile_name: name,
+            expires_at: Some(expires_at),
+        });
+        self.push_event(InstanceEvent::ProfileOverrideChanged);
+        self.update_full();
+    }
+    pub fn try_set_timed_profile_override(
+        &mut self,
+        name: String,
+        expires_at: SystemTime,
+    ) -> Result<(), InstanceError> {
+        if self
+            .profiles_info
+            .try_find_profile_index_by_name(&name)
+            .is_none()
+        {
+            debug!("Not updating profile override because profile name does not match with any existing profiles");
+            Err(InstanceError::ProfileNotFound(name))
+        } else {
+            self.set_timed_profile_override(name, expires_at);
+            Ok(())
         }
     }
     pub fn remove_profile_override(&mut self) {
         self.temporary_override = None;
+        self.push_event(InstanceEvent::ProfileOverrideChanged);
         self.update_full();
     }
+    /// Removes the temporary override if it expired
+    pub fn remove_expired_profile_override(&mut self) {
+        if self
+            .temporary_override
+            .as_ref()
+            .is_some_and(TemporaryOverride::has_expired)
+        {
+            info!("Temporary profile override expired");
+            self.remove_profile_override();
+        }
+    }
+    pub fn get_temporary_override(&self) -> Option<&TemporaryOverride> {
+        self.temporary_override.as_ref()
+    }
 
     pub fn update_full(&mut self) {
-        self.profiles_info.active_profile = self.pick_profile();
+        self.refresh_automatic_override();
+        self.set_active_profile(self.pick_profile());
+
+        let profile = self.resolve_active_profile();
+
+        // Reverting before applying avoids flipping attributes the profile
+        // sets back and forth
+        let restored = if self.config.unset_restores_baseline {
+            let plan = profile.plan(&self.root);
+            let written: HashSet<&Path> = plan
+                .operations
+                .iter()
+                .filter_map(|o| match o.operation {
+                    Operation::Write { ref path, .. } => Some(path.as_path()),
+                    _ => None,
+                })
+                .collect();
+            Some(self.baseline.restore_all_except(&written))
+        } else {
+            None
+        };
+
+        let mut report = profile.apply_all(&self.baseline);
+        self.baseline.save();
+
+        if let Some(restored) = restored.filter(|r| !r.settings.is_empty()) {
+            report.groups.push(restored);
+        }
+        self.store_apply_report(report);
 
-        self.profiles_info.get_active_profile().apply_all();
+        if self.config.drift_detection.enabled {
+            self.drift_ignored.clear();
+            self.ignore_unverifiable_attributes();
+        }
     }
-    pub fn update_reduced(&mut self, reduced_update: ReducedUpdate) {
-        self.profiles_info.active_profile = self.pick_profile();
-        self.profiles_info
-            .get_active_profile()
-            .apply_reduced(&reduced_update);
+    /// Picks the profile that should be active again and applies it only if
+    /// it's not the active one already. Meant to be called whenever something
+    /// profile rules depend on might have changed.
+    pub fn update_if_profile_changed(&mut self) {
+        self.refresh_automatic_override();
+        let picked = self.pick_profile();
+        if picked != self.profiles_info.active_profile {
+            debug!("Picked profile changed, applying");
+            self.update_full();
+        }
+    }
+
+    /// Looks for processes matching the configured process rules, applying
+    /// the profile again only if the rule in effect changed
+    pub fn update_if_automatic_override_changed(&mut self) {
+        if self.config.process_rules.is_empty() && self.automatic_override.is_none() {
+            return;
+        }
+
+        let previous = self.automatic_override.clone();
+        self.refresh_automatic_override();
+
+        if self.automatic_override != previous {
+            match self.automatic_override {
+                Some(ref rule) => info!("Automatically overriding profile with {}", rule.profile),
+                None => info!("Removing automatic profile override"),
+            }
+            self.update_full();
+        }
+    }
+
+    pub fn get_automatic_override(&self) -> Option<&ProcessRule> {
+        self.automatic_override.as_ref()
+    }
+
+    /// Only applies part of the active profile. Settings the profile leaves
+    /// unset are never reverted to the baseline here, only on full updates.
+    /// Fails if the update refers to cores the active profile has no
+    /// settings for.
+    pub fn update_reduced(&mut self, reduced_update: ReducedUpdate) -> Result<(), InstanceError> {
+        self.set_active_profile(self.pick_profile());
+        let profile = self.resolve_active_profile();
+        check_core_indices(&profile, &reduced_update)?;
+        let report = profile.apply_reduced(&self.baseline, &reduced_update);
+        self.baseline.save();
+        self.store_apply_report(report);
+        Ok(())
+    }
+
+    /// Looks for values of the active profile that were changed by something
+    /// else since they were applied, and applies their groups again if
+    /// configured to
+    pub fn check_drift(&mut self) {
+        let differences: Vec<_> = self
+            .diff_active_profile_with_system()
+            .differences
+            .into_iter()
+            .filter(|d| !self.drift_ignored.contains(&d.path))
+            .collect();
+
+        if differences.is_empty() {
+            self.drift.record(differences);
+            return;
+        }
+
+        for difference in &differences {
+            warn!(
+                "{} was changed to {:?} by something else, {} sets it to {:?}",
+                difference.path.display(),
+                difference.live,
+                difference.group,
+                difference.intended
+            );
+        }
+
+        let mut groups: Vec<_> = differences.iter().map(|d| d.group.clone()).collect();
+        groups.sort();
+        groups.dedup();
+        self.drift.record(differences);
+        self.push_event(InstanceEvent::DriftDetected);
+
+        if !self.config.drift_detection.reapply {
+            return;
+        }
+
+        info!("Applying {} again", groups.join(", "));
+        let profile = self.resolve_active_profile();
+        let mut report = ApplyReport::new(&profile.profile_name);
+        for group in groups {
+            if let Some(reduced_update) = ReducedUpdate::for_group(&group) {
+                report.groups.extend(
+                    profile
+                        .apply_reduced(&self.baseline, &reduced_update)
+                        .groups,
+                );
+            }
+        }
+        self.baseline.save();
+        self.store_apply_report(report);
+        self.drift.reapplied += 1;
+
+        self.ignore_unverifiable_attributes();
+    }
+
+    pub fn get_drift_status(&self) -> &DriftStatus {
+        &self.drift
+    }
+
+    /// Writes back the values the system had before the daemon touched it.
+    /// They stay in place until the next time a profile is applied.
+    pub fn restore_baseline(&mut self) -> GroupReport {
+        let report = self.baseline.restore();
+
+        if report.has_failures() {
+            warn!("Some baseline values could not be restored");
+        }
+        trace!("Baseline restore report: {report:#?}");
+
+        report
+    }
+
+    /// Returns and clears the events that happened since the last call, each
+    /// at most once and in the order they first happened
+    pub fn take_events(&mut self) -> Vec<InstanceEvent> {
+        // Every change ends with its events being taken, so revisions are
+        // always up to date before the next request is handled
+        self.update_revisions();
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn get_config_with_revision(&self) -> RevisionedConfig {
+        RevisionedConfig {
+            revision: self.config_revision,
+            config: self.config.clone(),
+        }
+    }
+
+    /// Fails if the config changed since `expected` was read
+    pub fn check_config_revision(&mut self, expected: u64) -> Result<(), InstanceError> {
+        self.update_revisions();
+        if expected != self.config_revision {
+            error!("Config changed since revision {expected}, ignoring...");
+            return Err(InstanceError::RevisionConflict(
+                "The config".to_string(),
+                self.config_revision,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the report of the last time the active profile was fully or
+    /// partially applied
+    pub fn get_last_apply_report(&self) -> Option<&ApplyReport> {
+        self.last_apply_report.as_ref()
     }
 
-    pub fn update_config(&mut self, config: Config) {
+    /// Fails if the config refers to a profile that's not among its profiles
+    pub fn update_config(&mut self, config: Config) -> Result<(), InstanceError> {
         debug!("Updating config...");
         trace!("New config: {config:#?}");
 
+        let (profiles, _) = parse_profiles(&config, &self.profiles_path, &self.root);
+        if let Some(unknown) = unknown_profile_name(&config, &profiles) {
+            error!("Config refers to {unknown}, which is not one of its profiles, ignoring...");
+            return Err(InstanceError::ProfileNotFound(unknown.to_string()));
+        }
+
         self.config = config;
-        serialize_config(&self.config, &self.config_path);
+        let config_path = self.config_path.clone();
+        self.load_errors.retain(|error| error.path != config_path);
+        self.save_config();
 
         // We might have updated the profiles too in the config, so reloading them is a must
-        self.profiles_info.profiles = parse_profiles(&self.config, &self.profiles_path);
+        self.reload_profiles();
 
         self.update_full();
+        Ok(())
+    }
+
+    /// Reads the config and the profiles again, for when they were edited by
+    /// hand. Files that can't be loaded and edits that don't fit the system
+    /// keep their previous contents, only the former aren't written until
+    /// they're replaced. The active profile is applied again only if what it
+    /// sets changed. Returns whether anything changed, which isn't the case
+    /// after the instance's own writes.
+    pub fn reload_files(&mut self) -> bool {
+        let mut errors = Vec::new();
+        let mut rejected = Vec::new();
+
+        let loaded = match parse_config(&self.config_path) {
+            Ok(config) => {
+                let (profiles, errors) = parse_profiles(&config, &self.profiles_path, &self.root);
+                match unknown_profile_name(&config, &profiles) {
+                    Some(unknown) => {
+                        let error = LoadError {
+                            path: self.config_path.clone(),
+                            reason: format!(
+                                "refers to {unknown}, which is not one of its profiles"
+                            ),
+                            backup: None,
+                        };
+                        error!("{error}, keeping the current config");
+                        rejected.push(error);
+                        None
+                    }
+                    None => Some((config, profiles, errors)),
+                }
+            }
+            Err(error) => {
+                error!("{error}, keeping the current config");
+                errors.push(error);
+                None
+            }
+        };
+        let (config, mut profiles, profile_errors) = loaded.unwrap_or_else(|| {
+            let (profiles, errors) = parse_profiles(&self.config, &self.profiles_path, &self.root);
+            (self.config.clone(), profiles, errors)
+        });
+        errors.extend(profile_errors);
+
+        let system_info = self
+            .system_info
+            .get_or_insert_with(|| SystemInfo::obtain(&self.root));
+        for profile in &mut profiles {
+            let path = profile_path(&self.profiles_path, &profile.id);
+            if !errors.iter().any(|error| error.path == path) {
+                let issues = profile.validate(system_info);
+                if issues.is_empty() {
+                    continue;
+                }
+                let error = LoadError {
+                    path,
+                    reason: InstanceError::InvalidProfile(profile.profile_name.clone(), issues)
+                        .to_string(),
+                    backup: None,
+                };
+                error!("{error}, keeping its previous contents");
+                rejected.push(error);
+            }
+
+            if let Some(previous) = self
+                .profiles_info
+                .profiles
+                .iter()
+                .find(|p| p.id == profile.id)
+            {
+                *profile = previous.clone();
+            }
+        }
+
+        if config == self.config
+            && profiles == self.profiles_info.profiles
+            && errors == self.load_errors
+            && rejected == self.rejected_files
+        {
+            debug!("Config and profiles are unchanged, nothing to reload");
+            return false;
+        }
+
+        info!("Config or profiles changed on disk, reloading");
+        let previous_name = self.get_active_profile_name();
+        let previous = self.resolve_active_profile();
+
+        if config != self.config {
+            self.config = config;
+            self.push_event(InstanceEvent::ConfigUpdated);
+        }
+        if profiles != self.profiles_info.profiles {
+            self.profiles_info.profiles = profiles;
+            self.push_event(InstanceEvent::ProfilesChanged);
+        }
+        self.load_errors = errors;
+        self.rejected_files = rejected;
+
+        if self.temporary_override.as_ref().is_some_and(|o| {
+            self.profiles_info
+                .try_find_profile_index_by_name(&o.profile_name)
+                .is_none()
+        }) {
+            info!("The overriding profile was removed, removing the override");
+            self.temporary_override = None;
+            self.push_event(InstanceEvent::ProfileOverrideChanged);
+        }
+        self.refresh_automatic_override();
+        // Indices may point to other profiles now, so the name is compared
+        self.profiles_info.active_profile = self.pick_profile();
+        if self.get_active_profile_name() != previous_name {
+            self.push_event(InstanceEvent::ActiveProfileChanged);
+        }
+
+        if self.resolve_active_profile() != previous {
+            self.update_full();
+        }
+        true
+    }
+
+    /// Same as `reload_files`, unless every one of the changed files holds
+    /// what the instance has for it, as they do after its own writes
+    pub fn reload_changed_files(&mut self, paths: &[PathBuf]) -> bool {
+        if paths.iter().all(|path| self.file_is_current(path)) {
+            debug!("Changed files hold what was written to them, nothing to reload");
+            return false;
+        }
+        self.reload_files()
+    }
+
+    /// Computes what applying the profile with the given name would do
+    pub fn plan_profile(&self, name: &str) -> Result<Plan, InstanceError> {
+        Ok(self.resolve_profile(name)?.profile.plan(&self.root))
+    }
+
+    /// Returns the profile with the given name as it would be applied, along
+    /// with where each of its settings comes from
+    pub fn resolve_profile(&self, name: &str) -> Result<ResolvedProfile, InstanceError> {
+        let idx = self
+            .profiles_info
+            .try_find_profile_index_by_name(name)
+            .ok_or_else(|| InstanceError::ProfileNotFound(name.to_string()))?;
+        self.profiles_info.resolve_profile(idx)
+    }
+
+    /// Compares the settings two profiles apply, inherited ones included
+    pub fn diff_profiles(&self, left: &str, right: &str) -> Result<ProfileDiff, InstanceError> {
+        Ok(ProfileDiff::between(
+            &self.resolve_profile(left)?.profile,
+            &self.resolve_profile(right)?.profile,
+        ))
+    }
+
+    /// Compares what the active profile writes with what the system holds
+    /// right now
+    pub fn diff_active_profile_with_system(&self) -> LiveDiff {
+        LiveDiff::of_plan(&self.resolve_active_profile().plan(&self.root))
+    }
+
+    /// Packs the profiles with the given names, or all of them if none are
+    /// given, into a bundle
+    pub fn export_bundle(
+        &self,
+        profile_names: &[String],
+        include_assignments: bool,
+    ) -> Result<Bundle, InstanceError> {
+        let profiles = if profile_names.is_empty() {
+            self.profiles_info.profiles.clone()
+        } else {
+            // The profiles they extend are needed to resolve them on the
+            // other machine, so they're exported too
+            let mut indices = Vec::new();
+            for name in profile_names {
+                let idx = self
+                    .profiles_info
+                    .try_find_profile_index_by_name(name)
+                    .ok_or_else(|| InstanceError::ProfileNotFound(name.clone()))?;
+                for idx in self.profiles_info.inheritance_chain(idx)? {
+                    if !indices.contains(&idx) {
+                        indices.push(idx);
+                    }
+                }
+            }
+            indices.sort();
+            indices
+                .into_iter()
+                .map(|idx| self.profiles_info.profiles[idx].clone())
+                .collect()
+        };
+
+        let assignments = include_assignments.then(|| BundleAssignments {
+            ac_profile: self.config.ac_profile.clone(),
+            bat_profile: self.config.bat_profile.clone(),
+            profile_rules: self.config.profile_rules.clone(),
+            process_rules: self.config.process_rules.clone(),
+        });
+
+        Ok(Bundle {
+            fingerprint: HardwareFingerprint::from_system_info(&SystemInfo::obtain(&self.root)),
+            assignments,
+            profiles,
+        })
+    }
+
+    /// Registers every profile of the bundle, renaming those whose name is
+    /// already taken, and applies its assignments if it has any. Nothing is
+    /// imported if the bundle is invalid.
+    pub fn import_bundle(&mut self, bundle: Bundle) -> Result<ImportReport, InstanceError> {
+        bundle.validate().map_err(InstanceError::InvalidBundle)?;
+
+        let mut taken: Vec<_> = self
+            .profiles_info
+            .profiles
+            .iter()
+            .map(|p| p.profile_name.clone())
+            .collect();
+        let mut imported = Vec::new();
+        for profile in &bundle.profiles {
+            let bundled_name = profile.profile_name.clone();
+            let mut name = bundled_name.clone();
+            let mut count = 1;
+            while taken.contains(&name) {
+                name = format!("{} #{}", bundled_name, count);
+                count += 1;
+            }
+            taken.push(name.clone());
+            imported.push(ImportedProfile { bundled_name, name });
+        }
+
+        let rename = |name: &str| {
+            imported
+                .iter()
+                .find(|i| i.bundled_name == name)
+                .map(|i| i.name.clone())
+                .unwrap_or_else(|| name.to_string())
+        };
+
+        let mut assignments = bundle.assignments.clone();
+        if let Some(ref mut assignments) = assignments {
+            assignments.rename_profiles(rename);
+            if let Some(unknown) = assignments
+                .profile_names()
+                .into_iter()
+                .find(|name| !taken.contains(*name))
+            {
+                return Err(InstanceError::InvalidBundle(format!(
+                    "the assignments refer to {unknown}, which is neither in the bundle nor on this system"
+                )));
+            }
+        }
+
+        let mut ids = Vec::new();
+        let profiles: Vec<Profile> = bundle
+            .profiles
+            .into_iter()
+            .zip(&imported)
+            .map(|(mut profile, imported)| {
+                profile.id = self.unused_profile_id(&imported.name, &ids);
+                ids.push(profile.id.clone());
+                profile.profile_name = imported.name.clone();
+                profile.extends = profile.extends.as_deref().map(rename);
+                profile
+            })
+            .collect();
+
+        let with_imported = ProfilesInfo {
+            active_profile: 0,
+            revisions: HashMap::new(),
+            profiles: self
+                .profiles_info
+                .profiles
+                .iter()
+                .chain(&profiles)
+                .cloned()
+                .collect(),
+        };
+        for idx in self.profiles_info.profiles.len()..with_imported.profiles.len() {
+            if let Err(error) = with_imported.inheritance_chain(idx) {
+                return Err(InstanceError::InvalidBundle(format!(
+                    "{} can't be resolved, {error}",
+                    with_imported.profiles[idx].profile_name
+                )));
+            }
+        }
+
+        let system_info = SystemInfo::obtain(&self.root);
+        for profile in &profiles {
+            let issues = profile.validate(&system_info);
+            if !issues.is_empty() {
+                return Err(InstanceError::InvalidBundle(
+                    InstanceError::InvalidProfile(profile.profile_name.clone(), issues).to_string(),
+                ));
+            }
+        }
+
+        self.config.profiles.extend(ids);
+
+        let assignments_applied = assignments.is_some();
+        if let Some(assignments) = assignments {
+            self.config.ac_profile = assignments.ac_profile;
+            self.config.bat_profile = assignments.bat_profile;
+            self.config.profile_rules = assignments.profile_rules;
+            self.config.process_rules = assignments.process_rules;
+        }
+
+        let mut transaction = self.transaction();
+        self.stage_config(&mut transaction);
+        for profile in &profiles {
+            self.stage_profile(&mut transaction, profile);
+        }
+        transaction.commit().expect("Could not import profiles");
+        self.push_event(InstanceEvent::ConfigUpdated);
+        self.reload_profiles();
+
+        if assignments_applied {
+            self.update_full();
+        }
+
+        let hardware_differences =
+            bundle
+                .fingerprint
+                .differences(&HardwareFingerprint::from_system_info(&SystemInfo::obtain(
+                    &self.root,
+                )));
+        let unsupported_settings = if hardware_differences.is_empty() {
+            Vec::new()
+        } else {
+            for difference in &hardware_differences {
+                warn!("Imported bundle comes from different hardware, {difference}");
+            }
+            (self.profiles_info.profiles.len() - profiles.len()..self.profiles_info.profiles.len())
+                .filter_map(|idx| self.profiles_info.resolve_profile(idx).ok())
+                .flat_map(|resolved| UnsupportedSetting::of_profile(&resolved.profile, &self.root))
+                .collect()
+        };
+
+        Ok(ImportReport {
+            imported,
+            assignments_applied,
+            hardware_differences,
+            unsupported_settings,
+        })
     }
 
     pub fn get_active_profile_name(&self) -> String {
         self.profiles_info.get_active_profile().profile_name.clone()
     }
 
-    pub fn create_profile(&mut self, profile_type: DefaultProfileType) {
-        debug!("Creating profile of type {profile_type:?}");
+    /// Index of the profile with the given id, for the methods that take one.
+    /// Unlike indices and names, ids keep pointing to the same profile while
+    /// other clients reorder, rename or remove profiles.
+    pub fn profile_index(&self, id: &str) -> Result<usize, InstanceError> {
+        self.profiles_info
+            .try_find_profile_index_by_id(id)
+            .ok_or_else(|| InstanceError::ProfileIdNotFound(id.to_string()))
+    }
 
-        let base_name = "New Profile";
-        let mut profile_name = base_name.to_string();
-        let mut count = 1;
-        while self.config.profiles.contains(&profile_name) {
-            profile_name = format!("{} #{}", base_name, count);
-            count += 1;
+    /// Same as `profile_index`, but fails if the profile changed since the
+    /// expected revision was read
+    pub fn checked_profile_index(
+        &mut self,
+        id: &str,
+        expected_revision: Option<u64>,
+    ) -> Result<usize, InstanceError> {
+        let idx = self.profile_index(id)?;
+        if let Some(expected) = expected_revision {
+            self.update_revisions();
+            let revision = self.profiles_info.revision_of(id);
+            if expected != revision {
+                error!("Profile {id} changed since revision {expected}, ignoring...");
+                return Err(InstanceError::RevisionConflict(
+                    self.profiles_info.profiles[idx].profile_name.clone(),
+                    revision,
+                ));
+            }
         }
+        Ok(idx)
+    }
 
-        profiles_generator::create_profile_file_with_name(
-            profile_name.clone(),
-            self.profiles_path.clone(),
+    /// Returns the id of the new profile
+    pub fn create_profile(&mut self, profile_type: DefaultProfileType) -> String {
+        debug!("Creating profile of type {profile_type:?}");
+
+        let profile_name = self.unused_profile_name();
+        let id = self.unused_profile_id(&profile_name, &[]);
+
+        let profile = profiles_generator::create_default(
+            &id,
+            &profile_name,
             profile_type,
-            &SystemInfo::obtain(),
+            &SystemInfo::obtain(&self.root),
         );
 
-        self.config.profiles.push(profile_name.clone());
-        serialize_config(&self.config, &self.config_path);
+        self.config.profiles.push(id.clone());
+        let mut transaction = self.transaction();
+        self.stage_config(&mut transaction);
+        self.stage_profile(&mut transaction, &profile);
+        transaction.commit().expect("Could not create profile");
+        self.push_event(InstanceEvent::ConfigUpdated);
         // parse_profiles obtains profiles according to the order defined in the
         // config. If the config's order changed then re-callign parse_profiles
         // should give a list of profiles in the new order
-        self.profiles_info.profiles = parse_profiles(&self.config, &self.profiles_path);
+        self.reload_profiles();
+        id
     }
 
-    pub fn reset_profile(&mut self, idx: usize) {
-        if self.verify_index_ranges(idx) {
-            return;
+    /// Creates a profile that extends `parent` without overriding any of its
+    /// settings, returning its id
+    pub fn create_derived_profile(&mut self, parent: String) -> Result<String, InstanceError> {
+        if self
+            .profiles_info
+            .try_find_profile_index_by_name(&parent)
+            .is_none()
+        {
+            return Err(InstanceError::ProfileNotFound(parent));
         }
 
+        debug!("Creating profile extending {parent}");
+
+        let profile_name = self.unused_profile_name();
+        let profile = Profile {
+            extends: Some(parent),
+            ..profiles_generator::create_empty(
+                &self.unused_profile_id(&profile_name, &[]),
+                &profile_name,
+            )
+        };
+
+        let id = profile.id.clone();
+        self.config.profiles.push(id.clone());
+        let mut transaction = self.transaction();
+        self.stage_config(&mut transaction);
+        self.stage_profile(&mut transaction, &profile);
+        transaction.commit().expect("Could not create profile");
+        self.push_event(InstanceEvent::ConfigUpdated);
+        self.profiles_info.profiles.push(profile);
+        self.push_event(InstanceEvent::ProfilesChanged);
+        Ok(id)
+    }
+
+    pub fn reset_profile(&mut self, idx: usize) -> Result<(), InstanceError> {
+        self.verify_index_ranges(idx)?;
+
         debug!("Resetting profile No {idx}");
-        let system_info = SystemInfo::obtain();
+        let system_info = SystemInfo::obtain(&self.root);
 
         self.profiles_info.profiles[idx] =
             self.profiles_info.profiles[idx].get_original_values(&system_info);
-        serialize_profiles(&self.profiles_info.profiles, &self.profiles_path);
+        self.forget_load_error(&self.profiles_info.profiles[idx].id.clone());
+        self.save_profiles();
+        self.push_event(InstanceEvent::ProfilesChanged);
 
         self.update_full();
+        Ok(())
     }
 
-    pub fn remove_profile(&mut self, idx: usize) {
+    pub fn remove_profile(&mut self, idx: usize) -> Result<(), InstanceError> {
         if self.profiles_info.profiles.len() <= 1 {
             error!(
                 "There's only 1 or less available profiles. Cannot remove remaining. Ignoring..."
             );
-            return;
+            return Err(InstanceError::CannotRemoveLastProfile);
         }
 
-        if self.verify_index_ranges(idx) {
-            return;
-        }
+        self.verify_index_ranges(idx)?;
 
         if self.profiles_info.active_profile == idx {
             error!("Cannot remove currently active profile, ignoring...");
-            return;
+            return Err(InstanceError::CannotRemoveActiveProfile);
         }
 
         if self.profiles_info.active_profile > idx {
             self.profiles_info.active_profile -= 1;
         }
 
-        let profile_to_remove = &self.profiles_info.profiles[idx];
-        let profile_to_remove_name = profile_to_remove.profile_name.clone();
+        let profile_to_remove_id = self.profiles_info.profiles[idx].id.clone();
+        let profile_to_remove_name = self.profiles_info.profiles[idx].profile_name.clone();
 
         let mut should_update = false;
 
         if let Some(ref temporary_override) = self.temporary_override {
-            if *temporary_override == profile_to_remove.profile_name {
+            if temporary_override.profile_name == profile_to_remove_name {
                 self.temporary_override = None;
+                self.push_event(InstanceEvent::ProfileOverrideChanged);
                 should_update = true;
             }
         }
         if let Some(ref persistent_override) = self.config.profile_override {
-            if *persistent_override == profile_to_remove.profile_name {
+            if *persistent_override == profile_to_remove_name {
                 self.config.profile_override = None;
                 should_update = true;
             }
@@ -201,10 +1125,22 @@ impl Instance {
             self.config
                 .profiles
                 .iter()
-                .position(|p| *p == profile_to_remove.profile_name)
+                .position(|p| *p == profile_to_remove_id)
                 .unwrap(),
         );
-        self.profiles_info.profiles.remove(idx);
+        let removed = self.profiles_info.profiles.remove(idx);
+        self.forget_load_error(&profile_to_remove_id);
+
+        // Profiles that extended the removed one take over its settings, so
+        // that what they apply doesn't change
+        let mut children = Vec::new();
+        for (idx, profile) in self.profiles_info.profiles.iter_mut().enumerate() {
+            if profile.extends.as_ref() == Some(&profile_to_remove_name) {
+                *profile = profile.merged_over(&removed);
+                profile.extends = removed.extends.clone();
+                children.push(idx);
+            }
+        }
 
         // This needs to be done after removing the actual profile from the
         // list, so that the .first() and .last() values would not point to
@@ -230,36 +1166,52 @@ impl Instance {
             should_update = true;
         }
 
-        fs::remove_file(
-            self.profiles_path
-                .join(format!("{}.toml", &profile_to_remove_name)),
-        )
-        .expect("Could not remove profile file");
+        let mut transaction = self.transaction();
+        self.stage_config(&mut transaction);
+        for idx in children {
+            self.stage_profile(&mut transaction, &self.profiles_info.profiles[idx]);
+        }
+        transaction.remove(&profile_path(&self.profiles_path, &profile_to_remove_id));
+        transaction.commit().expect("Could not remove profile file");
 
-        serialize_config(&self.config, &self.config_path);
+        self.push_event(InstanceEvent::ConfigUpdated);
+        self.push_event(InstanceEvent::ProfilesChanged);
 
         if should_update {
             self.update_full();
         }
+        Ok(())
     }
 
-    pub fn update_profile_name(&mut self, idx: usize, new_name: String) {
-        if self.verify_index_ranges(idx) {
-            return;
-        }
-        for profile in &self.config.profiles {
-            if new_name == *profile {
+    pub fn update_profile_name(
+        &mut self,
+        idx: usize,
+        new_name: String,
+    ) -> Result<(), InstanceError> {
+        self.verify_index_ranges(idx)?;
+        for profile in &self.profiles_info.profiles {
+            if new_name == profile.profile_name {
                 error!(
                     "Requested to update profile name to an already occupied name. Ignorring..."
                 );
-                return;
+                return Err(InstanceError::ProfileNameTaken(new_name));
             }
         }
 
-        let old_name = self.config.profiles[idx].clone();
+        let old_name = self.profiles_info.profiles[idx].profile_name.clone();
+        // The name is only stored in the profile's file, which is left as it
+        // is while it can't be loaded
+        if self.failed_to_load(&self.profiles_info.profiles[idx].id) {
+            error!("Requested to rename a profile that could not be loaded. Ignoring...");
+            return Err(InstanceError::ProfileNotLoaded(old_name));
+        }
 
-        self.config.profiles[idx] = new_name.clone();
         self.profiles_info.profiles[idx].profile_name = new_name.clone();
+        for profile in &mut self.profiles_info.profiles {
+            if profile.extends.as_ref() == Some(&old_name) {
+                profile.extends = Some(new_name.clone());
+            }
+        }
         if self.config.ac_profile == old_name {
             self.config.ac_profile = new_name.clone();
         }
@@ -271,22 +1223,39 @@ impl Instance {
                 self.config.profile_override = Some(new_name.clone());
             }
         }
-        if let Some(ref profile_override) = self.temporary_override {
-            if *profile_override == old_name {
-                self.temporary_override = Some(new_name.clone());
+        for mapped in [
+            &mut self.config.power_profiles_compat.power_saver,
+            &mut self.config.power_profiles_compat.balanced,
+            &mut self.config.power_profiles_compat.performance,
+        ] {
+            if *mapped == old_name {
+                *mapped = new_name.clone();
+            }
+        }
+        if let Some(ref mut profile_override) = self.temporary_override {
+            if profile_override.profile_name == old_name {
+                profile_override.profile_name = new_name.clone();
+                self.push_event(InstanceEvent::ProfileOverrideChanged);
             }
         }
 
-        serialize_config(&self.config, &self.config_path);
-        // Renaming a profile could cause a previous file with the same name
-        // left behind. Therefore we need to clear the directory first and then serialize
-        serialize_profiles_clean(&self.profiles_info.profiles, &self.profiles_path);
+        let mut transaction = self.transaction();
+        self.stage_config(&mut transaction);
+        for profile in &self.profiles_info.profiles {
+            self.stage_profile(&mut transaction, profile);
+        }
+        transaction.commit().expect("Could not rename profile");
+        self.push_event(InstanceEvent::ConfigUpdated);
+        self.push_event(InstanceEvent::ProfilesChanged);
+        if idx == self.profiles_info.active_profile {
+            self.push_event(InstanceEvent::ActiveProfileChanged);
+        }
+        Ok(())
     }
 
-    pub fn swap_profile_order(&mut self, idx: usize, new_idx: usize) {
-        if self.verify_index_ranges(idx) || self.verify_index_ranges(idx) {
-            return;
-        }
+    pub fn swap_profile_order(&mut self, idx: usize, new_idx: usize) -> Result<(), InstanceError> {
+        self.verify_index_ranges(idx)?;
+        self.verify_index_ranges(new_idx)?;
 
         if self.profiles_info.active_profile == idx {
             self.profiles_info.active_profile = new_idx;
@@ -297,28 +1266,36 @@ impl Instance {
         let tmp = self.config.profiles[idx].clone();
         self.config.profiles[idx] = self.config.profiles[new_idx].clone();
         self.config.profiles[new_idx] = tmp;
-        serialize_config(&self.config, &self.config_path);
-        self.profiles_info.profiles = parse_profiles(&self.config, &self.profiles_path);
+        self.save_config();
+        self.reload_profiles();
+        Ok(())
     }
 
-    pub fn update_profile_full(&mut self, idx: usize, profile: Profile) {
-        self.update_profile(idx, profile);
+    pub fn update_profile_full(
+        &mut self,
+        idx: usize,
+        profile: Profile,
+    ) -> Result<(), InstanceError> {
+        self.update_profile(idx, profile)?;
 
-        if idx == self.profiles_info.active_profile {
+        if self.is_inherited_by_active_profile(idx) {
             self.update_full();
         }
+        Ok(())
     }
     pub fn update_profile_reduced(
         &mut self,
         idx: usize,
         profile: Profile,
         reduced_update: ReducedUpdate,
-    ) {
-        self.update_profile(idx, profile);
+    ) -> Result<(), InstanceError> {
+        check_core_indices(&profile, &reduced_update)?;
+        self.update_profile(idx, profile)?;
 
-        if idx == self.profiles_info.active_profile {
-            self.update_reduced(reduced_update);
+        if self.is_inherited_by_active_profile(idx) {
+            self.update_reduced(reduced_update)?;
         }
+        Ok(())
     }
 
     /// Returns the index of the profile that should be selcted at the moment
@@ -327,12 +1304,19 @@ impl Instance {
         if let Some(ref temporary_override) = self.temporary_override {
             debug!("Picking temporary profile override");
             self.profiles_info
-                .find_profile_index_by_name(temporary_override)
+                .find_profile_index_by_name(&temporary_override.profile_name)
+        } else if let Some(ref automatic_override) = self.automatic_override {
+            debug!("Picking automatic profile override");
+            self.profiles_info
+                .find_profile_index_by_name(&automatic_override.profile)
         } else if let Some(ref profile_override) = self.config.profile_override {
             debug!("Picking settings profile override");
             self.profiles_info
                 .find_profile_index_by_name(profile_override)
-        } else if helpers::system_on_ac() {
+        } else if let Some(rule) = self.pick_rule() {
+            debug!("Picking profile from rule: {rule:?}");
+            self.profiles_info.find_profile_index_by_name(&rule.profile)
+        } else if helpers::system_on_ac(&self.root) {
             debug!("Picking AC profile");
             self.profiles_info
                 .find_profile_index_by_name(&self.config.ac_profile)
@@ -343,93 +1327,582 @@ impl Instance {
         }
     }
 
-    fn update_profile(&mut self, idx: usize, profile: Profile) {
-        if self.verify_index_ranges(idx) {
-            return;
+    fn refresh_automatic_override(&mut self) {
+        self.automatic_override = if self.config.process_rules.is_empty() {
+            None
+        } else {
+            process_rules::pick_process_rule(
+                &self.config.process_rules,
+                &process_rules::obtain_running_processes(&self.root),
+                |name| {
+                    self.profiles_info
+                        .try_find_profile_index_by_name(name)
+                        .is_some()
+                },
+            )
+            .cloned()
+        };
+    }
+
+    fn pick_rule(&self) -> Option<&ProfileRule> {
+        if self.config.profile_rules.is_empty() {
+            return None;
+        }
+
+        profile_rules::pick_rule(
+            &self.config.profile_rules,
+            &SystemState::obtain(&self.root, self.clock.as_ref()),
+            |name| {
+                self.profiles_info
+                    .try_find_profile_index_by_name(name)
+                    .is_some()
+            },
+        )
+    }
+
+    /// The active profile with its inherited settings. Falls back to its own
+    /// settings if the profiles it extends can't be resolved, which can only
+    /// happen if the files were edited by hand.
+    fn resolve_active_profile(&self) -> Profile {
+        match self
+            .profiles_info
+            .resolve_profile(self.profiles_info.active_profile)
+        {
+            Ok(resolved) => resolved.profile,
+            Err(error) => {
+                error!(
+                    "Could not resolve the active profile, applying only its own settings: {error}"
+                );
+                self.profiles_info.get_active_profile().clone()
+            }
+        }
+    }
+
+    /// Some drivers report values differently than they're written, or
+    /// silently reject them. Meant to be called right after applying.
+    fn ignore_unverifiable_attributes(&mut self) {
+        for difference in self.diff_active_profile_with_system().differences {
+            if self.drift_ignored.insert(difference.path.clone()) {
+                debug!(
+                    "{} doesn't hold the value written into it, it won't be checked for changes",
+                    difference.path.display()
+                );
+            }
+        }
+    }
+
+    /// Whether changing the profile at `idx` changes what the active profile
+    /// applies, either because it's the active one or one it extends
+    fn is_inherited_by_active_profile(&self, idx: usize) -> bool {
+        self.profiles_info
+            .inheritance_chain(self.profiles_info.active_profile)
+            .map_or(idx == self.profiles_info.active_profile, |chain| {
+                chain.contains(&idx)
+            })
+    }
+
+    fn unused_profile_name(&self) -> String {
+        let base_name = "New Profile";
+        let mut profile_name = base_name.to_string();
+        let mut count = 1;
+        while self
+            .profiles_info
+            .try_find_profile_index_by_name(&profile_name)
+            .is_some()
+        {
+            profile_name = format!("{} #{}", base_name, count);
+            count += 1;
+        }
+        profile_name
+    }
+
+    /// Derives the id from the name, `reserved` are ids that are about to be
+    /// taken. Files left in the profiles directory are never overwritten.
+    fn unused_profile_id(&self, name: &str, reserved: &[String]) -> String {
+        let mut base_id = String::new();
+        for c in name.chars() {
+            if c.is_ascii_alphanumeric() {
+                base_id.push(c.to_ascii_lowercase());
+            } else if !base_id.is_empty() && !base_id.ends_with('-') {
+                base_id.push('-');
+            }
         }
+        let base_id = match base_id.trim_end_matches('-') {
+            "" => "profile",
+            trimmed => trimmed,
+        };
+
+        let mut id = base_id.to_string();
+        let mut count = 2;
+        while self.config.profiles.contains(&id)
+            || reserved.contains(&id)
+            || profile_path(&self.profiles_path, &id).exists()
+        {
+            id = format!("{base_id}-{count}");
+            count += 1;
+        }
+        id
+    }
+
+    fn update_profile(&mut self, idx: usize, mut profile: Profile) -> Result<(), InstanceError> {
+        self.verify_index_ranges(idx)?;
+        profile.id = self.profiles_info.profiles[idx].id.clone();
 
         debug!("Updating profile No {idx}");
         trace!("New profile: {profile:#?}");
 
-        self.profiles_info.profiles[idx] = profile;
+        let name = &self.profiles_info.profiles[idx].profile_name;
+        if profile.profile_name != *name {
+            error!("Requested to rename profile No {idx} along with its settings, ignoring...");
+            return Err(InstanceError::ProfileNameChanged(
+                name.clone(),
+                profile.profile_name,
+            ));
+        }
+
+        let issues = profile.validate(&SystemInfo::obtain(&self.root));
+        if !issues.is_empty() {
+            error!("Profile No {idx} has values that don't fit the system, ignoring...");
+            return Err(InstanceError::InvalidProfile(profile.profile_name, issues));
+        }
+
+        let previous = std::mem::replace(&mut self.profiles_info.profiles[idx], profile);
+        if let Err(error) = self.profiles_info.inheritance_chain(idx) {
+            error!("Profile No {idx} extends a profile it can't be resolved with, ignoring...");
+            self.profiles_info.profiles[idx] = previous;
+            return Err(error);
+        }
+
         // We actually need to update the underlying files
-        serialize_profiles(&self.profiles_info.profiles, &self.profiles_path);
+        self.forget_load_error(&self.profiles_info.profiles[idx].id.clone());
+        self.save_profiles();
+        self.push_event(InstanceEvent::ProfilesChanged);
+        Ok(())
+    }
+
+    /// Keeps track of whether the name of the active profile changed, an
+    /// index alone can change just because profiles were reordered
+    fn set_active_profile(&mut self, idx: usize) {
+        let previous = self.get_active_profile_name();
+        self.profiles_info.active_profile = idx;
+        if self.get_active_profile_name() != previous {
+            self.push_event(InstanceEvent::ActiveProfileChanged);
+        }
+    }
+
+    /// Parses the profiles again, in the order of the config
+    fn reload_profiles(&mut self) {
+        let (profiles, errors) = parse_profiles(&self.config, &self.profiles_path, &self.root);
+        self.profiles_info.profiles = profiles;
+        let config_path = self.config_path.clone();
+        self.load_errors.retain(|error| error.path == config_path);
+        self.load_errors.extend(errors);
+        self.push_event(InstanceEvent::ProfilesChanged);
+    }
+
+    /// Writes every profile except the placeholders of those that could not
+    /// be loaded, which would overwrite the files
+    fn save_profiles(&self) {
+        let mut transaction = self.transaction();
+        for profile in &self.profiles_info.profiles {
+            self.stage_profile(&mut transaction, profile);
+        }
+        transaction
+            .commit()
+            .expect("Could not write to profile files");
+    }
+
+    /// For changes to several files that have to happen together
+    fn transaction(&self) -> Transaction {
+        Transaction::new(&persistence::journal_path(&self.config_path))
+    }
+
+    /// Same as `save_config`, as part of a transaction
+    fn stage_config(&self, transaction: &mut Transaction) {
+        if !self.config_failed_to_load() {
+            transaction.write(
+                &self.config_path,
+                toml::to_string_pretty(&self.config).expect("Could not serialize config"),
+            );
+        }
+    }
+
+    /// Same as `save_profiles` for a single profile, as part of a transaction
+    fn stage_profile(&self, transaction: &mut Transaction, profile: &Profile) {
+        if !self.failed_to_load(&profile.id) {
+            transaction.write(
+                &profile_path(&self.profiles_path, &profile.id),
+                toml::to_string_pretty(profile).expect("Could not serialize profile"),
+            );
+        }
+    }
+
+    fn config_failed_to_load(&self) -> bool {
+        self.load_errors
+            .iter()
+            .any(|error| error.path == self.config_path)
+    }
+
+    fn failed_to_load(&self, id: &str) -> bool {
+        let path = profile_path(&self.profiles_path, id);
+        self.load_errors.iter().any(|error| error.path == path)
+    }
+
+    /// Whether the file holds what the instance has for it, which is the case
+    /// after the instance's own writes
+    fn file_is_current(&self, path: &Path) -> bool {
+        let expected = if path == self.config_path {
+            Some(toml::to_string_pretty(&self.config).expect("Could not serialize config"))
+        } else {
+            self.profiles_info
+                .profiles
+                .iter()
+                .find(|profile| profile_path(&self.profiles_path, &profile.id) == path)
+                .map(|profile| {
+                    toml::to_string_pretty(profile).expect("Could not serialize profile")
+                })
+        };
+        std::fs::read_to_string(path).ok() == expected
+    }
+
+    /// For when the placeholder of a profile that could not be loaded is
+    /// deliberately replaced
+    fn forget_load_error(&mut self, id: &str) {
+        let path = profile_path(&self.profiles_path, id);
+        self.load_errors.retain(|error| error.path != path);
+    }
+
+    /// The defaults used in place of a config that could not be loaded aren't
+    /// written until the config is replaced with `update_config`
+    fn save_config(&mut self) {
+        if !self.config_failed_to_load() {
+            serialize_config(&self.config, &self.config_path);
+        }
+        self.push_event(InstanceEvent::ConfigUpdated);
     }
 
-    fn verify_index_ranges(&self, idx: usize) -> bool {
+    /// Gives the config, if it changed, and every profile that changed since
+    /// the last call a new revision
+    fn update_revisions(&mut self) {
+        if self.config != self.revisioned_config {
+            self.latest_revision += 1;
+            self.config_revision = self.latest_revision;
+            self.revisioned_config = self.config.clone();
+        }
+
+        let profiles = &self.profiles_info.profiles;
+        let unchanged = profiles.len() == self.revisioned_profiles.len()
+            && profiles
+                .iter()
+                .all(|p| self.revisioned_profiles.get(&p.id) == Some(p));
+        if unchanged {
+            return;
+        }
+
+        let mut revisions = HashMap::new();
+        for profile in profiles {
+            let revision = match self.profiles_info.revisions.get(&profile.id) {
+                Some(&revision) if self.revisioned_profiles.get(&profile.id) == Some(profile) => {
+                    revision
+                }
+                _ => {
+                    self.latest_revision += 1;
+                    self.latest_revision
+                }
+            };
+            revisions.insert(profile.id.clone(), revision);
+        }
+        self.profiles_info.revisions = revisions;
+        self.revisioned_profiles = profiles.iter().map(|p| (p.id.clone(), p.clone())).collect();
+    }
+
+    fn push_event(&mut self, event: InstanceEvent) {
+        if !self.events.contains(&event) {
+            self.events.push(event);
+        }
+    }
+
+    fn store_apply_report(&mut self, report: ApplyReport) {
+        if report.has_failures() {
+            warn!(
+                "Some settings of profile {} could not be applied",
+                report.profile_name
+            );
+        }
+        trace!("Apply report: {report:#?}");
+        self.last_apply_report = Some(report);
+        self.push_event(InstanceEvent::ApplyFinished);
+    }
+
+    fn verify_index_ranges(&self, idx: usize) -> Result<(), InstanceError> {
         if idx >= self.config.profiles.len() || idx >= self.profiles_info.profiles.len() {
             error!("Profile with requested index is outside of bounds, ignoring...");
-            true
+            Err(InstanceError::ProfileIndexOutOfRange(idx))
         } else {
-            false
+            Ok(())
         }
     }
 }
 
-pub fn parse_config(path: &Path) -> Config {
-    let content = fs::read_to_string(path).expect("Could not read config");
-
-    let config = Config::parse_or_default(&content);
+/// Migrates the config if it was written by an older version
+pub fn parse_config(path: &Path) -> Result<Config, LoadError> {
+    schema::load(path, schema::CONFIG_MIGRATIONS, |_| {
+        Config::create_default()
+    })
+}
 
-    fs::write(path, toml::to_string_pretty(&config).unwrap()).expect("Could not write to config");
+/// Core indices of reduced updates come from clients. Profiles without core
+/// settings have nothing to apply either way.
+fn check_core_indices(
+    profile: &Profile,
+    reduced_update: &ReducedUpdate,
+) -> Result<(), InstanceError> {
+    let Some(ref cores) = profile.cpu_core_settings.cores else {
+        return Ok(());
+    };
+    match reduced_update
+        .core_indices()
+        .iter()
+        .find(|idx| **idx as usize >= cores.len())
+    {
+        Some(idx) => {
+            error!(
+                "Reduced update refers to core settings No {idx}, which don't exist, ignoring..."
+            );
+            Err(InstanceError::CoreIndexOutOfRange(*idx))
+        }
+        None => Ok(()),
+    }
+}
 
+/// Returns the first profile name the config refers to that none of the
+/// profiles has
+fn unknown_profile_name<'a>(config: &'a Config, profiles: &[Profile]) -> Option<&'a str> {
     config
+        .profile_names()
+        .into_iter()
+        .find(|name| !profiles.iter().any(|p| p.profile_name == *name))
 }
 
-fn parse_profiles(config: &Config, path: &Path) -> Vec<Profile> {
+/// Profiles that can't be loaded are replaced by empty placeholders
+fn parse_profiles(
+    config: &Config,
+    path: &Path,
+    root: &SystemRoot,
+) -> (Vec<Profile>, Vec<LoadError>) {
     let mut profiles = Vec::new();
-    for profile_name in config.profiles.iter() {
-        let path = path.join(format!("{profile_name}.toml"));
-        let mut file = fs::File::open(&path).expect("Could not read file");
-        let mut contents = String::new();
-
-        file.read_to_string(&mut contents)
-            .expect("Could not read file");
+    let mut errors = Vec::new();
+    for id in config.profiles.iter() {
+        let profile_path = profile_path(path, id);
+        let loaded = if is_valid_profile_id(id) {
+            schema::load(&profile_path, schema::PROFILE_MIGRATIONS, |profile| {
+                let name = profile
+                    .get("profile_name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or(id);
+                let profile_type = profile
+                    .get("base_profile")
+                    .and_then(|t| t.clone().try_into::<DefaultProfileType>().ok());
+                match profile_type {
+                    Some(profile_type) => profiles_generator::create_default(
+                        id,
+                        name,
+                        profile_type,
+                        &SystemInfo::obtain(root),
+                    ),
+                    None => profiles_generator::create_empty(id, name),
+                }
+            })
+        } else {
+            Err(LoadError {
+                path: profile_path.clone(),
+                reason: format!("{id:?} is not a valid profile id"),
+                backup: None,
+            })
+        };
+
+        let mut profile = loaded.unwrap_or_else(|error| {
+            error!("{error}, using an empty profile in its place");
+            errors.push(error);
+            let name = if is_valid_profile_id(id) {
+                stored_profile_name(&profile_path, id)
+            } else {
+                id.clone()
+            };
+            profiles_generator::create_empty(id, &name)
+        });
+        profile.id = id.clone();
+        profiles.push(profile);
+    }
 
-        let mut profile = Profile::parse_or_default(&contents, profile_name);
+    (profiles, errors)
+}
 
-        fs::write(path, toml::to_string_pretty(&profile).unwrap())
-            .expect("Could not write to profile");
+/// Ids name files, so they can't point outside of the profiles directory
+fn is_valid_profile_id(id: &str) -> bool {
+    !id.is_empty() && !id.starts_with('.') && !id.contains(['/', '\0'])
+}
 
-        profile.profile_name = profile_name.clone();
-        profiles.push(profile);
-    }
+/// The name of a profile that could not be loaded, as far as it can be read
+fn stored_profile_name(path: &Path, id: &str) -> String {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+        .and_then(|table| table.get("profile_name")?.as_str().map(String::from))
+        .unwrap_or_else(|| id.to_string())
+}
 
-    profiles
+pub(crate) fn profile_path(profiles_path: &Path, id: &str) -> PathBuf {
+    profiles_path.join(format!("{id}.toml"))
 }
 
 pub fn serialize_config(config: &Config, path: &Path) {
-    fs::write(
+    write_atomically(
         path,
         toml::to_string_pretty(config).expect("Could not serialize config"),
     )
     .expect("Could not write to config");
 }
 
-fn serialize_profiles_clean(profiles: &[Profile], path: &Path) {
-    for entry in fs::read_dir(path).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// An instance with a single empty profile, kept in `dir`
+    fn instance_in(dir: &TempDir) -> Instance {
+        let config_path = dir.path().join("config.toml");
+        let profiles_path = dir.path().join("profiles");
+        fs::create_dir(&profiles_path).unwrap();
+        // The least the system info profiles are validated against needs
+        fs::create_dir_all(dir.path().join("sys/devices/system/cpu/cpu0")).unwrap();
+        fs::write(dir.path().join("sys/devices/system/cpu/cpu0/online"), "0").unwrap();
+        fs::create_dir_all(dir.path().join("sys/bus/pci/devices")).unwrap();
+        fs::create_dir_all(dir.path().join("sys/class/drm")).unwrap();
+        serialize_config(&Config::create_empty(), &config_path);
+        write_profile(dir, &profiles_generator::create_empty("default", "Default"));
+
+        Instance::new(
+            parse_config(&config_path).unwrap(),
+            &config_path,
+            &profiles_path,
+            &dir.path().join("baseline.json"),
+            SystemRoot::new(dir.path()),
+        )
+    }
+
+    fn write_profile(dir: &TempDir, profile: &Profile) {
+        fs::write(
+            profile_path(&dir.path().join("profiles"), &profile.id),
+            toml::to_string_pretty(profile).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn rejected_edits_can_be_overwritten() {
+        let dir = TempDir::new().unwrap();
+        let mut instance = instance_in(&dir);
+        let mut edited = profiles_generator::create_empty("default", "Default");
+        edited.cpu_settings.min_perf_pct = Some(150);
+        write_profile(&dir, &edited);
+
+        assert!(instance.reload_files());
+        assert_eq!(
+            instance.profiles_info.profiles[0].cpu_settings.min_perf_pct,
+            None
+        );
+        assert_eq!(instance.get_load_errors().len(), 1);
 
-        if path.is_dir() {
-            fs::remove_dir_all(&path).unwrap();
-        } else {
-            fs::remove_file(&path).unwrap();
+        instance
+            .update_profile_name(0, "Quiet".to_string())
+            .unwrap();
+
+        let path = profile_path(&dir.path().join("profiles"), "default");
+        let written: Profile = toml::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(written.profile_name, "Quiet");
+        assert_eq!(written.cpu_settings.min_perf_pct, None);
+        assert!(instance.get_load_errors().is_empty());
+    }
+
+    #[test]
+    fn own_writes_are_not_reloaded() {
+        let dir = TempDir::new().unwrap();
+        let mut instance = instance_in(&dir);
+        let changed = [
+            dir.path().join("config.toml"),
+            profile_path(&dir.path().join("profiles"), "default"),
+        ];
+
+        instance
+            .update_profile_name(0, "Quiet".to_string())
+            .unwrap();
+        assert!(!instance.reload_changed_files(&changed));
+
+        let mut edited = instance.config.clone();
+        edited.unset_restores_baseline = true;
+        serialize_config(&edited, &changed[0]);
+        assert!(instance.reload_changed_files(&changed));
+        assert_eq!(instance.config, edited);
+    }
+
+    #[test]
+    fn reduced_updates_of_missing_cores_are_refused() {
+        let dir = TempDir::new().unwrap();
+        let mut instance = instance_in(&dir);
+        let mut edited = instance.profiles_info.profiles[0].clone();
+        edited.cpu_core_settings.cores = Some(vec![CoreSetting {
+            cpu_id: 0,
+            online: Some(false),
+            ..Default::default()
+        }]);
+
+        for reduced_update in [
+            ReducedUpdate::SingleCPUCore(1),
+            ReducedUpdate::MultipleCPUCores(vec![0, 4]),
+        ] {
+            assert!(matches!(
+                instance.update_profile_reduced(0, edited.clone(), reduced_update.clone()),
+                Err(InstanceError::CoreIndexOutOfRange(_))
+            ));
         }
+        assert_eq!(
+            instance.profiles_info.profiles[0].cpu_core_settings.cores,
+            None
+        );
     }
 
-    serialize_profiles(profiles, path);
-}
+    #[test]
+    fn updates_cannot_rename_profiles() {
+        let dir = TempDir::new().unwrap();
+        let mut instance = instance_in(&dir);
+        let mut edited = instance.profiles_info.profiles[0].clone();
+        edited.profile_name = "Quiet".to_string();
+
+        assert!(matches!(
+            instance.update_profile_full(0, edited),
+            Err(InstanceError::ProfileNameChanged(..))
+        ));
+        assert_eq!(instance.profiles_info.profiles[0].profile_name, "Default");
+        assert_eq!(instance.config.ac_profile, "Default");
+    }
 
-fn serialize_profiles(profiles: &[Profile], path: &Path) {
-    for profile in profiles.iter() {
-        let path = path.join(format!("{}.toml", profile.profile_name));
-        let mut file = fs::File::create(&path).expect("Could not read file");
-        file.write_all(
-            toml::to_string_pretty(profile)
-                .expect("Could not serialize profile")
-                .as_bytes(),
-        )
-        .expect("Could not write to profile file");
+    #[test]
+    fn unparsable_files_are_not_overwritten() {
+        let dir = TempDir::new().unwrap();
+        let mut instance = instance_in(&dir);
+        let path = profile_path(&dir.path().join("profiles"), "default");
+        fs::write(&path, "cpu_settings = [").unwrap();
+
+        assert!(instance.reload_files());
+        assert_eq!(instance.get_load_errors().len(), 1);
+
+        assert!(instance
+            .update_profile_name(0, "Quiet".to_string())
+            .is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "cpu_settings = [");
     }
 }
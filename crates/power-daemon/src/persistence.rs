@@ -0,0 +1,212 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Replaces the file in a way that leaves either the old or the new content
+/// behind if the system goes down midway, never a truncated file
+pub fn write_atomically(path: &Path, content: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(content.as_ref())?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&temporary, path)?;
+    sync_parent(path)
+}
+
+/// Where the journal of the config at `config_path` is kept
+pub fn journal_path(config_path: &Path) -> PathBuf {
+    config_path.with_extension("journal")
+}
+
+/// A step of a [`Transaction`]. Every step can be carried out again after
+/// it already was, so an interrupted transaction can be replayed from the
+/// start.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum FileOperation {
+    Write { path: PathBuf, content: String },
+    Remove { path: PathBuf },
+}
+
+impl FileOperation {
+    fn run(&self) -> io::Result<()> {
+        match self {
+            FileOperation::Write { path, content } => write_atomically(path, content),
+            FileOperation::Remove { path } => match fs::remove_file(path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+                _ => sync_parent(path),
+            },
+        }
+    }
+}
+
+/// Changes to several files that must happen together. The steps are
+/// written to a journal before any of them is carried out, and the journal
+/// is removed once they all were. A journal left behind by a crash is
+/// finished by [`recover`].
+pub struct Transaction {
+    journal_path: PathBuf,
+    operations: Vec<FileOperation>,
+}
+
+impl Transaction {
+    pub fn new(journal_path: &Path) -> Transaction {
+        Transaction {
+            journal_path: journal_path.to_path_buf(),
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn write(&mut self, path: &Path, content: String) -> &mut Self {
+        self.operations.push(FileOperation::Write {
+            path: path.to_path_buf(),
+            content,
+        });
+        self
+    }
+
+    pub fn remove(&mut self, path: &Path) -> &mut Self {
+        self.operations.push(FileOperation::Remove {
+            path: path.to_path_buf(),
+        });
+        self
+    }
+
+    pub fn commit(&self) -> io::Result<()> {
+        debug!(
+            "Committing {} file operations through {}",
+            self.operations.len(),
+            self.journal_path.display()
+        );
+
+        let journal = serde_json::to_string(&self.operations).expect("Could not serialize journal");
+        write_atomically(&self.journal_path, journal)?;
+
+        run(&self.journal_path, &self.operations)
+    }
+}
+
+/// Finishes the transaction a crash interrupted, if there's one. Must run
+/// before the files the journal touches are read.
+pub fn recover(journal_path: &Path) -> io::Result<()> {
+    let content = match fs::read_to_string(journal_path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+
+    let operations: Vec<FileOperation> = match serde_json::from_str(&content) {
+        Ok(operations) => operations,
+        Err(error) => {
+            // The journal is written atomically, so this isn't one of ours
+            warn!(
+                "Ignoring unreadable journal {}: {error}",
+                journal_path.display()
+            );
+            return fs::remove_file(journal_path);
+        }
+    };
+
+    info!(
+        "Finishing {} file operations interrupted by a crash",
+        operations.len()
+    );
+    run(journal_path, &operations)
+}
+
+fn run(journal_path: &Path, operations: &[FileOperation]) -> io::Result<()> {
+    for operation in operations {
+        operation.run()?;
+    }
+    fs::remove_file(journal_path)?;
+    sync_parent(journal_path)
+}
+
+/// Renames and removals only survive a crash once their directory is synced
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn committing_writes_every_file() {
+        let dir = TempDir::new().unwrap();
+        let journal = journal_path(&dir.path().join("config.toml"));
+        let config = dir.path().join("config.toml");
+        let profile = dir.path().join("quiet.toml");
+        let removed = dir.path().join("old.toml");
+        fs::write(&removed, "").unwrap();
+
+        let mut transaction = Transaction::new(&journal);
+        transaction
+            .write(&config, "profiles = [\"quiet\"]".to_string())
+            .write(&profile, "id = \"quiet\"".to_string())
+            .remove(&removed);
+        transaction.commit().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "profiles = [\"quiet\"]"
+        );
+        assert_eq!(fs::read_to_string(&profile).unwrap(), "id = \"quiet\"");
+        assert!(!removed.exists());
+        assert!(!journal.exists());
+    }
+
+    /// A crash right after the journal was written leaves none of the files
+    /// changed, or only some of them
+    #[test]
+    fn recovering_finishes_an_interrupted_commit() {
+        let dir = TempDir::new().unwrap();
+        let journal = journal_path(&dir.path().join("config.toml"));
+        let config = dir.path().join("config.toml");
+        let profile = dir.path().join("quiet.toml");
+        fs::write(&config, "profiles = []").unwrap();
+        let operations = vec![
+            FileOperation::Write {
+                path: config.clone(),
+                content: "profiles = [\"quiet\"]".to_string(),
+            },
+            FileOperation::Write {
+                path: profile.clone(),
+                content: "id = \"quiet\"".to_string(),
+            },
+        ];
+        fs::write(&journal, serde_json::to_string(&operations).unwrap()).unwrap();
+
+        recover(&journal).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&config).unwrap(),
+            "profiles = [\"quiet\"]"
+        );
+        assert_eq!(fs::read_to_string(&profile).unwrap(), "id = \"quiet\"");
+        assert!(!journal.exists());
+    }
+
+    #[test]
+    fn recovering_without_a_journal_does_nothing() {
+        let dir = TempDir::new().unwrap();
+
+        recover(&journal_path(&dir.path().join("config.toml"))).unwrap();
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    fmt::{self, Display},
+    mem,
+    path::{Path, PathBuf},
+    process::Child,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apply_report::ApplyReport,
+    executor::Executor,
+    sysfs::{root::SystemRoot, writing::WriteError},
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Operation {
+    Write { path: PathBuf, value: String },
+    Command(String),
+    GraphicalCommand(String),
+    BackgroundGraphicalCommand(String),
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Write { path, value } => write!(f, "write {value:?} to {}", path.display()),
+            Operation::Command(command) => write!(f, "run `{command}`"),
+            Operation::GraphicalCommand(command) => write!(f, "run graphical `{command}`"),
+            Operation::BackgroundGraphicalCommand(command) => {
+                write!(f, "spawn graphical `{command}`")
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlannedOperation {
+    /// Name of the settings group that performs the operation, e.g. `cpu_settings`
+    pub group: String,
+    pub operation: Operation,
+}
+
+/// The ordered list of operations applying a profile would perform. Within a
+/// group the order is exact, but groups themselves are applied in parallel
+/// when actually applying a profile.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Plan {
+    pub profile_name: String,
+    pub operations: Vec<PlannedOperation>,
+    /// What the outcome of each setting would be if all the operations
+    /// succeeded. Useful to see which settings are unsupported.
+    pub report: ApplyReport,
+}
+
+/// Executor that records every operation instead of performing it
+#[derive(Default)]
+pub struct PlanRecorder {
+    root: SystemRoot,
+    operations: Mutex<Vec<Operation>>,
+}
+
+impl PlanRecorder {
+    pub fn new(root: SystemRoot) -> PlanRecorder {
+        PlanRecorder {
+            root,
+            operations: Mutex::default(),
+        }
+    }
+
+    /// Returns all the operations recorded since the last call
+    pub fn take(&self) -> Vec<Operation> {
+        mem::take(&mut self.operations.lock().unwrap())
+    }
+
+    fn record(&self, operation: Operation) {
+        self.operations.lock().unwrap().push(operation);
+    }
+}
+
+impl Executor for PlanRecorder {
+    fn root(&self) -> &SystemRoot {
+        &self.root
+    }
+
+    fn write(&self, path: &Path, value: &str) -> Result<(), WriteError> {
+        // Attributes that don't exist would fail with the real executor too,
+        // this way they're reported as unsupported instead of planned
+        if !path.exists() {
+            return Err(WriteError::NotFound(PathBuf::from(path)));
+        }
+
+        self.record(Operation::Write {
+            path: PathBuf::from(path),
+            value: value.to_string(),
+        });
+        Ok(())
+    }
+
+    fn run_command(&self, command: &str) -> Result<(), String> {
+        self.record(Operation::Command(command.to_string()));
+        Ok(())
+    }
+
+    fn run_graphical_command(&self, command: &str) -> Result<(), String> {
+        self.record(Operation::GraphicalCommand(command.to_string()));
+        Ok(())
+    }
+
+    fn spawn_graphical_command(&self, command: &str) -> Option<Child> {
+        self.record(Operation::BackgroundGraphicalCommand(command.to_string()));
+        None
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,290 @@
+use std::fs;
+
+use log::{trace, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::sysfs::root::SystemRoot;
+
+/// Activates a profile while any process matching any of the matchers is
+/// running
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProcessRule {
+    pub profile: String,
+    pub matchers: Vec<ProcessMatcher>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ProcessMatcher {
+    /// Exact executable name as it appears in `/proc/<pid>/comm`, which the
+    /// kernel truncates to 15 characters
+    Comm(String),
+    /// Regex searched for in the full command line, arguments separated by spaces
+    CmdlineRegex(String),
+    /// Name of a systemd unit or slice the process' cgroup is part of, e.g.
+    /// `app-steam@autostart.service`
+    Unit(String),
+}
+
+/// What rules can match a process against
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunningProcess {
+    pub comm: String,
+    pub cmdline: String,
+    pub cgroup: String,
+}
+
+pub fn obtain_running_processes(root: &SystemRoot) -> Vec<RunningProcess> {
+    let Ok(entries) = fs::read_dir(root.rooted("/proc")) else {
+        warn!("Could not read procfs");
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.chars().all(|c| c.is_ascii_digit()))
+        })
+        // Processes may exit while we're reading them
+        .filter_map(|entry| {
+            let path = entry.path();
+            Some(RunningProcess {
+                comm: fs::read_to_string(path.join("comm"))
+                    .ok()?
+                    .trim()
+                    .to_string(),
+                cmdline: fs::read_to_string(path.join("cmdline"))
+                    .ok()?
+                    .split('\0')
+                    .filter(|a| !a.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                cgroup: fs::read_to_string(path.join("cgroup")).unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+enum CompiledMatcher<'a> {
+    Comm(&'a str),
+    CmdlineRegex(Regex),
+    Unit(&'a str),
+}
+
+impl<'a> CompiledMatcher<'a> {
+    fn compile(matcher: &'a ProcessMatcher) -> Option<CompiledMatcher<'a>> {
+        Some(match matcher {
+            ProcessMatcher::Comm(comm) => CompiledMatcher::Comm(comm),
+            ProcessMatcher::CmdlineRegex(regex) => match Regex::new(regex) {
+                Ok(regex) => CompiledMatcher::CmdlineRegex(regex),
+                Err(error) => {
+                    warn!("Invalid process rule regex {regex}: {error}");
+                    return None;
+                }
+            },
+            ProcessMatcher::Unit(unit) => CompiledMatcher::Unit(unit),
+        })
+    }
+
+    fn matches(&self, process: &RunningProcess) -> bool {
+        match self {
+            CompiledMatcher::Comm(comm) => process.comm == *comm,
+            CompiledMatcher::CmdlineRegex(regex) => regex.is_match(&process.cmdline),
+            // Lines look like 0::/user.slice/user-1000.slice/app.slice/steam.service
+            CompiledMatcher::Unit(unit) => process.cgroup.lines().any(|line| {
+                line.rsplit(':')
+                    .next()
+                    .is_some_and(|path| path.split('/').any(|component| component == *unit))
+            }),
+        }
+    }
+}
+
+/// Returns the first rule with a matching running process and whose profile
+/// passes `profile_exists`
+pub fn pick_process_rule<'a>(
+    rules: &'a [ProcessRule],
+    processes: &[RunningProcess],
+    profile_exists: impl Fn(&str) -> bool,
+) -> Option<&'a ProcessRule> {
+    rules.iter().find(|rule| {
+        let matchers: Vec<_> = rule
+            .matchers
+            .iter()
+            .filter_map(CompiledMatcher::compile)
+            .collect();
+
+        let Some(process) = processes
+            .iter()
+            .find(|process| matchers.iter().any(|m| m.matches(process)))
+        else {
+            return false;
+        };
+
+        trace!("Process {} matches rule for {}", process.comm, rule.profile);
+
+        if !profile_exists(&rule.profile) {
+            warn!(
+                "Process rule matches but profile {} does not exist, ignoring",
+                rule.profile
+            );
+            return false;
+        }
+
+        true
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn spawn(root: &SystemRoot, pid: u32, comm: &str, cmdline: &[&str], cgroup: &str) {
+        let dir = root.rooted(format!("/proc/{pid}"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("comm"), format!("{comm}\n")).unwrap();
+        fs::write(dir.join("cmdline"), format!("{}\0", cmdline.join("\0"))).unwrap();
+        fs::write(dir.join("cgroup"), cgroup).unwrap();
+    }
+
+    fn exit(root: &SystemRoot, pid: u32) {
+        fs::remove_dir_all(root.rooted(format!("/proc/{pid}"))).unwrap();
+    }
+
+    fn rule(profile: &str, matchers: Vec<ProcessMatcher>) -> ProcessRule {
+        ProcessRule {
+            profile: profile.to_string(),
+            matchers,
+        }
+    }
+
+    #[test]
+    fn processes_are_read_from_procfs() {
+        let dir = TempDir::new().unwrap();
+        let root = SystemRoot::new(dir.path());
+        spawn(
+            &root,
+            1234,
+            "steam",
+            &["/usr/bin/steam", "-silent"],
+            "0::/user.slice/user-1000.slice/app.slice/app-steam@autostart.service\n",
+        );
+        // Not a process
+        fs::create_dir_all(root.rooted("/proc/sys/vm")).unwrap();
+
+        assert_eq!(
+            obtain_running_processes(&root),
+            vec![RunningProcess {
+                comm: "steam".to_string(),
+                cmdline: "/usr/bin/steam -silent".to_string(),
+                cgroup: "0::/user.slice/user-1000.slice/app.slice/app-steam@autostart.service\n"
+                    .to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn rules_follow_processes_starting_and_exiting() {
+        let dir = TempDir::new().unwrap();
+        let root = SystemRoot::new(dir.path());
+        fs::create_dir_all(root.rooted("/proc")).unwrap();
+
+        let rules = [
+            rule(
+                "Gaming",
+                vec![ProcessMatcher::Unit(
+                    "app-steam@autostart.service".to_string(),
+                )],
+            ),
+            rule(
+                "Compiling",
+                vec![
+                    // Invalid regexes are ignored, the other matchers still apply
+                    ProcessMatcher::CmdlineRegex("(".to_string()),
+                    ProcessMatcher::CmdlineRegex(r"^cargo (build|test)\b".to_string()),
+                ],
+            ),
+            rule("Video", vec![ProcessMatcher::Comm("mpv".to_string())]),
+        ];
+        let picked = || {
+            pick_process_rule(&rules, &obtain_running_processes(&root), |_| true)
+                .map(|rule| rule.profile.as_str())
+        };
+        assert_eq!(picked(), None);
+
+        spawn(&root, 10, "mpv", &["mpv", "video.mkv"], "0::/user.slice\n");
+        assert_eq!(picked(), Some("Video"));
+
+        spawn(
+            &root,
+            20,
+            "cargo",
+            &["cargo", "build", "--release"],
+            "0::/user.slice\n",
+        );
+        assert_eq!(picked(), Some("Compiling"));
+
+        spawn(
+            &root,
+            30,
+            "steam",
+            &["/usr/bin/steam"],
+            "0::/user.slice/app.slice/app-steam@autostart.service\n",
+        );
+        // Rules are in order of priority
+        assert_eq!(picked(), Some("Gaming"));
+
+        exit(&root, 30);
+        exit(&root, 20);
+        assert_eq!(picked(), Some("Video"));
+
+        exit(&root, 10);
+        assert_eq!(picked(), None);
+    }
+
+    #[test]
+    fn matchers_are_exact() {
+        let process = RunningProcess {
+            comm: "firefox-bin".to_string(),
+            cmdline: "/usr/lib/firefox/firefox-bin".to_string(),
+            cgroup: "0::/user.slice/app.slice/app-firefox.scope\n".to_string(),
+        };
+        let matches = |matcher: ProcessMatcher| {
+            pick_process_rule(
+                &[rule("Browsing", vec![matcher])],
+                std::slice::from_ref(&process),
+                |_| true,
+            )
+            .is_some()
+        };
+
+        assert!(!matches(ProcessMatcher::Comm("firefox".to_string())));
+        assert!(matches(ProcessMatcher::Comm("firefox-bin".to_string())));
+        assert!(!matches(ProcessMatcher::Unit("app".to_string())));
+        assert!(matches(ProcessMatcher::Unit(
+            "app-firefox.scope".to_string()
+        )));
+        assert!(matches(ProcessMatcher::CmdlineRegex("firefox".to_string())));
+    }
+
+    #[test]
+    fn rules_for_missing_profiles_are_skipped() {
+        let rules = [
+            rule("Missing", vec![ProcessMatcher::Comm("mpv".to_string())]),
+            rule("Video", vec![ProcessMatcher::Comm("mpv".to_string())]),
+        ];
+        let processes = [RunningProcess {
+            comm: "mpv".to_string(),
+            cmdline: "mpv".to_string(),
+            cgroup: String::new(),
+        }];
+
+        let picked = pick_process_rule(&rules, &processes, |name| name != "Missing");
+        assert_eq!(picked.map(|rule| rule.profile.as_str()), Some("Video"));
+    }
+}
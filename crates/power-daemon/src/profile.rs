@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Mutex;
 use std::{fs, io};
@@ -7,12 +8,12 @@ use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    helpers::{
-        command_exists, run_command, run_graphical_command, run_graphical_command_in_background,
-        WhiteBlackList,
-    },
+    apply_report::{ApplyReport, GroupReport},
+    executor::Executor,
+    helpers::{command_exists, WhiteBlackList},
+    plan::{Plan, PlanRecorder, PlannedOperation},
     profiles_generator::{self, DefaultProfileType},
-    sysfs::{gpu::*, reading::file_content_to_string},
+    sysfs::{gpu::*, reading::file_content_to_string, root::SystemRoot, writing::WriteError},
     ReducedUpdate, SystemInfo,
 };
 
@@ -22,6 +23,11 @@ use lazy_static::lazy_static;
 pub struct ProfilesInfo {
     pub active_profile: usize,
     pub profiles: Vec<Profile>,
+    /// Revision of every profile by id, changes whenever the profile does.
+    /// Passing it back along with a change makes the change fail if someone
+    /// else changed the profile in the meantime.
+    #[serde(default)]
+    pub revisions: HashMap<String, u64>,
 }
 
 impl ProfilesInfo {
@@ -37,13 +43,30 @@ impl ProfilesInfo {
     pub fn try_find_profile_index_by_name(&self, name: &str) -> Option<usize> {
         self.profiles.iter().position(|p| p.profile_name == name)
     }
+    pub fn try_find_profile_index_by_id(&self, id: &str) -> Option<usize> {
+        self.profiles.iter().position(|p| p.id == id)
+    }
+    pub fn revision_of(&self, id: &str) -> u64 {
+        self.revisions.get(id).copied().unwrap_or_default()
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
 pub struct Profile {
-    /// Name of the profile. Should match the profile filename
+    /// Version of the file format, see `schema::SCHEMA_VERSION`. Files
+    /// written before schema versions lack it.
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Identifies the profile and names its file, never changes once the
+    /// profile is created. Files written before ids existed lack it, it's
+    /// filled in from the config when they're loaded.
+    #[serde(default)]
+    pub id: String,
+    /// Name of the profile as shown to the user, unique among profiles
     pub profile_name: String,
     pub base_profile: Option<DefaultProfileType>,
+    /// Name of the profile this one takes every setting it leaves unset from
+    pub extends: Option<String>,
 
     pub sleep_settings: SleepSettings,
     pub cpu_settings: CPUSettings,
@@ -62,117 +85,162 @@ pub struct Profile {
 }
 
 impl Profile {
-    pub fn apply_all(&self) {
+    pub fn apply_all(&self, executor: &dyn Executor) -> ApplyReport {
         info!("Applying profile: {}", self.profile_name);
 
-        let settings_functions: Vec<Box<dyn FnOnce() + Send>> = vec![
-            Box::new(|| self.sleep_settings.apply()),
+        let settings_functions: Vec<Box<dyn FnOnce() -> Vec<GroupReport> + Send>> = vec![
+            Box::new(|| vec![self.sleep_settings.apply(executor)]),
             Box::new(|| {
-                self.cpu_settings.apply();
-                self.cpu_core_settings.apply();
+                vec![
+                    self.cpu_settings.apply(executor),
+                    self.cpu_core_settings.apply(executor),
+                ]
             }),
-            Box::new(|| self.screen_settings.apply()),
-            Box::new(|| self.radio_settings.apply()),
-            Box::new(|| self.network_settings.apply()),
-            Box::new(|| self.aspm_settings.apply()),
-            Box::new(|| self.pci_settings.apply()),
-            Box::new(|| self.usb_settings.apply()),
-            Box::new(|| self.sata_settings.apply()),
-            Box::new(|| self.kernel_settings.apply()),
-            Box::new(|| self.firmware_settings.apply()),
-            Box::new(|| self.audio_settings.apply()),
-            Box::new(|| self.gpu_settings.apply()),
+            Box::new(|| vec![self.screen_settings.apply(executor)]),
+            Box::new(|| vec![self.radio_settings.apply(executor)]),
+            Box::new(|| vec![self.network_settings.apply(executor)]),
+            Box::new(|| vec![self.aspm_settings.apply(executor)]),
+            Box::new(|| vec![self.pci_settings.apply(executor)]),
+            Box::new(|| vec![self.usb_settings.apply(executor)]),
+            Box::new(|| vec![self.sata_settings.apply(executor)]),
+            Box::new(|| vec![self.kernel_settings.apply(executor)]),
+            Box::new(|| vec![self.firmware_settings.apply(executor)]),
+            Box::new(|| vec![self.audio_settings.apply(executor)]),
+            Box::new(|| vec![self.gpu_settings.apply(executor)]),
+        ];
+
+        let mut report = ApplyReport::new(&self.profile_name);
+        // The order of the groups is preserved by rayon's collect
+        report.groups = settings_functions
+            .into_par_iter()
+            .map(|f| f())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flatten()
+            .collect();
+
+        report
+    }
+
+    /// Computes the operations [`Profile::apply_all`] would perform on the
+    /// system under `root` without applying anything
+    pub fn plan(&self, root: &SystemRoot) -> Plan {
+        debug!("Planning profile: {}", self.profile_name);
+
+        let recorder = PlanRecorder::new(root.clone());
+        let executor: &dyn Executor = &recorder;
+
+        let settings_functions: Vec<Box<dyn Fn() -> GroupReport>> = vec![
+            Box::new(|| self.sleep_settings.apply(executor)),
+            Box::new(|| self.cpu_settings.apply(executor)),
+            Box::new(|| self.cpu_core_settings.apply(executor)),
+            Box::new(|| self.screen_settings.apply(executor)),
+            Box::new(|| self.radio_settings.apply(executor)),
+            Box::new(|| self.network_settings.apply(executor)),
+            Box::new(|| self.aspm_settings.apply(executor)),
+            Box::new(|| self.pci_settings.apply(executor)),
+            Box::new(|| self.usb_settings.apply(executor)),
+            Box::new(|| self.sata_settings.apply(executor)),
+            Box::new(|| self.kernel_settings.apply(executor)),
+            Box::new(|| self.firmware_settings.apply(executor)),
+            Box::new(|| self.audio_settings.apply(executor)),
+            Box::new(|| self.gpu_settings.apply(executor)),
         ];
 
-        settings_functions.into_par_iter().for_each(|f| f());
+        let mut plan = Plan {
+            profile_name: self.profile_name.clone(),
+            report: ApplyReport::new(&self.profile_name),
+            ..Default::default()
+        };
+
+        for f in settings_functions {
+            let group_report = f();
+            plan.operations.extend(
+                recorder
+                    .take()
+                    .into_iter()
+                    .map(|operation| PlannedOperation {
+                        group: group_report.group.clone(),
+                        operation,
+                    }),
+            );
+            plan.report.groups.push(group_report);
+        }
+
+        plan
     }
 
-    pub fn apply_reduced(&self, reduced_update: &ReducedUpdate) {
+    /// Core settings that don't exist are skipped, the instance turns down
+    /// updates that refer to them beforehand
+    pub fn apply_reduced(
+        &self,
+        executor: &dyn Executor,
+        reduced_update: &ReducedUpdate,
+    ) -> ApplyReport {
         debug!("Applying reduced amount of settings: {reduced_update:?}");
+        let mut report = ApplyReport::new(&self.profile_name);
 
         match reduced_update {
             ReducedUpdate::None => {}
             ReducedUpdate::Sleep => {
-                self.sleep_settings.apply();
+                report.groups.push(self.sleep_settings.apply(executor));
             }
             ReducedUpdate::CPU => {
-                self.cpu_settings.apply();
-                self.cpu_core_settings.apply();
+                report.groups.push(self.cpu_settings.apply(executor));
+                report.groups.push(self.cpu_core_settings.apply(executor));
             }
-            ReducedUpdate::CPUCores => self.cpu_core_settings.apply(),
+            ReducedUpdate::CPUCores => report.groups.push(self.cpu_core_settings.apply(executor)),
             ReducedUpdate::SingleCPUCore(idx) => {
                 if let Some(ref cores) = self.cpu_core_settings.cores {
-                    cores[*idx as usize].apply()
+                    let mut group = GroupReport::new("cpu_core_settings");
+                    if let Some(core) = cores.get(*idx as usize) {
+                        group.append(core.apply(executor));
+                    }
+                    report.groups.push(group);
                 }
             }
             ReducedUpdate::MultipleCPUCores(tochange) => {
                 if let Some(ref cores) = self.cpu_core_settings.cores {
-                    for idx in tochange.iter() {
-                        cores[*idx as usize].apply()
+                    let mut group = GroupReport::new("cpu_core_settings");
+                    for core in tochange.iter().filter_map(|idx| cores.get(*idx as usize)) {
+                        group.append(core.apply(executor));
                     }
+                    report.groups.push(group);
                 }
             }
-            ReducedUpdate::Screen => self.screen_settings.apply(),
-            ReducedUpdate::Radio => self.radio_settings.apply(),
-            ReducedUpdate::Network => self.network_settings.apply(),
-            ReducedUpdate::ASPM => self.aspm_settings.apply(),
-            ReducedUpdate::PCI => self.pci_settings.apply(),
-            ReducedUpdate::USB => self.usb_settings.apply(),
-            ReducedUpdate::SATA => self.sata_settings.apply(),
-            ReducedUpdate::Kernel => self.kernel_settings.apply(),
-            ReducedUpdate::Firmware => self.firmware_settings.apply(),
-            ReducedUpdate::Audio => self.audio_settings.apply(),
-            ReducedUpdate::Gpu => self.gpu_settings.apply(),
+            ReducedUpdate::Screen => report.groups.push(self.screen_settings.apply(executor)),
+            ReducedUpdate::Radio => report.groups.push(self.radio_settings.apply(executor)),
+            ReducedUpdate::Network => report.groups.push(self.network_settings.apply(executor)),
+            ReducedUpdate::ASPM => report.groups.push(self.aspm_settings.apply(executor)),
+            ReducedUpdate::PCI => report.groups.push(self.pci_settings.apply(executor)),
+            ReducedUpdate::USB => report.groups.push(self.usb_settings.apply(executor)),
+            ReducedUpdate::SATA => report.groups.push(self.sata_settings.apply(executor)),
+            ReducedUpdate::Kernel => report.groups.push(self.kernel_settings.apply(executor)),
+            ReducedUpdate::Firmware => report.groups.push(self.firmware_settings.apply(executor)),
+            ReducedUpdate::Audio => report.groups.push(self.audio_settings.apply(executor)),
+            ReducedUpdate::Gpu => report.groups.push(self.gpu_settings.apply(executor)),
         }
-    }
-
-    pub fn parse_or_default(contents: &str, profile_name: &str) -> Profile {
-        match toml::from_str(contents) {
-            Ok(p) => p,
-            Err(_) => {
-                #[derive(Deserialize)]
-                struct ProfileTypeOnly {
-                    pub base_profile: Option<DefaultProfileType>,
-                }
-
-                warn!("Could not parse profile {profile_name}. Attempting to migrate to newer version.");
-
-                let profile_type = toml::from_str::<ProfileTypeOnly>(contents)
-                    .expect("Could not parse base profile type")
-                    .base_profile;
-
-                let base_profile = toml::to_string(&if let Some(profile_type) = profile_type {
-                    profiles_generator::create_default(
-                        profile_name,
-                        profile_type,
-                        &SystemInfo::obtain(),
-                    )
-                } else {
-                    profiles_generator::create_empty(profile_name)
-                })
-                .expect("Could not merge default profile and user profile");
 
-                let merged = serde_toml_merge::merge(
-                    base_profile.parse().unwrap(),
-                    contents.parse().unwrap(),
-                )
-                .unwrap();
-
-                debug!("Merged profile {merged:?}");
-
-                let profile =
-                    Profile::deserialize(merged).expect("Could not parse updated profile");
-
-                profile
-            }
-        }
+        report
     }
 
+    /// Profiles that extend another one are reset to inheriting every
+    /// setting
     pub fn get_original_values(&self, system_info: &SystemInfo) -> Profile {
-        if let Some(base_profile_type) = self.base_profile {
-            profiles_generator::create_default(&self.profile_name, base_profile_type, system_info)
+        if self.extends.is_some() {
+            Profile {
+                extends: self.extends.clone(),
+                ..profiles_generator::create_empty(&self.id, &self.profile_name)
+            }
+        } else if let Some(base_profile_type) = self.base_profile {
+            profiles_generator::create_default(
+                &self.id,
+                &self.profile_name,
+                base_profile_type,
+                system_info,
+            )
         } else {
-            profiles_generator::create_empty(&self.profile_name)
+            profiles_generator::create_empty(&self.id, &self.profile_name)
         }
     }
 }
@@ -190,47 +258,71 @@ lazy_static! {
 }
 
 impl SleepSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying Sleep settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("sleep_settings");
+
         if let Some(turn_off_screen_after) = self.turn_off_screen_after {
             if command_exists("xset") {
                 let time_in_secs = turn_off_screen_after * 60;
-                run_graphical_command(&format!(
-                    "xset dpms {time_in_secs} {time_in_secs} {time_in_secs}"
-                ));
+                report.record_command(
+                    "turn_off_screen_after",
+                    executor.run_graphical_command(&format!(
+                        "xset dpms {time_in_secs} {time_in_secs} {time_in_secs}"
+                    )),
+                );
             } else {
                 error!("Attempted to set screen turn off timeout when xset is not installed");
+                report.unsupported("turn_off_screen_after", "xset is not installed");
             }
         } else if command_exists("xset") {
-            run_graphical_command("xset -dpms");
+            report.record_command(
+                "turn_off_screen_after",
+                executor.run_graphical_command("xset -dpms"),
+            );
+        } else {
+            report.skipped("turn_off_screen_after");
         }
 
-        Self::kill_previous_autolock_instance();
+        Self::kill_previous_autolock_instance(executor);
 
         if let Some(suspend_after) = self.suspend_after {
             if command_exists("xautolock") {
-                *AUTOLOCK_INSTANCE.lock().unwrap() = run_graphical_command_in_background(&format!(
+                let instance = executor.spawn_graphical_command(&format!(
                     "xautolock -time {suspend_after} -locker 'systemctl suspend'"
-                ))
-                .into();
+                ));
+                if !executor.is_dry_run() {
+                    *AUTOLOCK_INSTANCE.lock().unwrap() = instance;
+                }
+                report.applied("suspend_after");
             } else {
                 error!("Attempted to set suspend time when xautolock is not installed");
+                report.unsupported("suspend_after", "xautolock is not installed");
             }
+        } else {
+            report.skipped("suspend_after");
         }
+
+        report
     }
 
-    fn kill_previous_autolock_instance() {
+    fn kill_previous_autolock_instance(executor: &dyn Executor) {
         debug!("Killing previous autolock instance");
 
         let mut instance_lock = AUTOLOCK_INSTANCE.lock().unwrap();
 
         if let Some(instance) = instance_lock.as_mut() {
             if command_exists("xautolock") {
-                run_graphical_command("xautolock -exit");
+                // Failures are already logged and there's nothing else to do about them
+                let _ = executor.run_graphical_command("xautolock -exit");
+            }
+
+            if executor.is_dry_run() {
+                return;
             }
 
             instance
@@ -272,108 +364,191 @@ pub struct CPUSettings {
 }
 
 impl CPUSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!("Applying CPU settings on {:?}", std::thread::current().id());
 
+        let mut report = GroupReport::new("cpu_settings");
+
         if let Some(ref mode) = self.mode {
-            if fs::metadata("/sys/devices/system/cpu/intel_pstate").is_ok() {
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/intel_pstate/status",
-                    mode
-                ))
-            } else if fs::metadata("/sys/devices/system/cpu/amd_pstate").is_ok() {
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/amd_pstate/status",
-                    mode
-                ))
+            if fs::metadata(executor.rooted("/sys/devices/system/cpu/intel_pstate")).is_ok() {
+                report.record_write(
+                    "mode",
+                    executor.write_value(
+                        executor.rooted("/sys/devices/system/cpu/intel_pstate/status"),
+                        mode,
+                    ),
+                );
+            } else if fs::metadata(executor.rooted("/sys/devices/system/cpu/amd_pstate")).is_ok() {
+                report.record_write(
+                    "mode",
+                    executor.write_value(
+                        executor.rooted("/sys/devices/system/cpu/amd_pstate/status"),
+                        mode,
+                    ),
+                );
             } else {
-                error!("Scaling driver operation mode is only supported on intel_pstate and amd_pstate drivers.")
+                error!("Scaling driver operation mode is only supported on intel_pstate and amd_pstate drivers.");
+                report.unsupported(
+                    "mode",
+                    "only supported on intel_pstate and amd_pstate drivers",
+                );
             }
+        } else {
+            report.skipped("mode");
         }
 
         // Governor and hwp_dynaamic_boost needs to run before epp options because those determine if epp is changable
         if let Some(hwp_dynamic_boost) = self.hwp_dyn_boost {
             let value = if hwp_dynamic_boost { "1" } else { "0" };
-            if fs::metadata("/sys/devices/system/cpu/intel_pstate").is_ok() {
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/intel_pstate/hwp_dynamic_boost",
-                    value
-                ))
+            if fs::metadata(executor.rooted("/sys/devices/system/cpu/intel_pstate")).is_ok() {
+                report.record_write(
+                    "hwp_dyn_boost",
+                    executor.write_value(
+                        executor.rooted("/sys/devices/system/cpu/intel_pstate/hwp_dynamic_boost"),
+                        value,
+                    ),
+                );
             } else {
                 error!("HWP dynamic boost is currently only supported for intel CPUs with intel_pstate");
+                report.unsupported("hwp_dyn_boost", "only supported with intel_pstate");
             }
+        } else {
+            report.skipped("hwp_dyn_boost");
         }
 
         if let Some(ref governor) = self.governor {
-            write_all_cores("cpufreq/scaling_governor", governor);
+            report.record_writes(
+                "governor",
+                write_all_cores(executor, "cpufreq/scaling_governor", governor),
+            );
+        } else {
+            report.skipped("governor");
         }
 
         if let Some(ref epp) = self.energy_perf_ratio {
-            if fs::metadata("/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_preference")
-                .is_ok()
+            if fs::metadata(
+                executor
+                    .rooted("/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_preference"),
+            )
+            .is_ok()
+            {
+                report.record_writes(
+                    "energy_perf_ratio",
+                    write_all_cores(executor, "cpufreq/energy_performance_preference", epp),
+                );
+            } else if fs::metadata(
+                executor.rooted("/sys/devices/system/cpu/cpu0/power/energy_perf_bias"),
+            )
+            .is_ok()
             {
-                write_all_cores("cpufreq/energy_performance_preference", epp);
-            } else if fs::metadata("/sys/devices/system/cpu/cpu0/power/energy_perf_bias").is_ok() {
                 debug!(
                     "System does not have EPP but EPB is present, translating and setting EPB..."
                 );
-                write_all_cores("power/energy_perf_bias", &Self::translate_epb_to_epp(epp));
+                report.record_writes(
+                    "energy_perf_ratio",
+                    write_all_cores(
+                        executor,
+                        "power/energy_perf_bias",
+                        &Self::translate_epp_to_epb(epp),
+                    ),
+                );
             } else {
                 warn!("System does not have EPP or EPB but configuration attempted to set anyways. Ignoring...");
+                report.unsupported("energy_perf_ratio", "system has neither EPP nor EPB");
             }
+        } else {
+            report.skipped("energy_perf_ratio");
         }
 
         if let Some(boost) = self.boost {
-            if fs::metadata("/sys/devices/system/cpu/intel_pstate/no_turbo").is_ok() {
+            if fs::metadata(executor.rooted("/sys/devices/system/cpu/intel_pstate/no_turbo"))
+                .is_ok()
+            {
                 // using intel turbo
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/intel_pstate/no_turbo",
-                    if boost { '0' } else { '1' }
-                ));
-            } else if fs::metadata("/sys/devices/system/cpu/cpufreq/boost").is_ok() {
+                report.record_write(
+                    "boost",
+                    executor.write_value(
+                        executor.rooted("/sys/devices/system/cpu/intel_pstate/no_turbo"),
+                        if boost { '0' } else { '1' },
+                    ),
+                );
+            } else if fs::metadata(executor.rooted("/sys/devices/system/cpu/cpufreq/boost")).is_ok()
+            {
                 // using amd precission boost
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/cpufreq/boost",
-                    if boost { '1' } else { '0' }
-                ));
+                report.record_write(
+                    "boost",
+                    executor.write_value(
+                        executor.rooted("/sys/devices/system/cpu/cpufreq/boost"),
+                        if boost { '1' } else { '0' },
+                    ),
+                );
             } else {
-                error!("CPU boost technology is unsupported by your CPU/driver")
+                error!("CPU boost technology is unsupported by your CPU/driver");
+                report.unsupported("boost", "unsupported by the CPU or driver");
             }
+        } else {
+            report.skipped("boost");
         }
 
         if let Some(min_frequency) = self.min_freq {
-            write_all_cores(
-                "cpufreq/scaling_min_freq",
-                (min_frequency * 1000).to_string().as_str(),
+            report.record_writes(
+                "min_freq",
+                write_all_cores(
+                    executor,
+                    "cpufreq/scaling_min_freq",
+                    (min_frequency * 1000).to_string().as_str(),
+                ),
             );
+        } else {
+            report.skipped("min_freq");
         }
         if let Some(max_frequency) = self.max_freq {
-            write_all_cores(
-                "cpufreq/scaling_max_freq",
-                (max_frequency * 1000).to_string().as_str(),
+            report.record_writes(
+                "max_freq",
+                write_all_cores(
+                    executor,
+                    "cpufreq/scaling_max_freq",
+                    (max_frequency * 1000).to_string().as_str(),
+                ),
             );
+        } else {
+            report.skipped("max_freq");
         }
 
         if let Some(min_perf_pct) = self.min_perf_pct {
-            if fs::metadata("/sys/devices/system/cpu/intel_pstate").is_ok() {
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/intel_pstate/min_perf_pct",
-                    min_perf_pct
-                ))
+            if fs::metadata(executor.rooted("/sys/devices/system/cpu/intel_pstate")).is_ok() {
+                report.record_write(
+                    "min_perf_pct",
+                    executor.write_value(
+                        executor.rooted("/sys/devices/system/cpu/intel_pstate/min_perf_pct"),
+                        min_perf_pct,
+                    ),
+                );
             } else {
                 error!("Min/Max scaling perf percentage is currently only supported for intel CPUs with intel_pstate");
+                report.unsupported("min_perf_pct", "only supported with intel_pstate");
             }
+        } else {
+            report.skipped("min_perf_pct");
         }
         if let Some(max_perf_pct) = self.max_perf_pct {
-            if fs::metadata("/sys/devices/system/cpu/intel_pstate").is_ok() {
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/intel_pstate/max_perf_pct",
-                    max_perf_pct
-                ))
+            if fs::metadata(executor.rooted("/sys/devices/system/cpu/intel_pstate")).is_ok() {
+                report.record_write(
+                    "max_perf_pct",
+                    executor.write_value(
+                        executor.rooted("/sys/devices/system/cpu/intel_pstate/max_perf_pct"),
+                        max_perf_pct,
+                    ),
+                );
             } else {
                 error!("Min/Max scaling perf percentage is currently only supported for intel CPUs with intel_pstate");
+                report.unsupported("max_perf_pct", "only supported with intel_pstate");
             }
+        } else {
+            report.skipped("max_perf_pct");
         }
+
+        report
     }
 
     pub fn translate_epp_to_epb(epp: &str) -> String {
@@ -427,90 +602,132 @@ pub struct CoreSetting {
 }
 
 impl CPUCoreSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying CPU core settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("cpu_core_settings");
+
         // In the UI, when disabling a core and then resetting the core override self.online would be set to None
         // But the user likely would have meant to return cpu back to the default values in the profile.
         // Given the way per-core settings work (first apply settings to all cores then individual overrides),
         // it's logical to also remove all the core-disabling overrides first and then maybe disable individual cores
         // Could this be fixed in the UI? Yes. Would it be better architecture-wise? Yes. But it's way easier to just to this
-        write_all_cores("online", "1");
+        report.record_writes("online", write_all_cores(executor, "online", "1"));
         if self.cores.is_none() {
-            return;
+            report.skipped("cores");
+            return report;
         }
 
         for core in self.cores.as_ref().unwrap().iter() {
-            core.apply();
+            report.append(core.apply(executor));
         }
+
+        report
     }
 }
 
 impl CoreSetting {
-    pub fn apply(&self) {
+    /// Returns a report whose fields are prefixed with the core, e.g. `cpu3.governor`
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
+        let mut report = GroupReport::new("cpu_core_settings");
+        let field = |name: &str| format!("cpu{}.{name}", self.cpu_id);
+
+        let cpu_path = executor.rooted(format!("/sys/devices/system/cpu/cpu{}", self.cpu_id));
+
         if let Some(online) = self.online {
-            run_command(&format!(
-                "echo {} > /sys/devices/system/cpu/cpu{}/online",
-                if online { "1" } else { "0" },
-                self.cpu_id,
-            ));
+            report.record_write(
+                &field("online"),
+                executor.write_value(cpu_path.join("online"), if online { "1" } else { "0" }),
+            );
+        } else {
+            report.skipped(&field("online"));
         }
 
         if let Some(ref epp) = self.epp {
-            if fs::metadata("/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_preference")
-                .is_ok()
+            if fs::metadata(
+                executor
+                    .rooted("/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_preference"),
+            )
+            .is_ok()
+            {
+                report.record_write(
+                    &field("epp"),
+                    executor
+                        .write_value(cpu_path.join("cpufreq/energy_performance_preference"), epp),
+                );
+            } else if fs::metadata(
+                executor.rooted("/sys/devices/system/cpu/cpu0/power/energy_perf_bias"),
+            )
+            .is_ok()
             {
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/cpu{}/cpufreq/energy_performance_preference",
-                    epp, self.cpu_id,
-                ));
-            } else if fs::metadata("/sys/devices/system/cpu/cpu0/power/energy_perf_bias").is_ok() {
                 debug!(
                     "System does not have EPP but EPB is present, translating and setting EPB..."
                 );
-                run_command(&format!(
-                    "echo {} > /sys/devices/system/cpu/cpu{}/power/energy_perf_bias",
-                    CPUSettings::translate_epp_to_epb(epp),
-                    self.cpu_id
-                ));
+                report.record_write(
+                    &field("epp"),
+                    executor.write_value(
+                        cpu_path.join("power/energy_perf_bias"),
+                        CPUSettings::translate_epp_to_epb(epp),
+                    ),
+                );
             } else {
                 warn!("System does not have EPP or EPB but configuration attempted to set anyways. Ignoring...");
+                report.unsupported(&field("epp"), "system has neither EPP nor EPB");
             }
+        } else {
+            report.skipped(&field("epp"));
         }
 
         if let Some(ref governor) = self.governor {
-            run_command(&format!(
-                "echo {} > /sys/devices/system/cpu/cpu{}/cpufreq/scaling_governor",
-                governor, self.cpu_id,
-            ));
+            report.record_write(
+                &field("governor"),
+                executor.write_value(cpu_path.join("cpufreq/scaling_governor"), governor),
+            );
+        } else {
+            report.skipped(&field("governor"));
         }
 
         if let Some(min_frequency) = self.min_frequency {
-            run_command(&format!(
-                "echo {} > /sys/devices/system/cpu/cpu{}/cpufreq/scaling_min_freq",
-                min_frequency * 1000,
-                self.cpu_id,
-            ));
+            report.record_write(
+                &field("min_frequency"),
+                executor.write_value(
+                    cpu_path.join("cpufreq/scaling_min_freq"),
+                    min_frequency * 1000,
+                ),
+            );
+        } else {
+            report.skipped(&field("min_frequency"));
         }
         if let Some(max_frequency) = self.max_frequency {
-            run_command(&format!(
-                "echo {} > /sys/devices/system/cpu/cpu{}/cpufreq/scaling_max_freq",
-                max_frequency * 1000,
-                self.cpu_id
-            ));
+            report.record_write(
+                &field("max_frequency"),
+                executor.write_value(
+                    cpu_path.join("cpufreq/scaling_max_freq"),
+                    max_frequency * 1000,
+                ),
+            );
+        } else {
+            report.skipped(&field("max_frequency"));
         }
+
+        report
     }
 }
 
 /// Writes a value to all CPU paths under /sys/devices/cpu/cpu* that are
 /// actually single CPU core management directories (which end in numbers),
 /// warning if any of the individual cores can't have that value set.
-fn write_all_cores(path: impl AsRef<Path>, data: &str) {
+fn write_all_cores(
+    executor: &dyn Executor,
+    path: impl AsRef<Path>,
+    data: &str,
+) -> Vec<Result<(), WriteError>> {
     let path = path.as_ref();
-    let raw = fs::read_dir("/sys/devices/system/cpu/").expect("Error reading CPU list");
+    let raw =
+        fs::read_dir(executor.rooted("/sys/devices/system/cpu/")).expect("Error reading CPU list");
     let relevant = raw.filter_map(Result::ok).filter(|ent| {
         let raw_filename = ent.file_name();
         let name = raw_filename.to_string_lossy();
@@ -520,16 +737,20 @@ fn write_all_cores(path: impl AsRef<Path>, data: &str) {
         suffix.parse::<u32>().is_ok()
     });
     let to_write = relevant.map(|ent| ent.path().join(path));
-    for target in to_write {
-        if !target.exists() {
-            warn!(
-                "Attempted to write {data} to CPU sysfs path {}, but that path does not exist!",
-                target.display()
-            );
-            continue;
-        }
-        run_command(&format!("echo {} > {}", data, target.display()));
-    }
+    to_write
+        .map(|target| {
+            let result = executor.write_value(&target, data);
+            match result {
+                Ok(()) => {}
+                Err(WriteError::NotFound(_)) => warn!(
+                    "Attempted to write {data} to CPU sysfs path {}, but that path does not exist!",
+                    target.display()
+                ),
+                Err(ref error) => error!("Could not write {data} to CPU sysfs path: {error}"),
+            }
+            result
+        })
+        .collect()
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
@@ -540,36 +761,73 @@ pub struct ScreenSettings {
 }
 
 impl ScreenSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying Screen settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("screen_settings");
+
         if let Some(ref resolution) = self.resolution {
-            Self::try_run_xrandr(&format!("xrandr --mode {}", resolution));
+            Self::try_run_xrandr(
+                executor,
+                &mut report,
+                "resolution",
+                &format!("xrandr --mode {}", resolution),
+            );
+        } else {
+            report.skipped("resolution");
         }
         if let Some(ref refresh_rate) = self.refresh_rate {
-            Self::try_run_xrandr(&format!("xrandr -r {}", refresh_rate));
+            Self::try_run_xrandr(
+                executor,
+                &mut report,
+                "refresh_rate",
+                &format!("xrandr -r {}", refresh_rate),
+            );
+        } else {
+            report.skipped("refresh_rate");
         }
         if let Some(brightness) = self.brightness {
-            Self::try_set_brightness(&format!("brightnessctl s {}%", brightness));
+            Self::try_set_brightness(
+                executor,
+                &mut report,
+                "brightness",
+                &format!("brightnessctl s {}%", brightness),
+            );
+        } else {
+            report.skipped("brightness");
         }
+
+        report
     }
 
-    pub fn try_run_xrandr(command: &str) {
+    pub fn try_run_xrandr(
+        executor: &dyn Executor,
+        report: &mut GroupReport,
+        field: &str,
+        command: &str,
+    ) {
         if command_exists("xrandr") {
-            run_graphical_command(command);
+            report.record_command(field, executor.run_graphical_command(command));
         } else {
             error!("xrandr is not present in the system. Ignoring settings utilizing it...");
+            report.unsupported(field, "xrandr is not installed");
         }
     }
 
-    pub fn try_set_brightness(command: &str) {
+    pub fn try_set_brightness(
+        executor: &dyn Executor,
+        report: &mut GroupReport,
+        field: &str,
+        command: &str,
+    ) {
         if command_exists("brightnessctl") {
-            run_command(command);
+            report.record_command(field, executor.run_command(command));
         } else {
             error!("brightnessctl is not present in the system. Install it if you want brightness configuration. Ignoring settings utilizing it...");
+            report.unsupported(field, "brightnessctl is not installed");
         }
     }
 }
@@ -582,30 +840,49 @@ pub struct RadioSettings {
 }
 
 impl RadioSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying Radio settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("radio_settings");
+
         if let Some(wifi) = self.block_wifi {
-            run_command(&format!(
-                "rfkill {} wifi",
-                if wifi { "block" } else { "unblock" },
-            ))
+            report.record_command(
+                "block_wifi",
+                executor.run_command(&format!(
+                    "rfkill {} wifi",
+                    if wifi { "block" } else { "unblock" },
+                )),
+            );
+        } else {
+            report.skipped("block_wifi");
         }
         if let Some(nfc) = self.block_nfc {
-            run_command(&format!(
-                "rfkill {} nfc",
-                if nfc { "block" } else { "unblock" },
-            ))
+            report.record_command(
+                "block_nfc",
+                executor.run_command(&format!(
+                    "rfkill {} nfc",
+                    if nfc { "block" } else { "unblock" },
+                )),
+            );
+        } else {
+            report.skipped("block_nfc");
         }
         if let Some(bt) = self.block_bt {
-            run_command(&format!(
-                "rfkill {} bluetooth",
-                if bt { "block" } else { "unblock" },
-            ))
+            report.record_command(
+                "block_bt",
+                executor.run_command(&format!(
+                    "rfkill {} bluetooth",
+                    if bt { "block" } else { "unblock" },
+                )),
+            );
+        } else {
+            report.skipped("block_bt");
         }
+
+        report
     }
 }
 
@@ -630,41 +907,71 @@ pub struct NetworkSettings {
 }
 
 impl NetworkSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying Network settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("network_settings");
+
         if let Some(disable_ethernet) = self.disable_ethernet {
-            Self::toggle_all_ethernet_cards(disable_ethernet);
+            Self::toggle_all_ethernet_cards(executor, &mut report, disable_ethernet);
+        } else {
+            report.skipped("disable_ethernet");
         }
 
-        if !self.all_kernel_module_settings_are_none() {
-            self.apply_kernel_module_settings();
-        }
+        self.apply_kernel_module_settings(executor, &mut report);
+
+        report
     }
 
-    fn toggle_all_ethernet_cards(disable: bool) {
+    fn toggle_all_ethernet_cards(executor: &dyn Executor, report: &mut GroupReport, disable: bool) {
         if !command_exists("ifconfig") {
             error!("ifconfig is not present in the system, ignoring ethernet settings...");
+            report.unsupported("disable_ethernet", "ifconfig is not installed");
+            return;
         }
 
-        let entries = fs::read_dir("/sys/class/net").expect("Could not read sysfs path");
+        let entries =
+            fs::read_dir(executor.rooted("/sys/class/net")).expect("Could not read sysfs path");
         let eth_pattern = regex::Regex::new(r"^(eth|enp|ens|eno)").unwrap();
 
+        let mut failures = Vec::new();
         for entry in entries.flatten() {
             let name = entry.file_name();
             let name_str = name.to_string_lossy();
 
             if eth_pattern.is_match(&name_str) {
-                run_command(&format!(
+                if let Err(error) = executor.run_command(&format!(
                     "ifconfig {} {}",
                     &name_str,
                     if disable { "down" } else { "up" }
-                ))
+                )) {
+                    failures.push(error);
+                }
             }
         }
+
+        if failures.is_empty() {
+            report.applied("disable_ethernet");
+        } else {
+            report.failed("disable_ethernet", failures.join("; "));
+        }
+    }
+
+    /// Returns the names of the kernel module settings alongside whether
+    /// they are set in this profile
+    fn kernel_module_settings(&self) -> [(&'static str, bool); 7] {
+        [
+            ("disable_wifi_7", self.disable_wifi_7.is_some()),
+            ("disable_wifi_6", self.disable_wifi_6.is_some()),
+            ("disable_wifi_5", self.disable_wifi_5.is_some()),
+            ("enable_power_save", self.enable_power_save.is_some()),
+            ("power_level", self.power_level.is_some()),
+            ("power_scheme", self.power_scheme.is_some()),
+            ("enable_uapsd", self.enable_uapsd.is_some()),
+        ]
     }
 
     fn all_kernel_module_settings_are_none(&self) -> bool {
@@ -677,15 +984,23 @@ impl NetworkSettings {
             && self.enable_uapsd.is_none()
     }
 
-    fn apply_kernel_module_settings(&self) {
-        let uses_iwlmvm = if fs::metadata("/sys/module/iwlmvm").is_ok() {
+    fn apply_kernel_module_settings(&self, executor: &dyn Executor, report: &mut GroupReport) {
+        if self.all_kernel_module_settings_are_none() {
+            self.record_kernel_module_settings(report, |_, _| {});
+            return;
+        }
+
+        let uses_iwlmvm = if fs::metadata(executor.rooted("/sys/module/iwlmvm")).is_ok() {
             debug!("Identified that the system uses iwlmvm");
             true
-        } else if fs::metadata("/sys/module/iwldvm").is_ok() {
+        } else if fs::metadata(executor.rooted("/sys/module/iwldvm")).is_ok() {
             debug!("Identified that the system uses iwldvm");
             false
         } else {
             error!("Could not identify spuported wifi firmware module. Expected either iwlmvm or iwldvm, neither found. Ignoring network kernel module settings...");
+            self.record_kernel_module_settings(report, |report, field| {
+                report.unsupported(field, "neither iwlmvm nor iwldvm is loaded")
+            });
             return;
         };
 
@@ -731,9 +1046,28 @@ impl NetworkSettings {
 
         let firmware_name = if uses_iwlmvm { "iwlmvm" } else { "iwldvm" };
 
-        run_command(&format!(
+        let result = executor.run_command(&format!(
             "modprobe -r {firmware_name} && modprobe -r iwlwifi && modprobe {firmware_name} {} && modprobe iwlwifi {}", firmware_parameters, driver_parameters,
         ));
+        self.record_kernel_module_settings(report, |report, field| {
+            report.record_command(field, result.clone())
+        });
+    }
+
+    /// All kernel module settings are applied with a single module reload,
+    /// so every one of them that is set shares the same outcome
+    fn record_kernel_module_settings(
+        &self,
+        report: &mut GroupReport,
+        outcome: impl Fn(&mut GroupReport, &str),
+    ) {
+        for (field, is_set) in self.kernel_module_settings() {
+            if is_set {
+                outcome(report, field);
+            } else {
+                report.skipped(field);
+            }
+        }
     }
 }
 
@@ -744,18 +1078,27 @@ pub struct ASPMSettings {
 }
 
 impl ASPMSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying ASPM settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("aspm_settings");
+
         if let Some(ref mode) = self.mode {
-            run_command(&format!(
-                "echo {} > /sys/module/pcie_aspm/parameters/policy",
-                mode
-            ));
+            report.record_write(
+                "mode",
+                executor.write_value(
+                    executor.rooted("/sys/module/pcie_aspm/parameters/policy"),
+                    mode,
+                ),
+            );
+        } else {
+            report.skipped("mode");
         }
+
+        report
     }
 }
 
@@ -768,18 +1111,23 @@ pub struct PCISettings {
 }
 
 impl PCISettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying PCI PM settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("pci_settings");
+
         if self.enable_power_management.is_none() {
-            return;
+            report.skipped("enable_power_management");
+            return report;
         }
 
-        let entries = fs::read_dir("/sys/bus/pci/devices").expect("Could not read sysfs directory");
+        let entries = fs::read_dir(executor.rooted("/sys/bus/pci/devices"))
+            .expect("Could not read sysfs directory");
 
+        let mut results = Vec::new();
         for entry in entries {
             let entry = entry.expect("Could not read sysfs entry");
             let path = entry.path();
@@ -790,12 +1138,14 @@ impl PCISettings {
                 self.enable_power_management.unwrap(),
             );
 
-            run_command(&format!(
-                "echo {} > {}",
+            results.push(executor.write_value(
+                path.join("power/control"),
                 if enable_pm { "auto" } else { "on" },
-                path.join("power/control").display()
-            ))
+            ));
         }
+        report.record_writes("enable_power_management", results);
+
+        report
     }
 }
 
@@ -808,14 +1158,22 @@ pub struct USBSettings {
 }
 
 impl USBSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!("Applying USB settings on {:?}", std::thread::current().id());
 
-        let entries = fs::read_dir("/sys/bus/usb/devices").expect("Could not read sysfs directory");
+        let mut report = GroupReport::new("usb_settings");
 
-        if self.enable_pm.is_none() {
-            return;
-        }
+        let entries = fs::read_dir(executor.rooted("/sys/bus/usb/devices"))
+            .expect("Could not read sysfs directory");
+
+        let Some(enable_power_management) = self.enable_pm else {
+            report.skipped("enable_pm");
+            report.skipped("autosuspend_delay_ms");
+            return report;
+        };
+
+        let mut pm_results = Vec::new();
+        let mut autosuspend_results = Vec::new();
 
         for entry in entries {
             let entry = entry.expect("Could not read sysfs entry");
@@ -829,29 +1187,35 @@ impl USBSettings {
             let vendor_id = file_content_to_string(path.join("idVendor"));
             let product_id = file_content_to_string(path.join("idProduct"));
 
-            if let Some(enable_power_management) = self.enable_pm {
-                let enable_pm = WhiteBlackList::should_enable_item(
-                    &self.whiteblacklist,
-                    &format!("{vendor_id}:{product_id}"),
-                    enable_power_management,
-                );
+            let enable_pm = WhiteBlackList::should_enable_item(
+                &self.whiteblacklist,
+                &format!("{vendor_id}:{product_id}"),
+                enable_power_management,
+            );
 
-                run_command(&format!(
-                    "echo {} > {}",
-                    if enable_pm { "auto" } else { "on" },
-                    path.join("power/control").display()
-                ));
+            pm_results.push(executor.write_value(
+                path.join("power/control"),
+                if enable_pm { "auto" } else { "on" },
+            ));
 
-                if enable_pm {
-                    if let Some(auto_suspend_ms) = self.autosuspend_delay_ms {
-                        run_command(&format!(
-                            "echo {auto_suspend_ms} > {}",
-                            path.join("power/autosuspend_delay_ms").display()
-                        ));
-                    }
+            if enable_pm {
+                if let Some(auto_suspend_ms) = self.autosuspend_delay_ms {
+                    autosuspend_results.push(
+                        executor
+                            .write_value(path.join("power/autosuspend_delay_ms"), auto_suspend_ms),
+                    );
                 }
             }
         }
+
+        report.record_writes("enable_pm", pm_results);
+        if self.autosuspend_delay_ms.is_some() {
+            report.record_writes("autosuspend_delay_ms", autosuspend_results);
+        } else {
+            report.skipped("autosuspend_delay_ms");
+        }
+
+        report
     }
 }
 
@@ -861,33 +1225,39 @@ pub struct SATASettings {
 }
 
 impl SATASettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying SATA settings on {:?}",
             std::thread::current().id()
         );
 
-        if self.active_link_pm_policy.is_none() {
-            return;
-        }
+        let mut report = GroupReport::new("sata_settings");
 
-        let pm_policy = self.active_link_pm_policy.as_ref().unwrap();
+        let Some(ref pm_policy) = self.active_link_pm_policy else {
+            report.skipped("active_link_pm_policy");
+            return report;
+        };
 
-        let entries = match fs::read_dir("/sys/class/scsi_host/") {
+        let entries = match fs::read_dir(executor.rooted("/sys/class/scsi_host/")) {
             Ok(itr) => itr,
-            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                report.unsupported("active_link_pm_policy", "system has no SCSI hosts");
+                return report;
+            }
             Err(e) => panic!("Could not read sysfs directory: {e:?}"),
         };
 
+        let mut results = Vec::new();
         for entry in entries {
             let entry = entry.expect("Could not read sysfs entry");
             let path = entry.path();
 
-            run_command(&format!(
-                "echo {pm_policy} > {}",
-                path.join("link_power_management_policy").display()
-            ))
+            results
+                .push(executor.write_value(path.join("link_power_management_policy"), pm_policy));
         }
+        report.record_writes("active_link_pm_policy", results);
+
+        report
     }
 }
 
@@ -899,27 +1269,46 @@ pub struct KernelSettings {
 }
 
 impl KernelSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
         info!(
             "Applying Kernel settings on {:?}",
             std::thread::current().id()
         );
 
+        let mut report = GroupReport::new("kernel_settings");
+
         if let Some(disable_wd) = self.disable_nmi_watchdog {
-            run_command(&format!(
-                "echo {} > /proc/sys/kernel/nmi_watchdog",
-                if disable_wd { "0" } else { "1" }
-            ))
+            report.record_write(
+                "disable_nmi_watchdog",
+                executor.write_value(
+                    executor.rooted("/proc/sys/kernel/nmi_watchdog"),
+                    if disable_wd { "0" } else { "1" },
+                ),
+            );
+        } else {
+            report.skipped("disable_nmi_watchdog");
         }
         if let Some(vm_writeback) = self.vm_writeback {
-            run_command(&format!(
-                "echo {} > /proc/sys/vm/dirty_writeback_centisecs",
-                vm_writeback * 100
-            ))
+            report.record_write(
+                "vm_writeback",
+                executor.write_value(
+                    executor.rooted("/proc/sys/vm/dirty_writeback_centisecs"),
+                    vm_writeback * 100,
+                ),
+            );
+        } else {
+            report.skipped("vm_writeback");
         }
         if let Some(lm) = self.laptop_mode {
-            run_command(&format!("echo {} > /proc/sys/vm/laptop_mode", lm))
+            report.record_write(
+                "laptop_mode",
+                executor.write_value(executor.rooted("/proc/sys/vm/laptop_mode"), lm),
+            );
+        } else {
+            report.skipped("laptop_mode");
         }
+
+        report
     }
 }
 
@@ -930,12 +1319,22 @@ pub struct FirmwareSettings {
 }
 
 impl FirmwareSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
+        let mut report = GroupReport::new("firmware_settings");
+
         if let Some(ref profile) = self.platform_profile {
-            run_command(&format!(
-                "echo {profile} > /sys/firmware/acpi/platform_profile"
-            ));
+            report.record_write(
+                "platform_profile",
+                executor.write_value(
+                    executor.rooted("/sys/firmware/acpi/platform_profile"),
+                    profile,
+                ),
+            );
+        } else {
+            report.skipped("platform_profile");
         }
+
+        report
     }
 }
 
@@ -945,20 +1344,38 @@ pub struct AudioSettings {
 }
 
 impl AudioSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
+        let mut report = GroupReport::new("audio_settings");
+
         if let Some(ref time) = self.idle_timeout {
-            if fs::metadata("/sys/module/snd_hda_intel/").is_ok() {
-                run_command(&format!(
-                    "echo {time} > /sys/module/snd_hda_intel/parameters/power_save"
-                ));
-            } else if fs::metadata("/sys/module/snd_ac97_codec/").is_ok() {
-                run_command(&format!(
-                    "echo {time} > /sys/module/snd_ac97_codec/parameters/power_save"
-                ));
+            if fs::metadata(executor.rooted("/sys/module/snd_hda_intel/")).is_ok() {
+                report.record_write(
+                    "idle_timeout",
+                    executor.write_value(
+                        executor.rooted("/sys/module/snd_hda_intel/parameters/power_save"),
+                        time,
+                    ),
+                );
+            } else if fs::metadata(executor.rooted("/sys/module/snd_ac97_codec/")).is_ok() {
+                report.record_write(
+                    "idle_timeout",
+                    executor.write_value(
+                        executor.rooted("/sys/module/snd_ac97_codec/parameters/power_save"),
+                        time,
+                    ),
+                );
             } else {
                 error!("Attempted to set audio idle timeout but only snd_hda_intel and snd_ac97_codec modules are supported for this feature.");
+                report.unsupported(
+                    "idle_timeout",
+                    "only snd_hda_intel and snd_ac97_codec are supported",
+                );
             }
+        } else {
+            report.skipped("idle_timeout");
         }
+
+        report
     }
 }
 
@@ -977,65 +1394,890 @@ pub struct GpuSettings {
 }
 
 impl GpuSettings {
-    pub fn apply(&self) {
+    pub fn apply(&self, executor: &dyn Executor) -> GroupReport {
+        let mut report = GroupReport::new("gpu_settings");
+
         if self.intel_min.is_some() || self.intel_max.is_some() || self.intel_boost.is_some() {
-            self.apply_intel_settings();
+            self.apply_intel_settings(executor, &mut report);
+        } else {
+            report.skipped("intel_min");
+            report.skipped("intel_max");
+            report.skipped("intel_boost");
         }
 
         if self.amd_dpm_perf_level.is_some()
             || self.amd_dpm_power_state.is_some()
             || self.amd_power_profile.is_some()
         {
-            self.apply_amd_settings();
+            self.apply_amd_settings(executor, &mut report);
+        } else {
+            report.skipped("amd_dpm_perf_level");
+            report.skipped("amd_dpm_power_state");
+            report.skipped("amd_power_profile");
         }
+
+        report
     }
 
-    fn apply_intel_settings(&self) {
+    fn apply_intel_settings(&self, executor: &dyn Executor, report: &mut GroupReport) {
         assert!(self.intel_min.is_some() || self.intel_max.is_some() || self.intel_boost.is_some());
 
-        for gpu in iterate_intel_gpus() {
+        let mut min_results = Vec::new();
+        let mut max_results = Vec::new();
+        let mut boost_results = Vec::new();
+
+        let mut found_gpu = false;
+        for gpu in iterate_intel_gpus(executor.root()) {
+            found_gpu = true;
+
             match (self.intel_min, self.intel_max) {
                 (Some(min), None) => {
-                    gpu.set_min(min);
+                    min_results.push(gpu.set_min(executor, min));
                 }
                 (None, Some(max)) => {
-                    gpu.set_max(max);
+                    max_results.push(gpu.set_max(executor, max));
                 }
                 (Some(min), Some(max)) => {
                     if min > gpu.max_frequency {
-                        gpu.set_max(max);
-                        gpu.set_min(min);
+                        max_results.push(gpu.set_max(executor, max));
+                        min_results.push(gpu.set_min(executor, min));
                     } else {
-                        gpu.set_min(min);
-                        gpu.set_max(max);
+                        min_results.push(gpu.set_min(executor, min));
+                        max_results.push(gpu.set_max(executor, max));
                     }
                 }
-                (None, None) => unreachable!(),
+                (None, None) => {}
             }
 
             if let Some(boost) = self.intel_boost {
-                gpu.set_boost(boost);
+                boost_results.push(gpu.set_boost(executor, boost));
+            }
+        }
+
+        for (field, is_set, results) in [
+            ("intel_min", self.intel_min.is_some(), min_results),
+            ("intel_max", self.intel_max.is_some(), max_results),
+            ("intel_boost", self.intel_boost.is_some(), boost_results),
+        ] {
+            if !is_set {
+                report.skipped(field);
+            } else if !found_gpu {
+                error!("Attempted to set {field} but no intel GPU is present");
+                report.unsupported(field, "no intel GPU found");
+            } else {
+                report.record_writes(field, results);
             }
         }
     }
 
-    fn apply_amd_settings(&self) {
+    fn apply_amd_settings(&self, executor: &dyn Executor, report: &mut GroupReport) {
         assert!(
             self.amd_dpm_perf_level.is_some()
                 || self.amd_dpm_power_state.is_some()
                 || self.amd_power_profile.is_some()
         );
 
-        for gpu in iterate_amd_gpus() {
+        let mut perf_level_results = Vec::new();
+        let mut power_state_results = Vec::new();
+        let mut power_profile_results = Vec::new();
+
+        let mut found_gpu = false;
+        for gpu in iterate_amd_gpus(executor.root()) {
+            found_gpu = true;
+
             if let Some(ref perf_level) = self.amd_dpm_perf_level {
-                gpu.set_dpm_perf_level(perf_level);
+                perf_level_results.push(gpu.set_dpm_perf_level(executor, perf_level));
             }
             if let Some(ref power_state) = self.amd_dpm_power_state {
-                gpu.set_dpm_power_state(power_state);
+                power_state_results.push(gpu.set_dpm_power_state(executor, power_state));
             }
             if let Some(ref power_profile) = self.amd_power_profile {
-                gpu.set_power_profile(power_profile);
+                power_profile_results.push(gpu.set_power_profile(executor, power_profile));
+            }
+        }
+
+        for (field, is_set, results) in [
+            (
+                "amd_dpm_perf_level",
+                self.amd_dpm_perf_level.is_some(),
+                perf_level_results,
+            ),
+            (
+                "amd_dpm_power_state",
+                self.amd_dpm_power_state.is_some(),
+                power_state_results,
+            ),
+            (
+                "amd_power_profile",
+                self.amd_power_profile.is_some(),
+                power_profile_results,
+            ),
+        ] {
+            if !is_set {
+                report.skipped(field);
+            } else if !found_gpu {
+                error!("Attempted to set {field} but no AMD GPU is present");
+                report.unsupported(field, "no AMD GPU found");
+            } else {
+                report.record_writes(field, results);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        os::unix::fs::symlink,
+        path::{Path, PathBuf},
+        process::Child,
+        sync::Mutex,
+    };
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{helpers::WhiteBlackListType, plan::Operation, SettingOutcome};
+
+    /// Directory tree of fake sysfs and procfs attributes
+    struct FakeSystem {
+        dir: TempDir,
+        root: SystemRoot,
+    }
+
+    impl FakeSystem {
+        fn new() -> FakeSystem {
+            let dir = TempDir::new().unwrap();
+            let root = SystemRoot::new(dir.path());
+            FakeSystem { dir, root }
+        }
+
+        fn file(&self, path: &str, content: &str) -> &Self {
+            let path = self.root.rooted(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+            self
+        }
+
+        fn dir(&self, path: &str) -> &Self {
+            fs::create_dir_all(self.root.rooted(path)).unwrap();
+            self
+        }
+
+        fn cpus(&self, count: u32) -> &Self {
+            for cpu in 0..count {
+                let cpu = format!("/sys/devices/system/cpu/cpu{cpu}");
+                self.file(&format!("{cpu}/online"), "1")
+                    .file(&format!("{cpu}/cpufreq/scaling_governor"), "powersave")
+                    .file(&format!("{cpu}/cpufreq/scaling_min_freq"), "400000")
+                    .file(&format!("{cpu}/cpufreq/scaling_max_freq"), "4000000")
+                    .file(
+                        &format!("{cpu}/cpufreq/energy_performance_preference"),
+                        "default",
+                    );
             }
+            self
+        }
+
+        fn read(&self, path: &str) -> String {
+            fs::read_to_string(self.root.rooted(path)).unwrap()
+        }
+
+        /// Applies a group through a [`PlanRecorder`], returning the writes
+        /// with their paths relative to the root, sorted as groups that
+        /// write to every core do so in no particular order
+        fn plan(
+            &self,
+            apply: impl Fn(&dyn Executor) -> GroupReport,
+        ) -> (Vec<(String, String)>, Vec<String>, GroupReport) {
+            let recorder = PlanRecorder::new(self.root.clone());
+            let report = apply(&recorder);
+
+            let mut writes = Vec::new();
+            let mut commands = Vec::new();
+            for operation in recorder.take() {
+                match operation {
+                    Operation::Write { path, value } => writes.push((self.relative(&path), value)),
+                    Operation::Command(command)
+                    | Operation::GraphicalCommand(command)
+                    | Operation::BackgroundGraphicalCommand(command) => commands.push(command),
+                }
+            }
+            writes.sort();
+
+            (writes, commands, report)
+        }
+
+        fn relative(&self, path: &Path) -> String {
+            format!("/{}", path.strip_prefix(self.dir.path()).unwrap().display())
+        }
+    }
+
+    /// Executor that writes into the fake system like the real one would,
+    /// but refuses to write into some attributes and to run any command
+    struct FakeExecutor {
+        root: SystemRoot,
+        rejected: Vec<PathBuf>,
+        written: Mutex<Vec<PathBuf>>,
+    }
+
+    impl FakeExecutor {
+        fn new(system: &FakeSystem, rejected: &[&str]) -> FakeExecutor {
+            FakeExecutor {
+                root: system.root.clone(),
+                rejected: rejected.iter().map(|p| system.root.rooted(p)).collect(),
+                written: Mutex::default(),
+            }
+        }
+    }
+
+    impl Executor for FakeExecutor {
+        fn root(&self) -> &SystemRoot {
+            &self.root
+        }
+
+        fn write(&self, path: &Path, value: &str) -> Result<(), WriteError> {
+            if self.rejected.iter().any(|p| p == path) {
+                return Err(WriteError::Rejected {
+                    path: PathBuf::from(path),
+                    value: value.to_string(),
+                });
+            }
+            crate::sysfs::writing::write_value(path, value)?;
+            self.written.lock().unwrap().push(PathBuf::from(path));
+            Ok(())
+        }
+
+        fn run_command(&self, command: &str) -> Result<(), String> {
+            Err(format!("{command} is not allowed"))
+        }
+
+        fn run_graphical_command(&self, command: &str) -> Result<(), String> {
+            Err(format!("{command} is not allowed"))
+        }
+
+        fn spawn_graphical_command(&self, _: &str) -> Option<Child> {
+            None
+        }
+
+        fn is_dry_run(&self) -> bool {
+            true
+        }
+    }
+
+    fn outcome(report: &GroupReport, field: &str) -> SettingOutcome {
+        report
+            .settings
+            .iter()
+            .find(|s| s.field == field)
+            .unwrap_or_else(|| panic!("{field} is not in the report"))
+            .outcome
+            .clone()
+    }
+
+    fn write(path: &str, value: &str) -> (String, String) {
+        (path.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn cpu_settings_on_intel_pstate() {
+        let system = FakeSystem::new();
+        system
+            .cpus(2)
+            .file("/sys/devices/system/cpu/intel_pstate/status", "passive")
+            .file("/sys/devices/system/cpu/intel_pstate/no_turbo", "0")
+            .file("/sys/devices/system/cpu/intel_pstate/min_perf_pct", "10")
+            .file("/sys/devices/system/cpu/intel_pstate/max_perf_pct", "100");
+
+        let settings = CPUSettings {
+            mode: Some("active".to_string()),
+            governor: Some("performance".to_string()),
+            energy_perf_ratio: Some("balance_power".to_string()),
+            min_freq: Some(800),
+            max_freq: Some(2000),
+            min_perf_pct: Some(20),
+            max_perf_pct: Some(80),
+            boost: Some(false),
+            hwp_dyn_boost: None,
+        };
+        let (writes, commands, report) = system.plan(|e| settings.apply(e));
+
+        let cpu = "/sys/devices/system/cpu";
+        assert_eq!(
+            writes,
+            vec![
+                write(
+                    &format!("{cpu}/cpu0/cpufreq/energy_performance_preference"),
+                    "balance_power"
+                ),
+                write(
+                    &format!("{cpu}/cpu0/cpufreq/scaling_governor"),
+                    "performance"
+                ),
+                write(&format!("{cpu}/cpu0/cpufreq/scaling_max_freq"), "2000000"),
+                write(&format!("{cpu}/cpu0/cpufreq/scaling_min_freq"), "800000"),
+                write(
+                    &format!("{cpu}/cpu1/cpufreq/energy_performance_preference"),
+                    "balance_power"
+                ),
+                write(
+                    &format!("{cpu}/cpu1/cpufreq/scaling_governor"),
+                    "performance"
+                ),
+                write(&format!("{cpu}/cpu1/cpufreq/scaling_max_freq"), "2000000"),
+                write(&format!("{cpu}/cpu1/cpufreq/scaling_min_freq"), "800000"),
+                write(&format!("{cpu}/intel_pstate/max_perf_pct"), "80"),
+                write(&format!("{cpu}/intel_pstate/min_perf_pct"), "20"),
+                // Intel turbo is enabled by writing 0 into no_turbo
+                write(&format!("{cpu}/intel_pstate/no_turbo"), "1"),
+                write(&format!("{cpu}/intel_pstate/status"), "active"),
+            ]
+        );
+        assert!(commands.is_empty());
+        assert_eq!(outcome(&report, "mode"), SettingOutcome::Applied);
+        assert_eq!(outcome(&report, "hwp_dyn_boost"), SettingOutcome::Skipped);
+    }
+
+    #[test]
+    fn cpu_settings_on_amd_pstate_with_epb() {
+        let system = FakeSystem::new();
+        system
+            .file("/sys/devices/system/cpu/amd_pstate/status", "passive")
+            .file("/sys/devices/system/cpu/cpufreq/boost", "0")
+            .file("/sys/devices/system/cpu/cpu0/power/energy_perf_bias", "6");
+
+        let settings = CPUSettings {
+            energy_perf_ratio: Some("balance_power".to_string()),
+            boost: Some(true),
+            min_perf_pct: Some(20),
+            hwp_dyn_boost: Some(true),
+            ..Default::default()
+        };
+        let (writes, _, report) = system.plan(|e| settings.apply(e));
+
+        assert_eq!(
+            writes,
+            vec![
+                write(
+                    "/sys/devices/system/cpu/cpu0/power/energy_perf_bias",
+                    "balance-power"
+                ),
+                write("/sys/devices/system/cpu/cpufreq/boost", "1"),
+            ]
+        );
+        assert!(matches!(
+            outcome(&report, "min_perf_pct"),
+            SettingOutcome::Unsupported(_)
+        ));
+        assert!(matches!(
+            outcome(&report, "hwp_dyn_boost"),
+            SettingOutcome::Unsupported(_)
+        ));
+    }
+
+    #[test]
+    fn cpu_settings_report_rejected_values() {
+        let system = FakeSystem::new();
+        system.cpus(2);
+
+        let settings = CPUSettings {
+            governor: Some("powersave".to_string()),
+            energy_perf_ratio: Some("power".to_string()),
+            ..Default::default()
+        };
+        let executor = FakeExecutor::new(
+            &system,
+            &["/sys/devices/system/cpu/cpu1/cpufreq/energy_performance_preference"],
+        );
+        let report = settings.apply(&executor);
+
+        assert_eq!(outcome(&report, "governor"), SettingOutcome::Applied);
+        assert!(matches!(
+            outcome(&report, "energy_perf_ratio"),
+            SettingOutcome::Failed(_)
+        ));
+        assert_eq!(
+            system.read("/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_preference"),
+            "power"
+        );
+        assert_eq!(
+            system.read("/sys/devices/system/cpu/cpu1/cpufreq/energy_performance_preference"),
+            "default"
+        );
+    }
+
+    #[test]
+    fn cpu_core_settings_bring_cores_online_before_overrides() {
+        let system = FakeSystem::new();
+        system.cpus(2);
+
+        let settings = CPUCoreSettings {
+            cores: Some(vec![CoreSetting {
+                cpu_id: 1,
+                online: Some(false),
+                max_frequency: Some(1500),
+                governor: Some("powersave".to_string()),
+                ..Default::default()
+            }]),
+        };
+        let recorder = PlanRecorder::new(system.root.clone());
+        let report = settings.apply(&recorder);
+        let operations = recorder.take();
+
+        // Every core goes online first, only then is cpu1 taken offline
+        let cpu1_online = system.root.rooted("/sys/devices/system/cpu/cpu1/online");
+        let online_writes: Vec<_> = operations
+            .iter()
+            .filter_map(|o| match o {
+                Operation::Write { path, value } if *path == cpu1_online => Some(value.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(online_writes, vec!["1", "0"]);
+        assert!(operations.contains(&Operation::Write {
+            path: system
+                .root
+                .rooted("/sys/devices/system/cpu/cpu1/cpufreq/scaling_max_freq"),
+            value: "1500000".to_string(),
+        }));
+        assert_eq!(outcome(&report, "cpu1.governor"), SettingOutcome::Applied);
+        assert_eq!(
+            outcome(&report, "cpu1.min_frequency"),
+            SettingOutcome::Skipped
+        );
+    }
+
+    #[test]
+    fn sleep_settings_depend_on_the_programs_available() {
+        let system = FakeSystem::new();
+
+        let settings = SleepSettings {
+            turn_off_screen_after: Some(5),
+            suspend_after: None,
+        };
+        let (writes, commands, report) = system.plan(|e| settings.apply(e));
+
+        assert!(writes.is_empty());
+        if command_exists("xset") {
+            assert!(commands.contains(&"xset dpms 300 300 300".to_string()));
+            assert_eq!(
+                outcome(&report, "turn_off_screen_after"),
+                SettingOutcome::Applied
+            );
+        } else {
+            assert!(commands.is_empty());
+            assert!(matches!(
+                outcome(&report, "turn_off_screen_after"),
+                SettingOutcome::Unsupported(_)
+            ));
         }
+        assert_eq!(outcome(&report, "suspend_after"), SettingOutcome::Skipped);
+    }
+
+    #[test]
+    fn screen_settings_run_nothing_when_unset() {
+        let system = FakeSystem::new();
+
+        let (writes, commands, report) = system.plan(|e| ScreenSettings::default().apply(e));
+
+        assert!(writes.is_empty());
+        assert!(commands.is_empty());
+        assert!(report
+            .settings
+            .iter()
+            .all(|s| s.outcome == SettingOutcome::Skipped));
+    }
+
+    #[test]
+    fn radio_settings_run_rfkill() {
+        let system = FakeSystem::new();
+
+        let settings = RadioSettings {
+            block_wifi: Some(false),
+            block_nfc: None,
+            block_bt: Some(true),
+        };
+        let (_, commands, report) = system.plan(|e| settings.apply(e));
+        assert_eq!(
+            commands,
+            vec!["rfkill unblock wifi", "rfkill block bluetooth"]
+        );
+        assert_eq!(outcome(&report, "block_nfc"), SettingOutcome::Skipped);
+
+        // A failing command fails the setting
+        let report = settings.apply(&FakeExecutor::new(&system, &[]));
+        assert!(matches!(
+            outcome(&report, "block_wifi"),
+            SettingOutcome::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn network_settings_reload_the_wifi_modules_once() {
+        let system = FakeSystem::new();
+        system.dir("/sys/module/iwlmvm");
+
+        let settings = NetworkSettings {
+            disable_wifi_6: Some(true),
+            power_scheme: Some(3),
+            enable_uapsd: Some(false),
+            ..Default::default()
+        };
+        let (writes, commands, report) = system.plan(|e| settings.apply(e));
+
+        assert!(writes.is_empty());
+        assert_eq!(
+            commands,
+            vec!["modprobe -r iwlmvm && modprobe -r iwlwifi && modprobe iwlmvm power_scheme=3 && modprobe iwlwifi disable_11ax=1 uapsd_disable=1 "]
+        );
+        assert_eq!(outcome(&report, "power_scheme"), SettingOutcome::Applied);
+        assert_eq!(outcome(&report, "enable_uapsd"), SettingOutcome::Applied);
+        assert_eq!(outcome(&report, "power_level"), SettingOutcome::Skipped);
+    }
+
+    #[test]
+    fn network_settings_without_a_supported_wifi_module() {
+        let system = FakeSystem::new();
+
+        let settings = NetworkSettings {
+            power_level: Some(2),
+            ..Default::default()
+        };
+        let (_, commands, report) = system.plan(|e| settings.apply(e));
+
+        assert!(commands.is_empty());
+        assert!(matches!(
+            outcome(&report, "power_level"),
+            SettingOutcome::Unsupported(_)
+        ));
+    }
+
+    #[test]
+    fn aspm_settings_write_the_policy() {
+        let system = FakeSystem::new();
+        system.file(
+            "/sys/module/pcie_aspm/parameters/policy",
+            "[default] performance powersave",
+        );
+
+        let settings = ASPMSettings {
+            mode: Some("powersave".to_string()),
+        };
+        let (writes, _, _) = system.plan(|e| settings.apply(e));
+        assert_eq!(
+            writes,
+            vec![write(
+                "/sys/module/pcie_aspm/parameters/policy",
+                "powersave"
+            )]
+        );
+    }
+
+    #[test]
+    fn pci_settings_follow_the_blacklist() {
+        let system = FakeSystem::new();
+        system
+            .file("/sys/bus/pci/devices/0000:00:02.0/power/control", "on")
+            .file("/sys/bus/pci/devices/0000:00:14.0/power/control", "on");
+
+        let settings = PCISettings {
+            enable_power_management: Some(true),
+            whiteblacklist: Some(WhiteBlackList {
+                items: vec!["0000:00:14.0".to_string()],
+                list_type: WhiteBlackListType::Blacklist,
+            }),
+        };
+        let (writes, _, report) = system.plan(|e| settings.apply(e));
+
+        assert_eq!(
+            writes,
+            vec![
+                write("/sys/bus/pci/devices/0000:00:02.0/power/control", "auto"),
+                write("/sys/bus/pci/devices/0000:00:14.0/power/control", "on"),
+            ]
+        );
+        assert_eq!(
+            outcome(&report, "enable_power_management"),
+            SettingOutcome::Applied
+        );
+    }
+
+    #[test]
+    fn usb_settings_skip_interfaces_and_excluded_devices() {
+        let system = FakeSystem::new();
+        system
+            .file("/sys/bus/usb/devices/1-1/idVendor", "046d")
+            .file("/sys/bus/usb/devices/1-1/idProduct", "c52b")
+            .file("/sys/bus/usb/devices/1-1/power/control", "on")
+            .file(
+                "/sys/bus/usb/devices/1-1/power/autosuspend_delay_ms",
+                "2000",
+            )
+            .file("/sys/bus/usb/devices/1-2/idVendor", "8087")
+            .file("/sys/bus/usb/devices/1-2/idProduct", "0026")
+            .file("/sys/bus/usb/devices/1-2/power/control", "on")
+            .file(
+                "/sys/bus/usb/devices/1-2/power/autosuspend_delay_ms",
+                "2000",
+            )
+            .file("/sys/bus/usb/devices/1-1:1.0/power/control", "on");
+
+        let settings = USBSettings {
+            enable_pm: Some(true),
+            autosuspend_delay_ms: Some(1000),
+            whiteblacklist: Some(WhiteBlackList {
+                items: vec!["046d:c52b".to_string()],
+                list_type: WhiteBlackListType::Blacklist,
+            }),
+        };
+        let (writes, _, _) = system.plan(|e| settings.apply(e));
+
+        assert_eq!(
+            writes,
+            vec![
+                write("/sys/bus/usb/devices/1-1/power/control", "on"),
+                write(
+                    "/sys/bus/usb/devices/1-2/power/autosuspend_delay_ms",
+                    "1000"
+                ),
+                write("/sys/bus/usb/devices/1-2/power/control", "auto"),
+            ]
+        );
+    }
+
+    #[test]
+    fn sata_settings_write_every_host() {
+        let system = FakeSystem::new();
+        system
+            .file(
+                "/sys/class/scsi_host/host0/link_power_management_policy",
+                "max_performance",
+            )
+            .file(
+                "/sys/class/scsi_host/host1/link_power_management_policy",
+                "max_performance",
+            );
+
+        let settings = SATASettings {
+            active_link_pm_policy: Some("med_power_with_dipm".to_string()),
+        };
+        let (writes, _, _) = system.plan(|e| settings.apply(e));
+        assert_eq!(
+            writes,
+            vec![
+                write(
+                    "/sys/class/scsi_host/host0/link_power_management_policy",
+                    "med_power_with_dipm"
+                ),
+                write(
+                    "/sys/class/scsi_host/host1/link_power_management_policy",
+                    "med_power_with_dipm"
+                ),
+            ]
+        );
+
+        // Without any host the setting can't be applied
+        let report = settings.apply(&PlanRecorder::new(FakeSystem::new().root.clone()));
+        assert!(matches!(
+            outcome(&report, "active_link_pm_policy"),
+            SettingOutcome::Unsupported(_)
+        ));
+    }
+
+    #[test]
+    fn kernel_settings_write_into_procfs() {
+        let system = FakeSystem::new();
+        system
+            .file("/proc/sys/kernel/nmi_watchdog", "1")
+            .file("/proc/sys/vm/dirty_writeback_centisecs", "500")
+            .file("/proc/sys/vm/laptop_mode", "0");
+
+        let settings = KernelSettings {
+            disable_nmi_watchdog: Some(true),
+            vm_writeback: Some(15),
+            laptop_mode: Some(5),
+        };
+        let executor = FakeExecutor::new(&system, &[]);
+        let report = settings.apply(&executor);
+
+        assert!(!report.has_failures());
+        assert_eq!(executor.written.lock().unwrap().len(), 3);
+        assert_eq!(system.read("/proc/sys/kernel/nmi_watchdog"), "0");
+        // Seconds are written as centiseconds
+        assert_eq!(
+            system.read("/proc/sys/vm/dirty_writeback_centisecs"),
+            "1500"
+        );
+        assert_eq!(system.read("/proc/sys/vm/laptop_mode"), "5");
+    }
+
+    #[test]
+    fn firmware_settings_are_unsupported_without_platform_profiles() {
+        let system = FakeSystem::new();
+        let settings = FirmwareSettings {
+            platform_profile: Some("low-power".to_string()),
+        };
+
+        let (writes, _, report) = system.plan(|e| settings.apply(e));
+        assert!(writes.is_empty());
+        assert!(matches!(
+            outcome(&report, "platform_profile"),
+            SettingOutcome::Unsupported(_)
+        ));
+
+        system.file("/sys/firmware/acpi/platform_profile", "balanced");
+        let (writes, _, _) = system.plan(|e| settings.apply(e));
+        assert_eq!(
+            writes,
+            vec![write("/sys/firmware/acpi/platform_profile", "low-power")]
+        );
+    }
+
+    #[test]
+    fn audio_settings_pick_the_loaded_module() {
+        let system = FakeSystem::new();
+        system.file("/sys/module/snd_ac97_codec/parameters/power_save", "0");
+
+        let settings = AudioSettings {
+            idle_timeout: Some(10),
+        };
+        let (writes, _, _) = system.plan(|e| settings.apply(e));
+        assert_eq!(
+            writes,
+            vec![write(
+                "/sys/module/snd_ac97_codec/parameters/power_save",
+                "10"
+            )]
+        );
+    }
+
+    #[test]
+    fn gpu_settings_lower_the_minimum_after_the_maximum() {
+        let system = FakeSystem::new();
+        system
+            .file("/sys/class/drm/card0/gt_min_freq_mhz", "300")
+            .file("/sys/class/drm/card0/gt_max_freq_mhz", "1100")
+            .file("/sys/class/drm/card0/gt_boost_freq_mhz", "1100")
+            .dir("/sys/bus/pci/drivers/i915")
+            .dir("/sys/class/drm/card0/device");
+        symlink(
+            system.root.rooted("/sys/bus/pci/drivers/i915"),
+            system.root.rooted("/sys/class/drm/card0/device/driver"),
+        )
+        .unwrap();
+
+        // A minimum above the current maximum can only be set once the
+        // maximum is raised
+        let settings = GpuSettings {
+            intel_min: Some(1200),
+            intel_max: Some(1300),
+            ..Default::default()
+        };
+        let recorder = PlanRecorder::new(system.root.clone());
+        let report = settings.apply(&recorder);
+
+        let card = system.root.rooted("/sys/class/drm/card0");
+        assert_eq!(
+            recorder.take(),
+            vec![
+                Operation::Write {
+                    path: card.join("gt_max_freq_mhz"),
+                    value: "1300".to_string()
+                },
+                Operation::Write {
+                    path: card.join("gt_min_freq_mhz"),
+                    value: "1200".to_string()
+                },
+            ]
+        );
+        assert_eq!(outcome(&report, "intel_boost"), SettingOutcome::Skipped);
+        assert!(matches!(
+            outcome(&report, "amd_dpm_perf_level"),
+            SettingOutcome::Skipped
+        ));
+    }
+
+    #[test]
+    fn plan_records_every_group_in_order() {
+        let system = FakeSystem::new();
+        system
+            .cpus(1)
+            .file("/proc/sys/vm/laptop_mode", "0")
+            .dir("/sys/bus/usb/devices");
+
+        let profile = Profile {
+            profile_name: "test".to_string(),
+            cpu_settings: CPUSettings {
+                governor: Some("powersave".to_string()),
+                ..Default::default()
+            },
+            kernel_settings: KernelSettings {
+                laptop_mode: Some(5),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let plan = profile.plan(&system.root);
+
+        let groups: Vec<_> = plan
+            .report
+            .groups
+            .iter()
+            .map(|g| g.group.as_str())
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                "sleep_settings",
+                "cpu_settings",
+                "cpu_core_settings",
+                "screen_settings",
+                "radio_settings",
+                "network_settings",
+                "aspm_settings",
+                "pci_settings",
+                "usb_settings",
+                "sata_settings",
+                "kernel_settings",
+                "firmware_settings",
+                "audio_settings",
+                "gpu_settings",
+            ]
+        );
+        let planned: Vec<_> = plan
+            .operations
+            .iter()
+            .filter(|o| o.group != "sleep_settings")
+            .map(|o| (o.group.as_str(), o.operation.clone()))
+            .collect();
+        assert_eq!(
+            planned,
+            vec![
+                (
+                    "cpu_settings",
+                    Operation::Write {
+                        path: system
+                            .root
+                            .rooted("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor"),
+                        value: "powersave".to_string()
+                    }
+                ),
+                (
+                    "cpu_core_settings",
+                    Operation::Write {
+                        path: system.root.rooted("/sys/devices/system/cpu/cpu0/online"),
+                        value: "1".to_string()
+                    }
+                ),
+                (
+                    "kernel_settings",
+                    Operation::Write {
+                        path: system.root.rooted("/proc/sys/vm/laptop_mode"),
+                        value: "5".to_string()
+                    }
+                ),
+            ]
+        );
     }
 }
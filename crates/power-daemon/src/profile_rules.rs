@@ -0,0 +1,606 @@
+use std::{
+    fmt, fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, warn};
+use nix::libc;
+use serde::{Deserialize, Serialize};
+
+use crate::{helpers::system_on_ac, sysfs::root::SystemRoot};
+
+/// Picks a profile when all of its conditions are met. Rules are evaluated in
+/// order and the first one that matches wins.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProfileRule {
+    pub profile: String,
+    pub conditions: Vec<RuleCondition>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RuleCondition {
+    PowerSource(PowerSource),
+    /// Both bounds are exclusive, e.g. `below = 20` matches 0% to 19%. Never
+    /// matches on systems without a battery.
+    BatteryPercentage {
+        above: Option<u8>,
+        below: Option<u8>,
+    },
+    /// Matches from `start` up to (not including) `end`, wrapping around
+    /// midnight if `end` comes before `start`
+    TimeWindow {
+        start: TimeOfDay,
+        end: TimeOfDay,
+    },
+    ExternalDisplayConnected(bool),
+    LidClosed(bool),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerSource {
+    AC,
+    Battery,
+}
+
+/// Time of the day in "HH:MM" format
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (hour, minute) = value
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| format!("Expected HH:MM, got {value}"))?;
+
+        let hour: u8 = hour
+            .parse()
+            .map_err(|_| format!("Invalid hour in {value}"))?;
+        let minute: u8 = minute
+            .parse()
+            .map_err(|_| format!("Invalid minute in {value}"))?;
+
+        if hour > 23 || minute > 59 {
+            return Err(format!("{value} is not a valid time of the day"));
+        }
+
+        Ok(TimeOfDay { hour, minute })
+    }
+}
+
+impl TimeOfDay {
+    /// Time of the day at `time` in a time zone `utc_offset` seconds ahead
+    /// of UTC
+    pub fn at(time: SystemTime, utc_offset: i64) -> TimeOfDay {
+        let seconds = unix_seconds(time) + utc_offset;
+        let seconds_of_day = seconds.rem_euclid(24 * 60 * 60);
+
+        TimeOfDay {
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day % 3600 / 60) as u8,
+        }
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(value: TimeOfDay) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+/// Where rules get the time from, so they can be evaluated at any time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Seconds the local time zone is ahead of UTC at `time`
+    fn utc_offset(&self, time: SystemTime) -> i64;
+
+    fn time_of_day(&self) -> TimeOfDay {
+        let now = self.now();
+        TimeOfDay::at(now, self.utc_offset(now))
+    }
+}
+
+/// The system clock in the system's time zone
+pub struct SystemClock;
+
+extern "C" {
+    fn tzset();
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn utc_offset(&self, time: SystemTime) -> i64 {
+        let seconds = unix_seconds(time) as libc::time_t;
+        // SAFETY: tzset only updates libc's time zone state, picking up
+        // changes to TZ or /etc/localtime that localtime_r doesn't check for.
+        // The pointers passed to localtime_r are valid during the call.
+        let tm = unsafe {
+            tzset();
+            let mut tm: libc::tm = std::mem::zeroed();
+            if libc::localtime_r(&seconds, &mut tm).is_null() {
+                warn!("Could not obtain the local time zone, using UTC");
+                return 0;
+            }
+            tm
+        };
+
+        tm.tm_gmtoff as i64
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(error) => -(error.duration().as_secs() as i64),
+    }
+}
+
+/// Everything rules can depend on. Obtained from the system when picking a
+/// profile, but can be built by hand to evaluate rules against any state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SystemState {
+    pub power_source: PowerSource,
+    /// None if the system has no battery
+    pub battery_percentage: Option<u8>,
+    pub time: TimeOfDay,
+    pub external_display_connected: bool,
+    pub lid_closed: bool,
+}
+
+impl SystemState {
+    pub fn obtain(root: &SystemRoot, clock: &dyn Clock) -> SystemState {
+        SystemState {
+            power_source: if system_on_ac(root) {
+                PowerSource::AC
+            } else {
+                PowerSource::Battery
+            },
+            battery_percentage: obtain_battery_percentage(root),
+            time: clock.time_of_day(),
+            external_display_connected: obtain_external_display_connected(root),
+            lid_closed: obtain_lid_closed(root),
+        }
+    }
+}
+
+impl RuleCondition {
+    pub fn matches(&self, state: &SystemState) -> bool {
+        match self {
+            RuleCondition::PowerSource(source) => *source == state.power_source,
+            RuleCondition::BatteryPercentage { above, below } => {
+                state.battery_percentage.is_some_and(|percentage| {
+                    above.is_none_or(|above| percentage > above)
+                        && below.is_none_or(|below| percentage < below)
+                })
+            }
+            RuleCondition::TimeWindow { start, end } => {
+                if start <= end {
+                    *start <= state.time && state.time < *end
+                } else {
+                    *start <= state.time || state.time < *end
+                }
+            }
+            RuleCondition::ExternalDisplayConnected(connected) => {
+                *connected == state.external_display_connected
+            }
+            RuleCondition::LidClosed(closed) => *closed == state.lid_closed,
+        }
+    }
+}
+
+impl ProfileRule {
+    pub fn matches(&self, state: &SystemState) -> bool {
+        self.conditions.iter().all(|c| c.matches(state))
+    }
+}
+
+/// Returns the first rule that matches the state and whose profile passes
+/// `profile_exists`
+pub fn pick_rule<'a>(
+    rules: &'a [ProfileRule],
+    state: &SystemState,
+    profile_exists: impl Fn(&str) -> bool,
+) -> Option<&'a ProfileRule> {
+    rules.iter().find(|rule| {
+        if !rule.matches(state) {
+            return false;
+        }
+
+        if !profile_exists(&rule.profile) {
+            warn!(
+                "Profile rule matches but profile {} does not exist, ignoring",
+                rule.profile
+            );
+            return false;
+        }
+
+        true
+    })
+}
+
+fn obtain_battery_percentage(root: &SystemRoot) -> Option<u8> {
+    let capacities: Vec<u32> = fs::read_dir(root.rooted("/sys/class/power_supply/"))
+        .ok()?
+        .flatten()
+        .filter(|entry| {
+            fs::read_to_string(entry.path().join("type")).is_ok_and(|t| t.trim() == "Battery")
+        })
+        .filter_map(|entry| fs::read_to_string(entry.path().join("capacity")).ok())
+        .filter_map(|capacity| capacity.trim().parse().ok())
+        .collect();
+
+    if capacities.is_empty() {
+        None
+    } else {
+        Some((capacities.iter().sum::<u32>() / capacities.len() as u32) as u8)
+    }
+}
+
+// Connectors are named after their card and type, e.g. card0-HDMI-A-1.
+// Internal panels are connected through eDP, LVDS or DSI.
+fn obtain_external_display_connected(root: &SystemRoot) -> bool {
+    let Ok(entries) = fs::read_dir(root.rooted("/sys/class/drm/")) else {
+        return false;
+    };
+
+    entries.flatten().any(|entry| {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some((_, connector)) = name.split_once('-') else {
+            return false;
+        };
+
+        let internal = ["eDP", "LVDS", "DSI"]
+            .iter()
+            .any(|internal| connector.starts_with(internal));
+
+        !internal
+            && fs::read_to_string(entry.path().join("status"))
+                .is_ok_and(|status| status.trim() == "connected")
+    })
+}
+
+fn obtain_lid_closed(root: &SystemRoot) -> bool {
+    let Ok(entries) = fs::read_dir(root.rooted("/proc/acpi/button/lid/")) else {
+        debug!("No ACPI lid found");
+        return false;
+    };
+
+    entries.flatten().any(|entry| {
+        fs::read_to_string(entry.path().join("state")).is_ok_and(|state| state.contains("closed"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{HashMap, VecDeque},
+        io,
+        sync::mpsc,
+        time::Duration,
+    };
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::uevent::{spawn_uevent_monitor, UEvent, UEventSource};
+
+    /// Clock stuck at a given time of a given time zone
+    struct FakeClock {
+        now: SystemTime,
+        utc_offset: i64,
+    }
+
+    impl FakeClock {
+        fn at(hour: u64, minute: u64, utc_offset: i64) -> FakeClock {
+            // 2024-03-01 00:00 UTC
+            let midnight = UNIX_EPOCH + Duration::from_secs(1_709_251_200);
+            FakeClock {
+                now: midnight + Duration::from_secs(hour * 3600 + minute * 60),
+                utc_offset,
+            }
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> SystemTime {
+            self.now
+        }
+
+        fn utc_offset(&self, _: SystemTime) -> i64 {
+            self.utc_offset
+        }
+    }
+
+    /// Changes a file of the fake system before sending each event, like
+    /// the kernel would. Waits for the previous event to be handled first so
+    /// that every event is evaluated against the state it announced.
+    struct FakeUEventSource {
+        root: SystemRoot,
+        changes: VecDeque<(&'static str, &'static str, &'static str)>,
+        handled: mpsc::Receiver<()>,
+        first: bool,
+    }
+
+    impl UEventSource for FakeUEventSource {
+        fn next_event(&mut self) -> io::Result<UEvent> {
+            if !std::mem::take(&mut self.first) {
+                self.handled
+                    .recv()
+                    .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+            }
+            let (path, content, subsystem) =
+                self.changes.pop_front().ok_or(io::ErrorKind::BrokenPipe)?;
+            write(&self.root, path, content);
+
+            Ok(UEvent {
+                action: "change".to_string(),
+                devpath: path.to_string(),
+                properties: HashMap::from([("SUBSYSTEM".to_string(), subsystem.to_string())]),
+            })
+        }
+    }
+
+    fn time(value: &str) -> TimeOfDay {
+        TimeOfDay::try_from(value.to_string()).unwrap()
+    }
+
+    fn rule(profile: &str, conditions: Vec<RuleCondition>) -> ProfileRule {
+        ProfileRule {
+            profile: profile.to_string(),
+            conditions,
+        }
+    }
+
+    fn write(root: &SystemRoot, path: &str, content: &str) {
+        let path = root.rooted(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn time_of_day_follows_the_time_zone() {
+        assert_eq!(FakeClock::at(13, 5, 0).time_of_day(), time("13:05"));
+        assert_eq!(FakeClock::at(13, 5, 2 * 3600).time_of_day(), time("15:05"));
+        // Both wrap around midnight
+        assert_eq!(FakeClock::at(23, 30, 3600).time_of_day(), time("00:30"));
+        assert_eq!(FakeClock::at(0, 15, -5 * 3600).time_of_day(), time("19:15"));
+        assert_eq!(
+            FakeClock::at(10, 0, 5 * 3600 + 1800).time_of_day(),
+            time("15:30")
+        );
+    }
+
+    #[test]
+    fn time_windows_wrap_around_midnight() {
+        let rules = [
+            rule(
+                "Night",
+                vec![RuleCondition::TimeWindow {
+                    start: time("22:00"),
+                    end: time("06:00"),
+                }],
+            ),
+            rule(
+                "Work",
+                vec![RuleCondition::TimeWindow {
+                    start: time("09:00"),
+                    end: time("17:00"),
+                }],
+            ),
+        ];
+        let dir = TempDir::new().unwrap();
+        let root = SystemRoot::new(dir.path());
+
+        let picked = |clock: FakeClock| {
+            let state = SystemState::obtain(&root, &clock);
+            pick_rule(&rules, &state, |_| true).map(|rule| rule.profile.clone())
+        };
+
+        assert_eq!(picked(FakeClock::at(23, 0, 0)), Some("Night".to_string()));
+        assert_eq!(picked(FakeClock::at(5, 59, 0)), Some("Night".to_string()));
+        assert_eq!(picked(FakeClock::at(6, 0, 0)), None);
+        assert_eq!(picked(FakeClock::at(9, 0, 0)), Some("Work".to_string()));
+        // The end is not part of the window
+        assert_eq!(picked(FakeClock::at(17, 0, 0)), None);
+        // 15:00 UTC is 23:00 in UTC+8
+        assert_eq!(
+            picked(FakeClock::at(15, 0, 8 * 3600)),
+            Some("Night".to_string())
+        );
+    }
+
+    #[test]
+    fn state_is_read_from_the_system_root() {
+        let dir = TempDir::new().unwrap();
+        let root = SystemRoot::new(dir.path());
+        write(&root, "/sys/class/power_supply/AC/type", "Mains\n");
+        write(&root, "/sys/class/power_supply/AC/online", "0\n");
+        write(&root, "/sys/class/power_supply/BAT0/type", "Battery\n");
+        write(&root, "/sys/class/power_supply/BAT0/capacity", "40\n");
+        write(&root, "/sys/class/power_supply/BAT1/type", "Battery\n");
+        write(&root, "/sys/class/power_supply/BAT1/capacity", "20\n");
+        write(&root, "/sys/class/drm/card0-eDP-1/status", "connected\n");
+        write(
+            &root,
+            "/sys/class/drm/card0-HDMI-A-1/status",
+            "disconnected\n",
+        );
+        write(
+            &root,
+            "/proc/acpi/button/lid/LID0/state",
+            "state:      open\n",
+        );
+
+        let clock = FakeClock::at(12, 0, 0);
+        assert_eq!(
+            SystemState::obtain(&root, &clock),
+            SystemState {
+                power_source: PowerSource::Battery,
+                battery_percentage: Some(30),
+                time: time("12:00"),
+                external_display_connected: false,
+                lid_closed: false,
+            }
+        );
+
+        write(&root, "/sys/class/drm/card0-HDMI-A-1/status", "connected\n");
+        write(
+            &root,
+            "/proc/acpi/button/lid/LID0/state",
+            "state:      closed\n",
+        );
+        let state = SystemState::obtain(&root, &clock);
+        assert!(state.external_display_connected);
+        assert!(state.lid_closed);
+    }
+
+    #[test]
+    fn first_matching_rule_with_an_existing_profile_wins() {
+        let rules = [
+            rule(
+                "Missing",
+                vec![RuleCondition::PowerSource(PowerSource::Battery)],
+            ),
+            rule(
+                "Saver",
+                vec![
+                    RuleCondition::PowerSource(PowerSource::Battery),
+                    RuleCondition::BatteryPercentage {
+                        above: None,
+                        below: Some(20),
+                    },
+                ],
+            ),
+            rule(
+                "Docked",
+                vec![RuleCondition::ExternalDisplayConnected(true)],
+            ),
+        ];
+        let state = SystemState {
+            power_source: PowerSource::Battery,
+            battery_percentage: Some(19),
+            time: time("12:00"),
+            external_display_connected: true,
+            lid_closed: false,
+        };
+        let picked = |state: &SystemState| {
+            pick_rule(&rules, state, |name| name != "Missing").map(|rule| rule.profile.as_str())
+        };
+
+        assert_eq!(picked(&state), Some("Saver"));
+        // Bounds are exclusive
+        let state = SystemState {
+            battery_percentage: Some(20),
+            ..state
+        };
+        assert_eq!(picked(&state), Some("Docked"));
+        // Percentage conditions never match without a battery
+        let state = SystemState {
+            battery_percentage: None,
+            external_display_connected: false,
+            ..state
+        };
+        assert_eq!(picked(&state), None);
+    }
+
+    #[test]
+    fn rules_are_evaluated_again_on_uevents() {
+        let dir = TempDir::new().unwrap();
+        let root = SystemRoot::new(dir.path());
+        write(&root, "/sys/class/power_supply/AC/type", "Mains\n");
+        write(&root, "/sys/class/power_supply/AC/online", "1\n");
+        write(&root, "/sys/class/power_supply/BAT0/type", "Battery\n");
+        write(&root, "/sys/class/power_supply/BAT0/capacity", "15\n");
+        write(
+            &root,
+            "/sys/class/drm/card0-HDMI-A-1/status",
+            "disconnected\n",
+        );
+
+        let rules = [
+            rule(
+                "Saver",
+                vec![
+                    RuleCondition::PowerSource(PowerSource::Battery),
+                    RuleCondition::BatteryPercentage {
+                        above: None,
+                        below: Some(20),
+                    },
+                ],
+            ),
+            rule(
+                "Docked",
+                vec![RuleCondition::ExternalDisplayConnected(true)],
+            ),
+        ];
+        let clock = FakeClock::at(12, 0, 0);
+        let picked = || {
+            pick_rule(&rules, &SystemState::obtain(&root, &clock), |_| true)
+                .map(|rule| rule.profile.as_str())
+        };
+        assert_eq!(picked(), None);
+
+        let (handled, handled_receiver) = mpsc::channel();
+        let source = FakeUEventSource {
+            root: root.clone(),
+            changes: VecDeque::from([
+                ("/sys/class/power_supply/AC/online", "0\n", "power_supply"),
+                ("/sys/class/drm/card0-HDMI-A-1/status", "connected\n", "drm"),
+                (
+                    "/sys/class/power_supply/BAT0/capacity",
+                    "60\n",
+                    "power_supply",
+                ),
+                ("/sys/class/power_supply/AC/online", "1\n", "power_supply"),
+            ]),
+            handled: handled_receiver,
+            first: true,
+        };
+        let mut events = spawn_uevent_monitor(source, &["power_supply", "drm"]);
+
+        let mut picks = Vec::new();
+        while let Some(event) = events.blocking_recv() {
+            picks.push((event.devpath, picked()));
+            // The source stops once it runs out of changes
+            let _ = handled.send(());
+        }
+
+        assert_eq!(
+            picks,
+            vec![
+                (
+                    "/sys/class/power_supply/AC/online".to_string(),
+                    Some("Saver")
+                ),
+                (
+                    "/sys/class/drm/card0-HDMI-A-1/status".to_string(),
+                    Some("Saver")
+                ),
+                (
+                    "/sys/class/power_supply/BAT0/capacity".to_string(),
+                    Some("Docked")
+                ),
+                (
+                    "/sys/class/power_supply/AC/online".to_string(),
+                    Some("Docked")
+                ),
+            ]
+        );
+    }
+}
@@ -1,17 +1,16 @@
-use std::{
-    fs::File,
-    io::Write,
-    path::{Path, PathBuf},
-};
+use std::path::Path;
 
 use log::{debug, trace};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    persistence::write_atomically,
     profile::{
         ASPMSettings, CPUCoreSettings, CPUSettings, KernelSettings, NetworkSettings, PCISettings,
         Profile, RadioSettings, SATASettings, ScreenSettings, USBSettings,
     },
+    profile_path,
+    schema::SCHEMA_VERSION,
     systeminfo::{CPUFreqDriver, SystemInfo},
     AmdGpuInfo, AudioModule, AudioSettings, FirmwareSettings, GpuSettings, SleepSettings,
 };
@@ -38,6 +37,28 @@ impl DefaultProfileType {
         ]
     }
 
+    pub fn get_id_of_all() -> Vec<String> {
+        use DefaultProfileType::*;
+        vec![
+            Superpowersave.get_id(),
+            Powersave.get_id(),
+            Balanced.get_id(),
+            Performance.get_id(),
+            Ultraperformance.get_id(),
+        ]
+    }
+
+    /// Id of the profile of this type generated on setup
+    pub fn get_id(&self) -> String {
+        String::from(match self {
+            DefaultProfileType::Superpowersave => "superpowersave",
+            DefaultProfileType::Powersave => "powersave",
+            DefaultProfileType::Balanced => "balanced",
+            DefaultProfileType::Performance => "performance",
+            DefaultProfileType::Ultraperformance => "ultraperformance",
+        })
+    }
+
     pub fn get_name(&self) -> String {
         String::from(match self {
             DefaultProfileType::Superpowersave => "Powersave++",
@@ -67,57 +88,48 @@ pub fn create_profile_file<P: AsRef<Path>>(
 ) {
     debug!("Creating profile of type {profile_type:?}");
 
-    let name = profile_type.get_name();
+    let id = profile_type.get_id();
+    let profile = create_default(&id, &profile_type.get_name(), profile_type, system_info);
 
-    create_profile_file_with_name(name, directory_path, profile_type, system_info);
-}
-
-pub fn create_empty_profile_file_with_name<P: AsRef<Path>>(directory_path: P, name: &str) {
-    debug!("Generating empty profile");
-
-    let profile = create_empty(name);
-
-    let path = PathBuf::from(directory_path.as_ref()).join(format!("{name}.toml"));
-
-    let mut file = File::create(path).expect("Could not create profile file");
+    let path = profile_path(directory_path.as_ref(), &id);
 
     let content = toml::to_string_pretty(&profile).unwrap();
 
     trace!("{content}");
 
-    file.write_all(content.as_bytes())
-        .expect("Could not write to profile file");
+    write_atomically(&path, content).expect("Could not write to profile file");
 }
 
-pub fn create_profile_file_with_name<P: AsRef<Path>>(
-    name: String,
+pub fn create_empty_profile_file_with_name<P: AsRef<Path>>(
     directory_path: P,
-    profile_type: DefaultProfileType,
-    system_info: &SystemInfo,
+    id: &str,
+    name: &str,
 ) {
-    let mut profile = create_default(&name, profile_type, system_info);
-    profile.profile_name = name.clone();
+    debug!("Generating empty profile");
 
-    let path = PathBuf::from(directory_path.as_ref()).join(format!("{name}.toml"));
+    let profile = create_empty(id, name);
 
-    let mut file = File::create(path).expect("Could not create profile file");
+    let path = profile_path(directory_path.as_ref(), id);
 
     let content = toml::to_string_pretty(&profile).unwrap();
 
     trace!("{content}");
 
-    file.write_all(content.as_bytes())
-        .expect("Could not write to profile file");
+    write_atomically(&path, content).expect("Could not write to profile file");
 }
 
 pub fn create_default(
+    id: &str,
     name: &str,
     profile_type: DefaultProfileType,
     system_info: &SystemInfo,
 ) -> Profile {
     Profile {
+        schema_version: SCHEMA_VERSION,
+        id: String::from(id),
         profile_name: String::from(name),
         base_profile: profile_type.into(),
+        extends: None,
 
         sleep_settings: sleep_settings_default(&profile_type),
         cpu_settings: cpu_settings_default(&profile_type, system_info),
@@ -136,8 +148,10 @@ pub fn create_default(
     }
 }
 
-pub fn create_empty(name: &str) -> Profile {
+pub fn create_empty(id: &str, name: &str) -> Profile {
     Profile {
+        schema_version: SCHEMA_VERSION,
+        id: String::from(id),
         profile_name: String::from(name),
         base_profile: None,
 
@@ -0,0 +1,269 @@
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::persistence::write_atomically;
+
+/// Version of the profile and config files this build writes. Bump it and
+/// add a migration to `PROFILE_MIGRATIONS` or `CONFIG_MIGRATIONS` whenever a
+/// change to `Profile` or `Config` would make older files fail to parse.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Brings a file from schema version `from` to `from + 1`
+pub struct Migration {
+    pub from: u32,
+    pub description: &'static str,
+    /// Receives the file and what a freshly generated file would hold
+    pub migrate: fn(&mut toml::Table, &toml::Table),
+}
+
+/// In order, one per version
+pub const PROFILE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        // Settings a profile leaves out are meant to stay unset, and the id
+        // is added by the next migration
+        description: "add the schema version",
+        migrate: |_, _| {},
+    },
+    Migration {
+        from: 1,
+        description: "store the id of the profile, the name it was stored under so far",
+        migrate: take_id_from_defaults,
+    },
+];
+
+/// In order, one per version
+pub const CONFIG_MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        // Options added since have defaults of their own
+        description: "add the schema version",
+        migrate: |_, _| {},
+    },
+    Migration {
+        from: 1,
+        // Profiles were stored under their name, which therefore is their id
+        description: "the list of profiles holds ids instead of names",
+        migrate: |_, _| {},
+    },
+];
+
+/// Why a profile or config file could not be loaded. The file is left as it
+/// is, but a backup is made in case it's overwritten later on.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoadError {
+    pub path: PathBuf,
+    pub reason: String,
+    /// Copy of the file at the time it was loaded
+    pub backup: Option<PathBuf>,
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "could not load {}: {}", self.path.display(), self.reason)?;
+        if let Some(ref backup) = self.backup {
+            write!(f, " (backed up to {})", backup.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Reads a versioned file, migrating it to `SCHEMA_VERSION` if it's older.
+/// Migrated files are backed up and written back. `defaults` receives the
+/// file as it was read and is only called when a migration runs.
+pub fn load<T: Serialize + DeserializeOwned>(
+    path: &Path,
+    migrations: &[Migration],
+    defaults: impl FnOnce(&toml::Table) -> T,
+) -> Result<T, LoadError> {
+    let fail = |reason: String| LoadError {
+        path: path.to_path_buf(),
+        reason,
+        backup: backup(path).ok(),
+    };
+
+    let content = fs::read_to_string(path).map_err(|error| LoadError {
+        path: path.to_path_buf(),
+        reason: error.to_string(),
+        backup: None,
+    })?;
+    let mut table: toml::Table =
+        toml::from_str(&content).map_err(|error| fail(error.to_string().trim().into()))?;
+
+    let version = match table.get("schema_version") {
+        None => 0,
+        Some(toml::Value::Integer(version)) => u32::try_from(*version)
+            .map_err(|_| fail(format!("invalid schema version {version}")))?,
+        Some(_) => return Err(fail("schema_version is not a number".into())),
+    };
+    if version > SCHEMA_VERSION {
+        return Err(fail(format!(
+            "schema version {version} is newer than this build supports ({SCHEMA_VERSION})"
+        )));
+    }
+
+    if version == SCHEMA_VERSION {
+        // Parsing the text rather than the table points at the offending line
+        return toml::from_str(&content).map_err(|error| fail(error.to_string().trim().into()));
+    }
+
+    let defaults =
+        match toml::Value::try_from(defaults(&table)).expect("Could not serialize defaults") {
+            toml::Value::Table(defaults) => defaults,
+            _ => unreachable!("Settings files serialize to a table"),
+        };
+    for migration in migrations.iter().filter(|m| m.from >= version) {
+        info!(
+            "Migrating {} from schema version {} to {}: {}",
+            path.display(),
+            migration.from,
+            migration.from + 1,
+            migration.description
+        );
+        (migration.migrate)(&mut table, &defaults);
+    }
+    table.insert(
+        "schema_version".to_string(),
+        toml::Value::Integer(SCHEMA_VERSION.into()),
+    );
+
+    let value: T = toml::Value::Table(table)
+        .try_into()
+        .map_err(|error| fail(error.message().into()))?;
+
+    let backup = backup(path).map_err(|error| LoadError {
+        path: path.to_path_buf(),
+        reason: format!("migrated, but could not back up the original: {error}"),
+        backup: None,
+    })?;
+    info!("Backed up {} to {}", path.display(), backup.display());
+    // The migrated value is still usable, the file is migrated again the
+    // next time it's loaded
+    if let Err(error) = write_atomically(path, toml::to_string_pretty(&value).unwrap()) {
+        error!("Could not write migrated {}: {error}", path.display());
+    }
+
+    Ok(value)
+}
+
+/// Copies the file next to itself, suffixed with the current time. A file
+/// that was already backed up as it is isn't copied again.
+pub fn backup(path: &Path) -> io::Result<PathBuf> {
+    let content = fs::read(path)?;
+    if let Some(existing) = existing_backups(path).find(|b| fs::read(b).is_ok_and(|c| c == content))
+    {
+        return Ok(existing);
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{timestamp}.bak"));
+    let backup = PathBuf::from(backup);
+
+    fs::write(&backup, content)?;
+    Ok(backup)
+}
+
+fn existing_backups(path: &Path) -> impl Iterator<Item = PathBuf> {
+    let prefix = path
+        .file_name()
+        .map(|name| format!("{}.", name.to_string_lossy()))
+        .unwrap_or_default();
+    path.parent()
+        .and_then(|parent| fs::read_dir(parent).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(move |backup| {
+            backup.file_name().is_some_and(|name| {
+                let name = name.to_string_lossy();
+                name.starts_with(&prefix) && name.ends_with(".bak")
+            })
+        })
+}
+
+/// The defaults of a profile carry the id it's listed under in the config
+fn take_id_from_defaults(table: &mut toml::Table, defaults: &toml::Table) {
+    if let Some(id) = defaults.get("id") {
+        table.insert("id".to_string(), id.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{profiles_generator::create_empty, Config, Profile};
+
+    #[test]
+    fn migrating_keeps_unset_settings_unset() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("quiet.toml");
+        let mut file = toml::Table::try_from(create_empty("quiet", "Quiet")).unwrap();
+        file.remove("schema_version");
+        file.remove("id");
+        fs::write(&path, toml::to_string(&file).unwrap()).unwrap();
+
+        let profile: Profile = load(&path, PROFILE_MIGRATIONS, |_| {
+            let mut defaults = create_empty("quiet", "Quiet");
+            defaults.cpu_settings.governor = Some("powersave".to_string());
+            defaults
+        })
+        .unwrap();
+
+        assert_eq!(profile.schema_version, SCHEMA_VERSION);
+        assert_eq!(profile.id, "quiet");
+        assert_eq!(profile.cpu_settings.governor, None);
+        let written: Profile = toml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, profile);
+        assert_eq!(existing_backups(&path).count(), 1);
+    }
+
+    /// Validating and importing files parses them without migrating
+    #[test]
+    fn unversioned_files_parse_as_version_zero() {
+        let mut file = toml::Table::try_from(create_empty("quiet", "Quiet")).unwrap();
+        file.remove("schema_version");
+
+        let profile: Profile = toml::from_str(&toml::to_string(&file).unwrap()).unwrap();
+
+        assert_eq!(profile.schema_version, 0);
+    }
+
+    #[test]
+    fn configs_from_before_schema_versions_load() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+ac_profile = "Performance"
+bat_profile = "Powersave"
+profiles = ["Powersave", "Balanced", "Performance"]
+"#,
+        )
+        .unwrap();
+
+        let config: Config = load(&path, CONFIG_MIGRATIONS, |_| Config::create_default()).unwrap();
+
+        assert_eq!(config.schema_version, SCHEMA_VERSION);
+        assert_eq!(config.profiles, ["Powersave", "Balanced", "Performance"]);
+        assert!(config.profile_rules.is_empty());
+        assert!(!config.unset_restores_baseline);
+        assert!(!config.power_profiles_compat.enabled);
+    }
+}
@@ -3,9 +3,13 @@ use std::{
     path::PathBuf,
 };
 
-use crate::helpers::{run_command, run_command_with_output};
+use crate::{executor::Executor, helpers::run_command_with_output};
 
-use super::reading::{file_content_to_string, file_content_to_u32};
+use super::{
+    reading::{file_content_to_string, file_content_to_u32},
+    root::SystemRoot,
+    writing::WriteError,
+};
 
 pub struct IntelGpu {
     pub min_frequency: u32,
@@ -25,23 +29,14 @@ impl IntelGpu {
         }
     }
 
-    pub fn set_min(&self, min: u32) {
-        run_command(&format!(
-            "echo {min} > {}",
-            self.path.join("gt_min_freq_mhz").display()
-        ));
+    pub fn set_min(&self, executor: &dyn Executor, min: u32) -> Result<(), WriteError> {
+        executor.write_value(self.path.join("gt_min_freq_mhz"), min)
     }
-    pub fn set_max(&self, max: u32) {
-        run_command(&format!(
-            "echo {max} > {}",
-            self.path.join("gt_max_freq_mhz").display()
-        ));
+    pub fn set_max(&self, executor: &dyn Executor, max: u32) -> Result<(), WriteError> {
+        executor.write_value(self.path.join("gt_max_freq_mhz"), max)
     }
-    pub fn set_boost(&self, boost: u32) {
-        run_command(&format!(
-            "echo {boost} > {}",
-            self.path.join("gt_boost_freq_mhz").display()
-        ));
+    pub fn set_boost(&self, executor: &dyn Executor, boost: u32) -> Result<(), WriteError> {
+        executor.write_value(self.path.join("gt_boost_freq_mhz"), boost)
     }
 }
 
@@ -100,70 +95,63 @@ impl AmdGpu {
         }
     }
 
-    pub fn set_dpm_perf_level(&self, perf_level: &str) {
+    pub fn set_dpm_perf_level(
+        &self,
+        executor: &dyn Executor,
+        perf_level: &str,
+    ) -> Result<(), WriteError> {
         match &self.driver {
-            AmdGpuDriver::AmdGpu { dpm_perf: _ } => {
-                run_command(&format!(
-                    "echo {perf_level} > {}",
-                    self.path
-                        .join("device/power_dpm_force_performance_level")
-                        .display()
-                ));
-            }
+            AmdGpuDriver::AmdGpu { dpm_perf: _ } => executor.write_value(
+                self.path.join("device/power_dpm_force_performance_level"),
+                perf_level,
+            ),
             AmdGpuDriver::Radeon {
                 dpm_perf: _,
                 dpm_state: _,
-            } => {
-                run_command(&format!(
-                    "echo {perf_level} > {}",
-                    self.path
-                        .join("device/power_dpm_force_performance_level")
-                        .display()
-                ));
-            }
-            AmdGpuDriver::Legacy { power_profile: _ } => {}
+            } => executor.write_value(
+                self.path.join("device/power_dpm_force_performance_level"),
+                perf_level,
+            ),
+            AmdGpuDriver::Legacy { power_profile: _ } => Ok(()),
         }
     }
 
-    pub fn set_dpm_power_state(&self, power_state: &str) {
+    pub fn set_dpm_power_state(
+        &self,
+        executor: &dyn Executor,
+        power_state: &str,
+    ) -> Result<(), WriteError> {
         match &self.driver {
-            AmdGpuDriver::AmdGpu { dpm_perf: _ } => {}
+            AmdGpuDriver::AmdGpu { dpm_perf: _ } => Ok(()),
             AmdGpuDriver::Radeon {
                 dpm_perf: _,
                 dpm_state: _,
-            } => {
-                run_command(&format!(
-                    "echo {power_state} > {}",
-                    self.path.join("device/power_dpm_state").display()
-                ));
-            }
-            AmdGpuDriver::Legacy { power_profile: _ } => {}
+            } => executor.write_value(self.path.join("device/power_dpm_state"), power_state),
+            AmdGpuDriver::Legacy { power_profile: _ } => Ok(()),
         }
     }
 
-    pub fn set_power_profile(&self, power_profile: &str) {
+    pub fn set_power_profile(
+        &self,
+        executor: &dyn Executor,
+        power_profile: &str,
+    ) -> Result<(), WriteError> {
         match &self.driver {
-            AmdGpuDriver::AmdGpu { dpm_perf: _ } => {}
+            AmdGpuDriver::AmdGpu { dpm_perf: _ } => Ok(()),
             AmdGpuDriver::Radeon {
                 dpm_perf: _,
                 dpm_state: _,
-            } => {}
+            } => Ok(()),
             AmdGpuDriver::Legacy { power_profile: _ } => {
-                run_command(&format!(
-                    "echo profile > {}",
-                    self.path.join("device/power_method").display()
-                ));
-                run_command(&format!(
-                    "echo {power_profile} > {}",
-                    self.path.join("power_profile").display()
-                ));
+                executor.write_value(self.path.join("device/power_method"), "profile")?;
+                executor.write_value(self.path.join("power_profile"), power_profile)
             }
         }
     }
 }
 
-pub fn iterate_intel_gpus() -> impl IntoIterator<Item = IntelGpu> {
-    fs::read_dir("/sys/class/drm")
+pub fn iterate_intel_gpus(root: &SystemRoot) -> impl IntoIterator<Item = IntelGpu> {
+    fs::read_dir(root.rooted("/sys/class/drm"))
         .expect("Could not read sysfs drm directory")
         .flatten()
         .filter(|entry| {
@@ -181,8 +169,8 @@ pub fn iterate_intel_gpus() -> impl IntoIterator<Item = IntelGpu> {
         .map(IntelGpu::from_dir)
 }
 
-pub fn iterate_amd_gpus() -> impl IntoIterator<Item = AmdGpu> {
-    fs::read_dir("/sys/class/drm")
+pub fn iterate_amd_gpus(root: &SystemRoot) -> impl IntoIterator<Item = AmdGpu> {
+    fs::read_dir(root.rooted("/sys/class/drm"))
         .expect("Could not read sysfs drm directory")
         .flatten()
         .filter(|entry| {
@@ -1,2 +1,4 @@
 pub mod gpu;
 pub mod reading;
+pub mod root;
+pub mod writing;
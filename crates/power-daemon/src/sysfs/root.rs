@@ -0,0 +1,30 @@
+use std::path::{Path, PathBuf};
+
+/// The directory that all sysfs and procfs paths are resolved against.
+/// Defaults to `/`, pointing it somewhere else allows running the daemon
+/// against a directory tree of fake hardware files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemRoot(PathBuf);
+
+impl SystemRoot {
+    pub fn new<P: AsRef<Path>>(root: P) -> SystemRoot {
+        SystemRoot(PathBuf::from(root.as_ref()))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Resolves an absolute system path such as `/sys/class/drm` against
+    /// this root
+    pub fn rooted<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let path = path.as_ref();
+        self.0.join(path.strip_prefix("/").unwrap_or(path))
+    }
+}
+
+impl Default for SystemRoot {
+    fn default() -> Self {
+        SystemRoot::new("/")
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    fmt::{self, Display},
+    fs::OpenOptions,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use log::trace;
+
+#[derive(Debug)]
+pub enum WriteError {
+    /// The attribute does not exist, usually because the hardware or driver
+    /// does not support it
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    /// The kernel refused the value (EINVAL), e.g. setting an EPP while the
+    /// performance governor is active
+    Rejected {
+        path: PathBuf,
+        value: String,
+    },
+    /// The device is busy (EBUSY) and the value might be accepted later
+    Busy(PathBuf),
+    Other {
+        path: PathBuf,
+        error: io::Error,
+    },
+}
+
+impl WriteError {
+    fn from_io(error: io::Error, path: &Path, value: &str) -> WriteError {
+        let path = PathBuf::from(path);
+        match error.kind() {
+            io::ErrorKind::NotFound => WriteError::NotFound(path),
+            io::ErrorKind::PermissionDenied => WriteError::PermissionDenied(path),
+            io::ErrorKind::InvalidInput => WriteError::Rejected {
+                path,
+                value: value.to_string(),
+            },
+            io::ErrorKind::ResourceBusy => WriteError::Busy(path),
+            _ => WriteError::Other { path, error },
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            WriteError::NotFound(path)
+            | WriteError::PermissionDenied(path)
+            | WriteError::Rejected { path, value: _ }
+            | WriteError::Busy(path)
+            | WriteError::Other { path, error: _ } => path,
+        }
+    }
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            WriteError::PermissionDenied(path) => {
+                write!(f, "permission denied writing to {}", path.display())
+            }
+            WriteError::Rejected { path, value } => {
+                write!(f, "{} rejected value {value:?}", path.display())
+            }
+            WriteError::Busy(path) => write!(f, "{} is busy", path.display()),
+            WriteError::Other { path, error } => {
+                write!(f, "could not write to {}: {error}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+/// Writes `value` into a sysfs or procfs attribute directly, without
+/// spawning a shell. The attribute is never created if it doesn't exist.
+pub fn write_value<P: AsRef<Path>>(path: P, value: impl Display) -> Result<(), WriteError> {
+    let path = path.as_ref();
+    let value = value.to_string();
+
+    trace!("Writing {value} to {}", path.display());
+
+    OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(value.as_bytes()))
+        .map_err(|error| WriteError::from_io(error, path, &value))
+}
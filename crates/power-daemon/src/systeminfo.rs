@@ -1,17 +1,18 @@
-use std::{collections::HashMap, fs, io};
+use std::{collections::HashMap, fs, io, path::Path};
 
 use log::{error, trace};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    helpers::{command_exists, run_command_with_output},
+    helpers::command_exists,
     sysfs::gpu::IntelGpu,
     sysfs::{
         gpu::*,
         reading::{
             file_content_to_bool, file_content_to_list, file_content_to_string, file_content_to_u32,
         },
+        root::SystemRoot,
     },
 };
 
@@ -27,17 +28,17 @@ pub struct SystemInfo {
 }
 
 impl SystemInfo {
-    pub fn obtain() -> SystemInfo {
+    pub fn obtain(root: &SystemRoot) -> SystemInfo {
         trace!("Obtaining system info");
 
         SystemInfo {
-            cpu_info: CPUInfo::obtain(),
-            pci_info: PCIInfo::obtain(),
-            usb_info: USBInfo::obtain(),
-            sata_info: SATAInfo::obtain(),
-            firmware_info: FirmwareInfo::obtain(),
-            gpu_info: GpuInfo::obtain(),
-            opt_features_info: OptionalFeaturesInfo::obtain(),
+            cpu_info: CPUInfo::obtain(root),
+            pci_info: PCIInfo::obtain(root),
+            usb_info: USBInfo::obtain(root),
+            sata_info: SATAInfo::obtain(root),
+            firmware_info: FirmwareInfo::obtain(root),
+            gpu_info: GpuInfo::obtain(root),
+            opt_features_info: OptionalFeaturesInfo::obtain(root),
         }
     }
 }
@@ -57,6 +58,12 @@ pub struct CPUInfo {
     pub has_epp: bool,
     pub has_epb: bool,
 
+    /// Governors the scaling driver offers in its current mode, empty if
+    /// cpufreq is unavailable
+    pub available_governors: Vec<String>,
+    /// Empty if the system does not have EPP
+    pub available_epps: Vec<String>,
+
     pub has_perf_pct_scaling: bool,
 
     pub hybrid: bool,
@@ -97,10 +104,10 @@ pub struct CoreInfo {
 }
 
 impl CPUInfo {
-    pub fn obtain() -> CPUInfo {
-        let driver = if fs::metadata("/sys/devices/system/cpu/intel_pstate").is_ok() {
+    pub fn obtain(root: &SystemRoot) -> CPUInfo {
+        let driver = if fs::metadata(root.rooted("/sys/devices/system/cpu/intel_pstate")).is_ok() {
             CPUFreqDriver::Intel
-        } else if fs::metadata("/sys/devices/system/cpu/amd_pstate").is_ok() {
+        } else if fs::metadata(root.rooted("/sys/devices/system/cpu/amd_pstate")).is_ok() {
             CPUFreqDriver::Amd
         } else {
             CPUFreqDriver::Other
@@ -120,21 +127,35 @@ impl CPUInfo {
             mode: if driver == CPUFreqDriver::Other {
                 None
             } else {
-                Some(file_content_to_string(format!(
+                Some(file_content_to_string(root.rooted(format!(
                     "/sys/devices/system/cpu/{}/status",
                     well_known_driver_name
-                )))
+                ))))
             },
 
-            has_epp: fs::metadata(
+            has_epp: fs::metadata(root.rooted(
                 "/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_available_preferences",
+            ))
+            .is_ok(),
+            has_epb: fs::metadata(
+                root.rooted("/sys/devices/system/cpu/cpu0/power/energy_perf_bias"),
             )
             .is_ok(),
-            has_epb: fs::metadata("/sys/devices/system/cpu/cpu0/power/energy_perf_bias").is_ok(),
+
+            available_governors: read_list_if_exists(
+                root,
+                "/sys/devices/system/cpu/cpu0/cpufreq/scaling_available_governors",
+            ),
+            available_epps: read_list_if_exists(
+                root,
+                "/sys/devices/system/cpu/cpu0/cpufreq/energy_performance_available_preferences",
+            ),
 
             // This feature is exclusive to intel
-            has_perf_pct_scaling: fs::metadata("/sys/devices/system/cpu/intel_pstate/min_perf_pct")
-                .is_ok(),
+            has_perf_pct_scaling: fs::metadata(
+                root.rooted("/sys/devices/system/cpu/intel_pstate/min_perf_pct"),
+            )
+            .is_ok(),
 
             hybrid: false,
             cores: Vec::default(),
@@ -144,23 +165,23 @@ impl CPUInfo {
 
             boost: match driver {
                 CPUFreqDriver::Intel => Some(!file_content_to_bool(
-                    "/sys/devices/system/cpu/intel_pstate/no_turbo",
+                    root.rooted("/sys/devices/system/cpu/intel_pstate/no_turbo"),
                 )),
                 CPUFreqDriver::Amd => Some(file_content_to_bool(
-                    "/sys/devices/system/cpu/cpufreq/boost",
+                    root.rooted("/sys/devices/system/cpu/cpufreq/boost"),
                 )),
                 CPUFreqDriver::Other => None,
             },
             hwp_dynamic_boost: if let CPUFreqDriver::Intel = driver {
-                Some(file_content_to_bool(
+                Some(file_content_to_bool(root.rooted(
                     "/sys/devices/system/cpu/intel_pstate/hwp_dynamic_boost",
-                ))
+                )))
             } else {
                 None
             },
         };
 
-        ret.obtain_core_info();
+        ret.obtain_core_info(root);
 
         ret.total_max_frequency = ret
             .cores
@@ -206,7 +227,7 @@ impl CPUInfo {
         }
     }
 
-    fn obtain_core_info(&mut self) {
+    fn obtain_core_info(&mut self, root: &SystemRoot) {
         let mut base_frequency_variations: HashMap<u32, Vec<usize>> = HashMap::new();
 
         let cpu_pattern = Regex::new(r"cpu\d+").unwrap();
@@ -215,7 +236,7 @@ impl CPUInfo {
 
         let mut count = 0;
 
-        let mut entries: Vec<_> = fs::read_dir("/sys/devices/system/cpu/")
+        let mut entries: Vec<_> = fs::read_dir(root.rooted("/sys/devices/system/cpu/"))
             .expect("Could not read sysfs directory")
             .filter_map(Result::ok)
             .collect();
@@ -383,8 +404,8 @@ pub struct ASPMInfo {
 }
 
 impl PCIInfo {
-    pub fn obtain() -> PCIInfo {
-        let mut entries: Vec<_> = fs::read_dir("/sys/bus/pci/devices")
+    pub fn obtain(root: &SystemRoot) -> PCIInfo {
+        let mut entries: Vec<_> = fs::read_dir(root.rooted("/sys/bus/pci/devices"))
             .expect("Could not read sysfs directory")
             .filter_map(Result::ok)
             .collect();
@@ -393,14 +414,16 @@ impl PCIInfo {
             natord::compare(a.path().to_str().unwrap(), b.path().to_str().unwrap())
         });
 
+        let ids = PCIIds::load(root);
         let mut pci_devices = Vec::new();
 
         for device in entries {
-            let display_name = run_command_with_output(&format!(
-                "lspci -s \"{}\" | sed -E 's/^[0-9a-f]+:[0-9a-f]+.[0-9] //; s/ \\(rev.*\\)//'",
-                device.file_name().into_string().unwrap()
-            ))
-            .0;
+            let path = device.path();
+            let display_name = ids.describe(
+                &read_hex_attribute(&path.join("class")),
+                &read_hex_attribute(&path.join("vendor")),
+                &read_hex_attribute(&path.join("device")),
+            );
 
             let pci_address = device.file_name().into_string().unwrap();
 
@@ -410,20 +433,116 @@ impl PCIInfo {
             })
         }
         PCIInfo {
-            aspm_info: ASPMInfo::obtain(),
+            aspm_info: ASPMInfo::obtain(root),
             pci_devices,
         }
     }
 }
 
+/// Where distributions put the PCI ID database, which lspci reads too
+const PCI_IDS_PATHS: [&str; 3] = [
+    "/usr/share/hwdata/pci.ids",
+    "/usr/share/misc/pci.ids",
+    "/usr/share/pci.ids",
+];
+
+/// Names of PCI vendors, devices and classes, keyed by their ids in
+/// lowercase hex without the `0x` prefix
+#[derive(Default)]
+struct PCIIds {
+    vendors: HashMap<String, String>,
+    devices: HashMap<(String, String), String>,
+    /// Both classes and subclasses, the latter keyed by the class id
+    /// followed by the subclass id
+    classes: HashMap<String, String>,
+}
+
+impl PCIIds {
+    /// Empty if the database isn't installed, devices are then described by
+    /// their ids
+    fn load(root: &SystemRoot) -> PCIIds {
+        let mut ids = PCIIds::default();
+        let Some(content) = PCI_IDS_PATHS
+            .iter()
+            .find_map(|path| fs::read_to_string(root.rooted(path)).ok())
+        else {
+            return ids;
+        };
+
+        // Devices are listed under their vendor and subclasses under their
+        // class, indented by a tab. Subsystems and programming interfaces are
+        // indented twice and skipped.
+        let mut vendor: Option<String> = None;
+        let mut class: Option<String> = None;
+        for line in content.lines() {
+            if line.starts_with('#') || line.starts_with("\t\t") {
+                continue;
+            }
+            if let Some(entry) = line.strip_prefix("C ") {
+                if let Some((id, name)) = split_pci_ids_entry(entry) {
+                    ids.classes.insert(id.clone(), name);
+                    class = Some(id);
+                    vendor = None;
+                }
+            } else if let Some(entry) = line.strip_prefix('\t') {
+                let Some((id, name)) = split_pci_ids_entry(entry) else {
+                    continue;
+                };
+                if let Some(ref class) = class {
+                    ids.classes.insert(format!("{class}{id}"), name);
+                } else if let Some(ref vendor) = vendor {
+                    ids.devices.insert((vendor.clone(), id), name);
+                }
+            } else if let Some((id, name)) = split_pci_ids_entry(line) {
+                ids.vendors.insert(id.clone(), name);
+                vendor = Some(id);
+                class = None;
+            }
+        }
+        ids
+    }
+
+    /// Same as lspci, e.g. `Network controller: Intel Corporation Wi-Fi 6
+    /// AX201`. `class` is the class code from sysfs, which is followed by the
+    /// subclass and the programming interface.
+    fn describe(&self, class: &str, vendor: &str, device: &str) -> String {
+        let class_name = class
+            .get(..4)
+            .and_then(|subclass| self.classes.get(subclass))
+            .or_else(|| class.get(..2).and_then(|class| self.classes.get(class)))
+            .cloned()
+            .unwrap_or_else(|| format!("Class {}", class.get(..4).unwrap_or(class)));
+
+        let device_name = match self.vendors.get(vendor) {
+            Some(vendor_name) => {
+                match self.devices.get(&(vendor.to_string(), device.to_string())) {
+                    Some(device_name) => format!("{vendor_name} {device_name}"),
+                    None => format!("{vendor_name} Device {device}"),
+                }
+            }
+            None => format!("Device {vendor}:{device}"),
+        };
+
+        format!("{class_name}: {device_name}")
+    }
+}
+
+/// Lines are an id and a name separated by two spaces
+fn split_pci_ids_entry(line: &str) -> Option<(String, String)> {
+    let (id, name) = line.split_once("  ")?;
+    Some((id.to_lowercase(), name.to_string()))
+}
+
 impl ASPMInfo {
-    pub fn obtain() -> ASPMInfo {
+    pub fn obtain(root: &SystemRoot) -> ASPMInfo {
         ASPMInfo {
-            supported_modes: if fs::metadata("/sys/module/pcie_aspm/parameters/policy").is_err() {
+            supported_modes: if fs::metadata(root.rooted("/sys/module/pcie_aspm/parameters/policy"))
+                .is_err()
+            {
                 None
             } else {
                 Some(
-                    file_content_to_list("/sys/module/pcie_aspm/parameters/policy")
+                    file_content_to_list(root.rooted("/sys/module/pcie_aspm/parameters/policy"))
                         .into_iter()
                         .map(|s| {
                             // The current enabled mode is written [mode_name] when reading the sysfs entry
@@ -453,19 +572,40 @@ pub struct USBDeviceInfo {
 }
 
 impl USBInfo {
-    pub fn obtain() -> USBInfo {
+    pub fn obtain(root: &SystemRoot) -> USBInfo {
+        // Systems without USB lack the directory entirely
+        let mut entries: Vec<_> = fs::read_dir(root.rooted("/sys/bus/usb/devices"))
+            .map(|entries| entries.filter_map(Result::ok).collect())
+            .unwrap_or_default();
+
+        entries.sort_by(|a, b| {
+            natord::compare(a.path().to_str().unwrap(), b.path().to_str().unwrap())
+        });
+
         let mut usb_devices = Vec::new();
-        let lsusb = run_command_with_output("lsusb").0;
+        for entry in entries {
+            let path = entry.path();
+            // Interfaces of the devices are listed too, but have no ids
+            let (Some(vendor), Some(product)) = (
+                read_attribute(&path.join("idVendor")),
+                read_attribute(&path.join("idProduct")),
+            ) else {
+                continue;
+            };
 
-        let re = Regex::new(r"ID (\w+:\w+) (.+)").unwrap();
-        for line in lsusb.lines() {
-            let captures = re.captures(line).unwrap();
-            let name = &captures[2];
-            let id = &captures[1];
+            let names: Vec<_> = ["manufacturer", "product"]
+                .into_iter()
+                .filter_map(|name| read_attribute(&path.join(name)))
+                .collect();
+            let display_name = if names.is_empty() {
+                String::from("Unknown device")
+            } else {
+                names.join(" ")
+            };
 
             usb_devices.push(USBDeviceInfo {
-                display_name: name.to_string(),
-                id: id.to_string(),
+                display_name,
+                id: format!("{vendor}:{product}"),
             });
         }
 
@@ -479,8 +619,8 @@ pub struct SATAInfo {
 }
 
 impl SATAInfo {
-    pub fn obtain() -> SATAInfo {
-        let raw = fs::read_dir("/sys/class/scsi_host/");
+    pub fn obtain(root: &SystemRoot) -> SATAInfo {
+        let raw = fs::read_dir(root.rooted("/sys/class/scsi_host/"));
         match raw {
             Ok(itr) => SATAInfo {
                 hosts: itr.filter_map(Result::ok).count() as u32,
@@ -500,11 +640,13 @@ pub struct FirmwareInfo {
 }
 
 impl FirmwareInfo {
-    pub fn obtain() -> FirmwareInfo {
-        let supports_acpi_profiles = fs::metadata("/sys/firmware/acpi/platform_profile").is_ok();
+    pub fn obtain(root: &SystemRoot) -> FirmwareInfo {
+        let supports_acpi_profiles =
+            fs::metadata(root.rooted("/sys/firmware/acpi/platform_profile")).is_ok();
         FirmwareInfo {
             platform_profiles: if supports_acpi_profiles {
-                file_content_to_list("/sys/firmware/acpi/platform_profile_choices").into()
+                file_content_to_list(root.rooted("/sys/firmware/acpi/platform_profile_choices"))
+                    .into()
             } else {
                 None
             },
@@ -559,16 +701,16 @@ impl AmdGpuInfo {
 }
 
 impl GpuInfo {
-    pub fn obtain() -> GpuInfo {
+    pub fn obtain(root: &SystemRoot) -> GpuInfo {
         GpuInfo {
             // 98% of users will have a single intel GPU, complicating the UI
             // for the 0% is a bit suboptimal at least for now
-            intel_info: iterate_intel_gpus()
+            intel_info: iterate_intel_gpus(root)
                 .into_iter()
                 .next()
                 .map(IntelGpuInfo::from_gpu_entry),
 
-            amd_info: iterate_amd_gpus()
+            amd_info: iterate_amd_gpus(root)
                 .into_iter()
                 .next()
                 .map(AmdGpuInfo::from_gpu_entry),
@@ -597,19 +739,19 @@ pub enum AudioModule {
 }
 
 impl OptionalFeaturesInfo {
-    pub fn obtain() -> OptionalFeaturesInfo {
+    pub fn obtain(root: &SystemRoot) -> OptionalFeaturesInfo {
         OptionalFeaturesInfo {
             supports_xautolock: command_exists("xautolock"),
             supports_xset: command_exists("xset"),
             supports_xrandr: command_exists("xrandr"),
             supports_brightnessctl: command_exists("brightnessctl"),
-            supports_wifi_drivers: fs::metadata("/sys/module/iwlwifi").is_ok()
-                && (fs::metadata("/sys/module/iwlmvm").is_ok()
-                    || fs::metadata("/sys/module/iwldvm").is_ok()),
+            supports_wifi_drivers: fs::metadata(root.rooted("/sys/module/iwlwifi")).is_ok()
+                && (fs::metadata(root.rooted("/sys/module/iwlmvm")).is_ok()
+                    || fs::metadata(root.rooted("/sys/module/iwldvm")).is_ok()),
             supports_ifconfig: command_exists("ifconfig"),
-            audio_module: if fs::metadata("/sys/module/snd_hda_intel/").is_ok() {
+            audio_module: if fs::metadata(root.rooted("/sys/module/snd_hda_intel/")).is_ok() {
                 AudioModule::SndHdaIntel
-            } else if fs::metadata("/sys/module/snd_ac97_codec/").is_ok() {
+            } else if fs::metadata(root.rooted("/sys/module/snd_ac97_codec/")).is_ok() {
                 AudioModule::SndAc9Codec
             } else {
                 AudioModule::Other
@@ -617,3 +759,137 @@ impl OptionalFeaturesInfo {
         }
     }
 }
+
+/// Trimmed content of a sysfs attribute, None if it's missing or empty
+fn read_attribute(path: &Path) -> Option<String> {
+    let content = fs::read_to_string(path).ok()?;
+    let content = content.trim();
+    (!content.is_empty()).then(|| content.to_string())
+}
+
+/// Ids in sysfs are written in hex with a `0x` prefix
+fn read_hex_attribute(path: &Path) -> String {
+    let content = read_attribute(path).unwrap_or_default();
+    content
+        .strip_prefix("0x")
+        .unwrap_or(&content)
+        .to_lowercase()
+}
+
+fn read_list_if_exists(root: &SystemRoot, path: &str) -> Vec<String> {
+    let path = root.rooted(path);
+    if fs::metadata(&path).is_ok() {
+        file_content_to_list(path)
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn write(root: &SystemRoot, path: &str, content: &str) {
+        let path = root.rooted(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn usb_devices_are_read_from_sysfs() {
+        let dir = TempDir::new().unwrap();
+        let root = SystemRoot::new(dir.path());
+        let devices = "/sys/bus/usb/devices";
+        write(&root, &format!("{devices}/1-2/idVendor"), "046d\n");
+        write(&root, &format!("{devices}/1-2/idProduct"), "c52b\n");
+        write(&root, &format!("{devices}/1-2/manufacturer"), "Logitech\n");
+        write(&root, &format!("{devices}/1-2/product"), "USB Receiver\n");
+        write(&root, &format!("{devices}/1-2:1.0/bInterfaceClass"), "03\n");
+        write(&root, &format!("{devices}/1-10/idVendor"), "8087\n");
+        write(&root, &format!("{devices}/1-10/idProduct"), "0026\n");
+
+        assert_eq!(
+            USBInfo::obtain(&root).usb_devices,
+            vec![
+                USBDeviceInfo {
+                    display_name: "Logitech USB Receiver".to_string(),
+                    id: "046d:c52b".to_string(),
+                },
+                USBDeviceInfo {
+                    display_name: "Unknown device".to_string(),
+                    id: "8087:0026".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn usb_devices_are_empty_without_usb() {
+        let dir = TempDir::new().unwrap();
+
+        assert!(USBInfo::obtain(&SystemRoot::new(dir.path()))
+            .usb_devices
+            .is_empty());
+    }
+
+    #[test]
+    fn pci_devices_are_named_from_the_id_database() {
+        let dir = TempDir::new().unwrap();
+        let root = SystemRoot::new(dir.path());
+        write(
+            &root,
+            "/usr/share/hwdata/pci.ids",
+            "# comment\n\
+             8086  Intel Corporation\n\
+             \t0a0c  Haswell-ULT HD Audio Controller\n\
+             \t\t17aa 2214  ThinkPad X240\n\
+             \ta0f0  Wi-Fi 6 AX201\n\
+             C 02  Network controller\n\
+             \t80  Network controller\n\
+             C 04  Multimedia controller\n\
+             \t03  Audio device\n\
+             \t\t00  Legacy\n",
+        );
+        let devices = "/sys/bus/pci/devices";
+        for (address, class, vendor, device) in [
+            ("0000:00:03.0", "0x040300", "0x8086", "0x0a0c"),
+            ("0000:00:14.3", "0x028000", "0x8086", "0xa0f0"),
+            ("0000:00:1f.0", "0x060100", "0x8086", "0x9d4e"),
+            ("0000:01:00.0", "0x010802", "0x144d", "0xa808"),
+        ] {
+            write(&root, &format!("{devices}/{address}/class"), class);
+            write(&root, &format!("{devices}/{address}/vendor"), vendor);
+            write(&root, &format!("{devices}/{address}/device"), device);
+        }
+
+        let names: Vec<_> = PCIInfo::obtain(&root)
+            .pci_devices
+            .into_iter()
+            .map(|device| (device.pci_address, device.display_name))
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                (
+                    "0000:00:03.0".to_string(),
+                    "Audio device: Intel Corporation Haswell-ULT HD Audio Controller".to_string()
+                ),
+                (
+                    "0000:00:1f.0".to_string(),
+                    "Class 0601: Intel Corporation Device 9d4e".to_string()
+                ),
+                (
+                    "0000:00:14.3".to_string(),
+                    "Network controller: Intel Corporation Wi-Fi 6 AX201".to_string()
+                ),
+                (
+                    "0000:01:00.0".to_string(),
+                    "Class 0108: Device 144d:a808".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, OwnedFd},
+    thread,
+};
+
+use log::{debug, error, trace};
+use nix::sys::socket::{
+    bind, recv, socket, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType,
+};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// A kernel uevent, as broadcasted through the kobject-uevent netlink socket
+#[derive(Debug, Clone, PartialEq)]
+pub struct UEvent {
+    pub action: String,
+    pub devpath: String,
+    pub properties: HashMap<String, String>,
+}
+
+impl UEvent {
+    /// Parses a raw message of the form `action@devpath\0KEY=VALUE\0...`.
+    /// Returns None for anything else, like messages rebroadcasted by udev.
+    pub fn parse(message: &[u8]) -> Option<UEvent> {
+        let message = String::from_utf8_lossy(message);
+        let mut fields = message.split('\0').filter(|f| !f.is_empty());
+
+        let (action, devpath) = fields.next()?.split_once('@')?;
+
+        let properties = fields
+            .filter_map(|f| f.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        Some(UEvent {
+            action: action.to_string(),
+            devpath: devpath.to_string(),
+            properties,
+        })
+    }
+
+    pub fn subsystem(&self) -> Option<&str> {
+        self.properties.get("SUBSYSTEM").map(String::as_str)
+    }
+}
+
+/// Anything uevents can be received from. Allows feeding the daemon with fake
+/// events instead of the kernel's.
+pub trait UEventSource: Send {
+    /// Blocks until the next event is available
+    fn next_event(&mut self) -> io::Result<UEvent>;
+}
+
+/// Receives uevents straight from the kernel
+pub struct NetlinkUEventSource {
+    fd: OwnedFd,
+    buffer: Vec<u8>,
+}
+
+impl NetlinkUEventSource {
+    pub fn new() -> io::Result<NetlinkUEventSource> {
+        let fd = socket(
+            AddressFamily::Netlink,
+            SockType::Datagram,
+            SockFlag::SOCK_CLOEXEC,
+            SockProtocol::NetlinkKObjectUEvent,
+        )?;
+
+        // Multicast group 1 is the one the kernel broadcasts uevents to
+        bind(fd.as_raw_fd(), &NetlinkAddr::new(0, 1))?;
+
+        Ok(NetlinkUEventSource {
+            fd,
+            buffer: vec![0; 8192],
+        })
+    }
+}
+
+impl UEventSource for NetlinkUEventSource {
+    fn next_event(&mut self) -> io::Result<UEvent> {
+        loop {
+            let len = recv(self.fd.as_raw_fd(), &mut self.buffer, MsgFlags::empty())?;
+            if let Some(event) = UEvent::parse(&self.buffer[..len]) {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+/// Runs the source on its own thread, every event of one of the given
+/// subsystems is sent through the returned channel. The channel closes if the
+/// source fails.
+pub fn spawn_uevent_monitor<S: UEventSource + 'static>(
+    mut source: S,
+    subsystems: &'static [&'static str],
+) -> UnboundedReceiver<UEvent> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    thread::spawn(move || loop {
+        match source.next_event() {
+            Ok(event) => {
+                if !event.subsystem().is_some_and(|s| subsystems.contains(&s)) {
+                    continue;
+                }
+
+                trace!("Received uevent: {event:?}");
+                if sender.send(event).is_err() {
+                    debug!("Uevent receiver dropped, stopping monitor");
+                    return;
+                }
+            }
+            Err(error) => {
+                error!("Could not receive uevents, stopping monitor: {error}");
+                return;
+            }
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Replays raw messages, then fails like a closed socket would
+    struct FakeUEventSource {
+        messages: VecDeque<&'static [u8]>,
+    }
+
+    impl UEventSource for FakeUEventSource {
+        fn next_event(&mut self) -> io::Result<UEvent> {
+            while let Some(message) = self.messages.pop_front() {
+                if let Some(event) = UEvent::parse(message) {
+                    return Ok(event);
+                }
+            }
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+    }
+
+    #[test]
+    fn parses_kernel_messages() {
+        let event = UEvent::parse(
+            b"change@/devices/LNXSYSTM:00/ACPI0003:00/power_supply/AC\0ACTION=change\0SUBSYSTEM=power_supply\0POWER_SUPPLY_ONLINE=0\0",
+        )
+        .unwrap();
+
+        assert_eq!(event.action, "change");
+        assert_eq!(
+            event.devpath,
+            "/devices/LNXSYSTM:00/ACPI0003:00/power_supply/AC"
+        );
+        assert_eq!(event.subsystem(), Some("power_supply"));
+        assert_eq!(
+            event.properties.get("POWER_SUPPLY_ONLINE"),
+            Some(&"0".to_string())
+        );
+    }
+
+    #[test]
+    fn ignores_messages_rebroadcasted_by_udev() {
+        assert_eq!(UEvent::parse(b"libudev\0\xfe\xed\xca\xfe"), None);
+        assert_eq!(UEvent::parse(b""), None);
+    }
+
+    #[test]
+    fn monitor_forwards_only_the_given_subsystems() {
+        let source = FakeUEventSource {
+            messages: VecDeque::from([
+                &b"add@/devices/usb1/1-1\0SUBSYSTEM=usb\0"[..],
+                &b"change@/devices/power_supply/AC\0SUBSYSTEM=power_supply\0"[..],
+                &b"libudev\0garbage"[..],
+                &b"change@/devices/card0/card0-HDMI-A-1\0SUBSYSTEM=drm\0"[..],
+                &b"change@/devices/power_supply/BAT0\0SUBSYSTEM=power_supply\0"[..],
+            ]),
+        };
+        let mut events = spawn_uevent_monitor(source, &["power_supply"]);
+
+        let mut devpaths = Vec::new();
+        // The channel closes once the source fails
+        while let Some(event) = events.blocking_recv() {
+            devpaths.push(event.devpath);
+        }
+        assert_eq!(
+            devpaths,
+            vec!["/devices/power_supply/AC", "/devices/power_supply/BAT0"]
+        );
+    }
+}
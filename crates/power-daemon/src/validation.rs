@@ -0,0 +1,343 @@
+use std::{
+    fmt::{self, Display},
+    ops::RangeInclusive,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AmdGpuInfo, CPUFreqDriver, CPUInfo, Profile, SystemInfo, WhiteBlackList};
+
+/// Governors the kernel ships, offered by every scaling driver except
+/// intel_pstate and amd_pstate in active mode
+const GENERIC_GOVERNORS: [&str; 6] = [
+    "conservative",
+    "ondemand",
+    "userspace",
+    "powersave",
+    "performance",
+    "schedutil",
+];
+const ACTIVE_MODE_GOVERNORS: [&str; 2] = ["performance", "powersave"];
+/// What EPP values are translated from when the system only has EPB
+const EPP_NAMES: [&str; 5] = [
+    "performance",
+    "balance_performance",
+    "default",
+    "balance_power",
+    "power",
+];
+const SATA_POLICIES: [&str; 4] = [
+    "max_performance",
+    "medium_power",
+    "med_power_with_dipm",
+    "min_power",
+];
+const AMDGPU_PERF_LEVELS: [&str; 8] = [
+    "auto",
+    "low",
+    "high",
+    "manual",
+    "profile_standard",
+    "profile_min_sclk",
+    "profile_min_mclk",
+    "profile_peak",
+];
+const RADEON_PERF_LEVELS: [&str; 3] = ["auto", "low", "high"];
+const RADEON_POWER_STATES: [&str; 3] = ["battery", "balanced", "performance"];
+const RADEON_POWER_PROFILES: [&str; 5] = ["default", "auto", "low", "mid", "high"];
+
+/// A value of a profile that can't be used on this system
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// Name of the settings group, e.g. `cpu_settings`
+    pub group: String,
+    /// Per-core fields are prefixed with the core id, e.g. `cpu3.governor`
+    pub field: String,
+    pub value: String,
+    pub reason: String,
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{} = {:?}: {}",
+            self.group, self.field, self.value, self.reason
+        )
+    }
+}
+
+impl Profile {
+    /// Checks every value the profile sets against what this system offers.
+    /// Settings for hardware or drivers the system lacks entirely are only
+    /// checked for their format, applying them reports them as unsupported.
+    pub fn validate(&self, system_info: &SystemInfo) -> Vec<ValidationIssue> {
+        let mut validator = Validator::default();
+        let cpu_info = &system_info.cpu_info;
+
+        validator.group = "cpu_settings";
+        let cpu = &self.cpu_settings;
+        match cpu_info.driver {
+            CPUFreqDriver::Intel => validator.one_of("mode", &cpu.mode, &["active", "passive"]),
+            CPUFreqDriver::Amd => {
+                validator.one_of("mode", &cpu.mode, &["active", "passive", "guided"])
+            }
+            CPUFreqDriver::Other => validator.identifier("mode", &cpu.mode),
+        }
+        let governors = available_governors(cpu_info, cpu.mode.as_deref());
+        validator.one_of("governor", &cpu.governor, &governors);
+        validator.one_of("energy_perf_ratio", &cpu.energy_perf_ratio, &epps(cpu_info));
+        let frequencies = cpu_info.total_min_frequency..=cpu_info.total_max_frequency;
+        if cpu_info.total_max_frequency != 0 {
+            validator.in_range("min_freq", cpu.min_freq, &frequencies);
+            validator.in_range("max_freq", cpu.max_freq, &frequencies);
+        }
+        validator.ordered(("min_freq", cpu.min_freq), ("max_freq", cpu.max_freq));
+        validator.in_range("min_perf_pct", cpu.min_perf_pct, &(0..=100));
+        validator.in_range("max_perf_pct", cpu.max_perf_pct, &(0..=100));
+        validator.ordered(
+            ("min_perf_pct", cpu.min_perf_pct),
+            ("max_perf_pct", cpu.max_perf_pct),
+        );
+
+        validator.group = "cpu_core_settings";
+        for core in self.cpu_core_settings.cores.iter().flatten() {
+            let field = |name: &str| format!("cpu{}.{name}", core.cpu_id);
+            let Some(core_info) = cpu_info
+                .cores
+                .iter()
+                .find(|c| c.logical_cpu_id == core.cpu_id)
+            else {
+                validator.fail(
+                    &format!("cpu{}", core.cpu_id),
+                    core.cpu_id,
+                    "the system has no CPU with this id",
+                );
+                continue;
+            };
+
+            validator.one_of(&field("governor"), &core.governor, &governors);
+            validator.one_of(&field("epp"), &core.epp, &epps(cpu_info));
+            // Offline cores don't report their frequencies
+            if core_info.total_max_frequency != 0 {
+                let frequencies = core_info.total_min_frequency..=core_info.total_max_frequency;
+                validator.in_range(&field("min_frequency"), core.min_frequency, &frequencies);
+                validator.in_range(&field("max_frequency"), core.max_frequency, &frequencies);
+            }
+            validator.ordered(
+                (&field("min_frequency"), core.min_frequency),
+                (&field("max_frequency"), core.max_frequency),
+            );
+        }
+
+        validator.group = "screen_settings";
+        let screen = &self.screen_settings;
+        validator.matches("resolution", &screen.resolution, "WIDTHxHEIGHT", |value| {
+            value
+                .split_once('x')
+                .is_some_and(|(width, height)| is_number(width) && is_number(height))
+        });
+        validator.matches("refresh_rate", &screen.refresh_rate, "a number", |value| {
+            let mut parts = value.splitn(2, '.');
+            parts.all(is_number)
+        });
+        validator.in_range("brightness", screen.brightness, &(0..=100));
+
+        validator.group = "network_settings";
+        validator.in_range("power_level", self.network_settings.power_level, &(0..=5));
+        validator.in_range("power_scheme", self.network_settings.power_scheme, &(1..=3));
+
+        validator.group = "aspm_settings";
+        match system_info.pci_info.aspm_info.supported_modes {
+            Some(ref modes) => validator.one_of("mode", &self.aspm_settings.mode, modes),
+            None => validator.identifier("mode", &self.aspm_settings.mode),
+        }
+
+        validator.group = "pci_settings";
+        validator.list_items(&self.pci_settings.whiteblacklist, "a PCI address", |item| {
+            item.len() == 12
+                && item.chars().enumerate().all(|(idx, c)| match idx {
+                    4 | 7 => c == ':',
+                    10 => c == '.',
+                    _ => c.is_ascii_hexdigit(),
+                })
+        });
+
+        validator.group = "usb_settings";
+        validator.list_items(
+            &self.usb_settings.whiteblacklist,
+            "VENDOR:PRODUCT ids",
+            |item| {
+                item.split_once(':').is_some_and(|(vendor, product)| {
+                    [vendor, product]
+                        .iter()
+                        .all(|id| id.len() == 4 && id.chars().all(|c| c.is_ascii_hexdigit()))
+                })
+            },
+        );
+
+        validator.group = "sata_settings";
+        validator.one_of(
+            "active_link_pm_policy",
+            &self.sata_settings.active_link_pm_policy,
+            &SATA_POLICIES,
+        );
+
+        validator.group = "firmware_settings";
+        match system_info.firmware_info.platform_profiles {
+            Some(ref profiles) => validator.one_of(
+                "platform_profile",
+                &self.firmware_settings.platform_profile,
+                profiles,
+            ),
+            None => {
+                validator.identifier("platform_profile", &self.firmware_settings.platform_profile)
+            }
+        }
+
+        validator.group = "gpu_settings";
+        let gpu = &self.gpu_settings;
+        validator.ordered(("intel_min", gpu.intel_min), ("intel_max", gpu.intel_max));
+        let perf_levels: &[&str] = match system_info.gpu_info.amd_info {
+            Some(AmdGpuInfo::AmdGpu { .. }) => &AMDGPU_PERF_LEVELS,
+            _ => &RADEON_PERF_LEVELS,
+        };
+        validator.one_of("amd_dpm_perf_level", &gpu.amd_dpm_perf_level, perf_levels);
+        validator.one_of(
+            "amd_dpm_power_state",
+            &gpu.amd_dpm_power_state,
+            &RADEON_POWER_STATES,
+        );
+        validator.one_of(
+            "amd_power_profile",
+            &gpu.amd_power_profile,
+            &RADEON_POWER_PROFILES,
+        );
+
+        validator.issues
+    }
+}
+
+/// Governors available once the profile's scaling driver mode is in effect
+fn available_governors(cpu_info: &CPUInfo, mode: Option<&str>) -> Vec<String> {
+    let switches_mode = mode.is_some_and(|mode| Some(mode) != cpu_info.mode.as_deref());
+    if switches_mode || cpu_info.available_governors.is_empty() {
+        let governors: &[&str] = if mode == Some("active") {
+            &ACTIVE_MODE_GOVERNORS
+        } else {
+            &GENERIC_GOVERNORS
+        };
+        governors.iter().map(|g| g.to_string()).collect()
+    } else {
+        cpu_info.available_governors.clone()
+    }
+}
+
+fn epps(cpu_info: &CPUInfo) -> Vec<String> {
+    if cpu_info.available_epps.is_empty() {
+        EPP_NAMES.iter().map(|e| e.to_string()).collect()
+    } else {
+        cpu_info.available_epps.clone()
+    }
+}
+
+fn is_number(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+#[derive(Default)]
+struct Validator {
+    group: &'static str,
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator {
+    fn fail(&mut self, field: &str, value: impl Display, reason: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            group: self.group.to_string(),
+            field: field.to_string(),
+            value: value.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    fn one_of(&mut self, field: &str, value: &Option<String>, choices: &[impl AsRef<str>]) {
+        if let Some(value) = value {
+            if !choices.iter().any(|c| c.as_ref() == value) {
+                let choices: Vec<_> = choices.iter().map(AsRef::as_ref).collect();
+                self.fail(
+                    field,
+                    value,
+                    format!("expected one of {}", choices.join(", ")),
+                );
+            }
+        }
+    }
+
+    fn in_range<T: PartialOrd + Display>(
+        &mut self,
+        field: &str,
+        value: Option<T>,
+        range: &RangeInclusive<T>,
+    ) {
+        if let Some(value) = value {
+            if !range.contains(&value) {
+                self.fail(
+                    field,
+                    &value,
+                    format!("expected {} to {}", range.start(), range.end()),
+                );
+            }
+        }
+    }
+
+    /// Checks that the lower bound of a pair of settings isn't above the upper
+    fn ordered<T: PartialOrd + Display>(
+        &mut self,
+        (min_field, min): (&str, Option<T>),
+        (max_field, max): (&str, Option<T>),
+    ) {
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                self.fail(min_field, min, format!("above {max_field}, which is {max}"));
+            }
+        }
+    }
+
+    fn matches(
+        &mut self,
+        field: &str,
+        value: &Option<String>,
+        expected: &str,
+        is_valid: impl Fn(&str) -> bool,
+    ) {
+        if let Some(value) = value {
+            if !is_valid(value) {
+                self.fail(field, value, format!("expected {expected}"));
+            }
+        }
+    }
+
+    /// For values the system can't list the choices of
+    fn identifier(&mut self, field: &str, value: &Option<String>) {
+        self.matches(field, value, "letters, digits, - and _", |value| {
+            !value.is_empty()
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+    }
+
+    fn list_items(
+        &mut self,
+        list: &Option<WhiteBlackList>,
+        expected: &str,
+        is_valid: impl Fn(&str) -> bool,
+    ) {
+        for item in list.iter().flat_map(|l| &l.items) {
+            if !is_valid(item) {
+                self.fail("whiteblacklist.items", item, format!("expected {expected}"));
+            }
+        }
+    }
+}
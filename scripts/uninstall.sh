@@ -8,6 +8,16 @@ check_root() {
     fi
 }
 
+# Function to write back the values the system had before power-options
+# changed them. Stopping the daemon already does this, this takes care of the
+# case where it wasn't running.
+restore_baseline() {
+    if [ -f "/usr/bin/power-daemon-mgr" ]; then
+        echo "Restoring original system settings..."
+        /usr/bin/power-daemon-mgr restore-baseline
+    fi
+}
+
 # Function to remove files and directories
 remove_files_and_dirs() {
     # Remove directory
@@ -16,6 +26,11 @@ remove_files_and_dirs() {
         rm -rf /etc/power-options
     fi
 
+    if [ -d "/var/lib/power-options" ]; then
+        echo "Removing /var/lib/power-options directory..."
+        rm -rf /var/lib/power-options
+    fi
+
     # Remove application files
     echo "Removing application files..."
     rm -f /usr/share/applications/power-options-*
@@ -43,6 +58,7 @@ remove_files_and_dirs() {
     rm -f /etc/acpi/events/power-options
     rm -f /usr/lib/udev/rules.d/85-power-daemon.rules
     rm -f /usr/share/dbus-1/system.d/power-daemon.conf
+    rm -f /usr/share/polkit-1/actions/io.github.thealexdev23.power_daemon.policy
 }
 
 # Function to disable and remove systemd service
@@ -64,6 +80,9 @@ remove_systemd_service() {
 
 # Main execution
 check_root
-remove_files_and_dirs
+# The service has to be stopped first so the daemon restores the baseline
+# while its files are still around
 remove_systemd_service
+restore_baseline
+remove_files_and_dirs
 echo "Cleanup completed successfully."
\ No newline at end of file
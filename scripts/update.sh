@@ -1,3 +1,2 @@
-sudo systemctl restart acpid
 sudo systemctl daemon-reload
 sudo systemctl restart power-options
\ No newline at end of file